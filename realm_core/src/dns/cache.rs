@@ -0,0 +1,146 @@
+//! Cached resolution.
+
+use std::io::{Result, Error, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::resolve_ip;
+use crate::endpoint::RemoteAddr;
+
+/// Resolved address of a remote peer, refreshed once expired.
+///
+/// A domain name is re-resolved when its dns record expires,
+/// or when `interval` elapses if it is shorter than the ttl.
+///
+/// Interval = 0 means only follow the ttl.
+pub struct CachedAddr {
+    raddr: RemoteAddr,
+    interval: Duration,
+    cache: Mutex<Option<(SocketAddr, Instant)>>,
+}
+
+impl CachedAddr {
+    pub fn new(raddr: RemoteAddr, interval: usize) -> Self {
+        Self {
+            raddr,
+            interval: Duration::from_secs(interval as u64),
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// Get the unresolved remote address.
+    #[inline]
+    pub const fn remote(&self) -> &RemoteAddr {
+        &self.raddr
+    }
+
+    /// Get cached address, or resolve again if expired.
+    pub async fn resolve(&self) -> Result<SocketAddr> {
+        let (host, port) = match &self.raddr {
+            RemoteAddr::SocketAddr(addr) => return Ok(*addr),
+            RemoteAddr::DomainName(host, port) => (host, *port),
+            RemoteAddr::OriginalDst => {
+                return Err(Error::new(ErrorKind::InvalidInput, "unknown original destination"));
+            }
+            RemoteAddr::Unix(_) => {
+                return Err(Error::new(ErrorKind::InvalidInput, "not an inet address"));
+            }
+        };
+
+        let now = Instant::now();
+
+        if let Some(addr) = self.cached(now) {
+            return Ok(addr);
+        }
+
+        let ip = resolve_ip(host).await?;
+        let addr = match ip.iter().next() {
+            Some(ip) => SocketAddr::new(ip, port),
+            None => return Err(Error::new(ErrorKind::NotFound, "no record found")),
+        };
+
+        let expire = self.expire_at(ip.valid_until(), now);
+        match self.store(addr, expire) {
+            Some(last) => log::info!("[dns]{} changed: {} => {}", self.raddr, last, addr),
+            None => log::debug!("[dns]{} resolved as {}", self.raddr, addr),
+        }
+
+        Ok(addr)
+    }
+
+    /// Get cached address if not expired.
+    fn cached(&self, now: Instant) -> Option<SocketAddr> {
+        match *self.cache.lock().unwrap() {
+            Some((addr, expire)) if now < expire => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// The ttl, or the interval if it is shorter.
+    fn expire_at(&self, valid_until: Instant, now: Instant) -> Instant {
+        match self.interval.is_zero() {
+            true => valid_until,
+            false => valid_until.min(now + self.interval),
+        }
+    }
+
+    /// Cache a new address, returns the last one if it has changed.
+    fn store(&self, addr: SocketAddr, expire: Instant) -> Option<SocketAddr> {
+        let last = self.cache.lock().unwrap().replace((addr, expire));
+        last.map(|(x, _)| x).filter(|x| *x != addr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn domain(interval: usize) -> CachedAddr {
+        CachedAddr::new(RemoteAddr::DomainName("example.com".to_string(), 80), interval)
+    }
+
+    #[test]
+    fn expire_at() {
+        let now = Instant::now();
+        let ttl = now + Duration::from_secs(300);
+
+        // follow the ttl
+        assert_eq!(domain(0).expire_at(ttl, now), ttl);
+        assert_eq!(domain(600).expire_at(ttl, now), ttl);
+        // interval is shorter
+        assert_eq!(domain(60).expire_at(ttl, now), now + Duration::from_secs(60));
+    }
+
+    #[test]
+    fn cached() {
+        let cache = domain(0);
+        let now = Instant::now();
+        let addr1: SocketAddr = "192.0.2.1:80".parse().unwrap();
+        let addr2: SocketAddr = "192.0.2.2:80".parse().unwrap();
+        assert_eq!(cache.cached(now), None);
+
+        let expire = now + Duration::from_secs(60);
+        assert_eq!(cache.store(addr1, expire), None);
+        assert_eq!(cache.cached(now), Some(addr1));
+        assert_eq!(cache.cached(expire - Duration::from_millis(1)), Some(addr1));
+        assert_eq!(cache.cached(expire), None);
+
+        // the same address again
+        let expire = now + Duration::from_secs(120);
+        assert_eq!(cache.store(addr1, expire), None);
+        assert_eq!(cache.cached(now + Duration::from_secs(60)), Some(addr1));
+
+        // changed
+        assert_eq!(cache.store(addr2, expire), Some(addr1));
+        assert_eq!(cache.cached(now), Some(addr2));
+    }
+
+    #[tokio::test]
+    async fn no_lookup() {
+        let addr: SocketAddr = "192.0.2.1:80".parse().unwrap();
+        let cache = CachedAddr::new(RemoteAddr::SocketAddr(addr), 1);
+        assert_eq!(cache.resolve().await.unwrap(), addr);
+        assert!(CachedAddr::new(RemoteAddr::OriginalDst, 0).resolve().await.is_err());
+    }
+}
@@ -2,7 +2,7 @@
 
 //! Global dns resolver.
 
-use std::io::{Result, Error};
+use std::io::{Result, Error, ErrorKind};
 use std::net::SocketAddr;
 
 use hickory_resolver as resolver;
@@ -24,6 +24,9 @@ pub mod config {
     pub use resolver::config::*;
 }
 
+mod cache;
+pub use cache::CachedAddr;
+
 /// Dns config.
 #[derive(Debug, Clone)]
 pub struct DnsConf {
@@ -101,6 +104,8 @@ pub async fn resolve_addr(addr: &RemoteAddr) -> Result<LookupRemoteAddr<'_>> {
     match addr {
         SocketAddr(addr) => Ok(NoLookup(addr)),
         DomainName(ip, port) => resolve_ip(ip).await.map(|ip| Dolookup(ip, *port)),
+        OriginalDst => Err(Error::new(ErrorKind::InvalidInput, "unknown original destination")),
+        Unix(_) => Err(Error::new(ErrorKind::InvalidInput, "not an inet address")),
     }
 }
 
@@ -1,7 +1,16 @@
 //! Relay endpoint.
 
 use std::fmt::{Display, Formatter};
+use std::io::{Error, ErrorKind};
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use ipnet::IpNet;
+use realm_io::Shutdown;
+
+#[cfg(feature = "proxy")]
+use std::net::IpAddr;
 
 #[cfg(feature = "transport")]
 use kaminari::mix::{MixAccept, MixConnect};
@@ -9,21 +18,111 @@ use kaminari::mix::{MixAccept, MixConnect};
 #[cfg(feature = "balance")]
 use realm_lb::Balancer;
 
+/// Unix socket address, written as `unix:/path` or `unix:@name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnixAddr {
+    /// A path on the filesystem.
+    Path(PathBuf),
+    /// A name in the abstract namespace, only available on Linux.
+    Abstract(String),
+}
+
+/// Local address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocalAddr {
+    SocketAddr(SocketAddr),
+    Unix(UnixAddr),
+}
+
 /// Remote address.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RemoteAddr {
     SocketAddr(SocketAddr),
     DomainName(String, u16),
+    /// Original destination of a redirected connection.
+    OriginalDst,
+    Unix(UnixAddr),
+}
+
+impl UnixAddr {
+    const PREFIX: &'static str = "unix:";
+
+    /// Path understood by tokio, where an abstract name starts with a null byte.
+    #[cfg(unix)]
+    pub fn to_path(&self) -> PathBuf {
+        match self {
+            UnixAddr::Path(path) => path.clone(),
+            UnixAddr::Abstract(name) => PathBuf::from(format!("\0{}", name)),
+        }
+    }
+
+    /// Address understood by std, which does not accept a null byte in a path.
+    #[cfg(unix)]
+    pub fn to_socket_addr(&self) -> std::io::Result<std::os::unix::net::SocketAddr> {
+        match self {
+            UnixAddr::Path(path) => std::os::unix::net::SocketAddr::from_pathname(path),
+            #[cfg(target_os = "linux")]
+            UnixAddr::Abstract(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                std::os::unix::net::SocketAddr::from_abstract_name(name)
+            }
+            #[cfg(not(target_os = "linux"))]
+            UnixAddr::Abstract(_) => Err(Error::new(ErrorKind::Unsupported, "abstract unix socket")),
+        }
+    }
+}
+
+impl FromStr for UnixAddr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let addr = s
+            .strip_prefix(Self::PREFIX)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "missing unix: prefix"))?;
+        match addr.strip_prefix('@') {
+            _ if addr.is_empty() => Err(Error::new(ErrorKind::InvalidInput, "empty unix socket path")),
+            Some("") => Err(Error::new(ErrorKind::InvalidInput, "empty unix socket name")),
+            Some(name) => Ok(UnixAddr::Abstract(name.to_string())),
+            None => Ok(UnixAddr::Path(PathBuf::from(addr))),
+        }
+    }
+}
+
+impl FromStr for LocalAddr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with(UnixAddr::PREFIX) {
+            s.parse().map(LocalAddr::Unix)
+        } else {
+            s.parse()
+                .map(LocalAddr::SocketAddr)
+                .map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+        }
+    }
+}
+
+impl From<SocketAddr> for LocalAddr {
+    fn from(addr: SocketAddr) -> Self {
+        LocalAddr::SocketAddr(addr)
+    }
 }
 
 /// Proxy protocol options.
 #[cfg(feature = "proxy")]
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone)]
 pub struct ProxyOpts {
     pub send_proxy: bool,
     pub accept_proxy: bool,
     pub send_proxy_version: usize,
     pub accept_proxy_timeout: usize,
+    pub send_proxy_tlvs: ProxyTlvs,
+    pub send_proxy_udp: UdpProxy,
+    pub accept_proxy_udp: bool,
+    /// Fall back to the peer address if there is no header.
+    pub accept_proxy_optional: bool,
+    /// Peers allowed to send a header, empty means all.
+    pub accept_proxy_from: Vec<IpNet>,
 }
 
 #[cfg(feature = "proxy")]
@@ -32,18 +131,142 @@ impl ProxyOpts {
     pub(crate) const fn enabled(&self) -> bool {
         self.send_proxy || self.accept_proxy
     }
+
+    #[inline]
+    pub(crate) const fn udp_enabled(&self) -> bool {
+        !matches!(self.send_proxy_udp, UdpProxy::Off) || self.accept_proxy_udp
+    }
+
+    /// Whether a header from this peer should be accepted.
+    pub(crate) fn trusts(&self, peer: IpAddr) -> bool {
+        let peer = peer.to_canonical();
+        self.accept_proxy_from.is_empty() || self.accept_proxy_from.iter().any(|x| x.contains(&peer))
+    }
+}
+
+/// Which datagrams of an association carry a PROXY v2 header.
+#[cfg(feature = "proxy")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UdpProxy {
+    #[default]
+    Off,
+    /// Only the first one.
+    First,
+    Every,
+}
+
+#[cfg(feature = "proxy")]
+impl FromStr for UdpProxy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use UdpProxy::*;
+        match s {
+            "off" => Ok(Off),
+            "first" => Ok(First),
+            "every" => Ok(Every),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown udp proxy mode: {}", s),
+            )),
+        }
+    }
+}
+
+/// TLVs of a sent PROXY v2 header,
+/// written as a comma separated list, e.g. `forward,unique-id`.
+#[cfg(feature = "proxy")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ProxyTlvs {
+    /// TLVs received from the client.
+    pub forward: bool,
+    /// SNI of an incoming tls transport.
+    pub authority: bool,
+    /// ALPN of an incoming tls transport.
+    pub alpn: bool,
+    /// A unique id of each connection.
+    pub unique_id: bool,
+    /// Version and cipher of an incoming tls transport.
+    pub ssl: bool,
+}
+
+#[cfg(feature = "proxy")]
+impl ProxyTlvs {
+    const NAMES: [&'static str; 5] = ["forward", "authority", "alpn", "unique-id", "ssl"];
+
+    fn flags(&self) -> [bool; 5] {
+        [self.forward, self.authority, self.alpn, self.unique_id, self.ssl]
+    }
+
+    /// Whether any TLV comes from the incoming tls transport.
+    #[inline]
+    pub const fn need_tls(&self) -> bool {
+        self.authority || self.alpn || self.ssl
+    }
+}
+
+#[cfg(feature = "proxy")]
+impl FromStr for ProxyTlvs {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tlvs = ProxyTlvs::default();
+        for name in s.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let flag = match name {
+                "forward" => &mut tlvs.forward,
+                "authority" => &mut tlvs.authority,
+                "alpn" => &mut tlvs.alpn,
+                "unique-id" | "unique_id" => &mut tlvs.unique_id,
+                "ssl" => &mut tlvs.ssl,
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("unknown proxy tlv: {}", name),
+                    ))
+                }
+            };
+            *flag = true;
+        }
+        Ok(tlvs)
+    }
+}
+
+/// How connections are spread across tcp listeners.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Steering {
+    /// Hashed by the kernel.
+    #[default]
+    None,
+    /// Prefer the listener bound to the receiving cpu, with `SO_INCOMING_CPU`.
+    IncomingCpu,
+    /// Select the listener by the receiving cpu, with a classic BPF program.
+    Bpf,
 }
 
 /// Connect or associate options.
 #[derive(Debug, Default, Clone)]
 pub struct ConnectOpts {
     pub send_mptcp: bool,
+    pub send_fast_open: bool,
+    pub send_udp_over_tcp: bool,
     pub connect_timeout: usize,
     pub associate_timeout: usize,
+    pub udp_resolve_interval: usize,
+    pub udp_migrate: bool,
+    pub udp_packet_size: usize,
+    pub udp_offload: bool,
     pub tcp_keepalive: usize,
     pub tcp_keepalive_probe: usize,
+    pub tcp_idle_timeout: usize,
+    pub tcp_max_lifetime: usize,
+    pub tcp_shutdown: Shutdown,
     pub bind_address: Option<SocketAddr>,
     pub bind_interface: Option<String>,
+    pub send_mark: u32,
+    pub send_tos: u8,
+    pub send_congestion: Option<String>,
+    pub spoof_source: bool,
+    pub original_dst_allow: Vec<IpNet>,
 
     #[cfg(feature = "proxy")]
     pub proxy_opts: ProxyOpts,
@@ -55,17 +278,32 @@ pub struct ConnectOpts {
     pub balancer: Balancer,
 }
 
+/// Fast open queue of a listener if [`BindOpts::fast_open_queue`] is 0.
+pub const DEFAULT_FAST_OPEN_QUEUE: usize = 256;
+
 #[derive(Debug, Default, Clone)]
 pub struct BindOpts {
     pub ipv6_only: bool,
+    pub transparent: bool,
     pub accept_mptcp: bool,
+    pub accept_fast_open: bool,
+    pub fast_open_queue: usize,
+    pub accept_udp_over_tcp: bool,
+    pub tcp_backlog: usize,
+    pub tcp_listeners: usize,
+    pub tcp_steering: Steering,
+    pub udp_listeners: usize,
     pub bind_interface: Option<String>,
+    pub fd_name: Option<String>,
+    pub accept_mark: u32,
+    pub accept_tos: u8,
+    pub accept_congestion: Option<String>,
 }
 
 /// Relay endpoint.
 #[derive(Debug, Clone)]
 pub struct Endpoint {
-    pub laddr: SocketAddr,
+    pub laddr: LocalAddr,
     pub raddr: RemoteAddr,
     pub bind_opts: BindOpts,
     pub conn_opts: ConnectOpts,
@@ -80,7 +318,67 @@ impl Display for RemoteAddr {
         match self {
             SocketAddr(addr) => write!(f, "{}", addr),
             DomainName(host, port) => write!(f, "{}:{}", host, port),
+            OriginalDst => write!(f, "original-dst"),
+            Unix(addr) => write!(f, "{}", addr),
+        }
+    }
+}
+
+impl Display for UnixAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnixAddr::Path(path) => write!(f, "{}{}", Self::PREFIX, path.display()),
+            UnixAddr::Abstract(name) => write!(f, "{}@{}", Self::PREFIX, name),
+        }
+    }
+}
+
+impl Display for LocalAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LocalAddr::SocketAddr(addr) => write!(f, "{}", addr),
+            LocalAddr::Unix(addr) => write!(f, "{}", addr),
+        }
+    }
+}
+
+impl Display for Steering {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use Steering::*;
+        let s = match self {
+            None => "none",
+            IncomingCpu => "incoming-cpu",
+            Bpf => "bpf",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[cfg(feature = "proxy")]
+impl Display for ProxyTlvs {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut sep = "";
+        for (name, _) in Self::NAMES.iter().zip(self.flags()).filter(|(_, x)| *x) {
+            write!(f, "{}{}", sep, name)?;
+            sep = "|";
         }
+        if sep.is_empty() {
+            write!(f, "none")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "proxy")]
+impl Display for UdpProxy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use UdpProxy::*;
+        let s = match self {
+            Off => "off",
+            First => "first",
+            Every => "every",
+        };
+        write!(f, "{}", s)
     }
 }
 
@@ -98,14 +396,44 @@ impl Display for BindOpts {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let BindOpts {
             accept_mptcp,
+            accept_fast_open,
+            fast_open_queue,
+            accept_udp_over_tcp,
+            tcp_backlog,
+            tcp_listeners,
+            tcp_steering,
+            udp_listeners,
             ipv6_only,
+            transparent,
             bind_interface,
+            fd_name,
+            accept_mark,
+            accept_tos,
+            accept_congestion,
         } = self;
         if let Some(iface) = bind_interface {
             write!(f, "listen-iface={}, ", iface)?;
         }
+        if let Some(name) = fd_name {
+            write!(f, "listen-fd-name={}, ", name)?;
+        }
+        if *accept_mark != 0 {
+            write!(f, "accept-mark={:#x}, ", accept_mark)?;
+        }
+        if *accept_tos != 0 {
+            write!(f, "accept-tos={:#04x}, ", accept_tos)?;
+        }
+        if let Some(cc) = accept_congestion {
+            write!(f, "accept-congestion={}, ", cc)?;
+        }
         write!(f, "ipv6-only={}, ", ipv6_only)?;
-        write!(f, "accept-mptcp={}", accept_mptcp)?;
+        write!(f, "transparent={}, ", transparent)?;
+        write!(f, "accept-mptcp={}, ", accept_mptcp)?;
+        write!(f, "accept-fast-open={}[{}], ", accept_fast_open, fast_open_queue)?;
+        write!(f, "accept-udp-over-tcp={}, ", accept_udp_over_tcp)?;
+        write!(f, "tcp-backlog={}, ", tcp_backlog)?;
+        write!(f, "tcp-listeners={}[{}], ", tcp_listeners, tcp_steering)?;
+        write!(f, "udp-listeners={}", udp_listeners)?;
         Ok(())
     }
 }
@@ -114,12 +442,26 @@ impl Display for ConnectOpts {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let ConnectOpts {
             send_mptcp,
+            send_fast_open,
+            send_udp_over_tcp,
             connect_timeout,
             associate_timeout,
+            udp_resolve_interval,
+            udp_migrate,
+            udp_packet_size,
+            udp_offload,
             tcp_keepalive,
             tcp_keepalive_probe,
+            tcp_idle_timeout,
+            tcp_max_lifetime,
+            tcp_shutdown,
             bind_address,
             bind_interface,
+            send_mark,
+            send_tos,
+            send_congestion,
+            spoof_source,
+            original_dst_allow,
 
             #[cfg(feature = "proxy")]
             proxy_opts,
@@ -139,7 +481,31 @@ impl Display for ConnectOpts {
             write!(f, "send-through={}, ", send_through)?;
         }
 
-        write!(f, "send-mptcp={}; ", send_mptcp)?;
+        if *send_mark != 0 {
+            write!(f, "send-mark={:#x}, ", send_mark)?;
+        }
+
+        if *send_tos != 0 {
+            write!(f, "send-tos={:#04x}, ", send_tos)?;
+        }
+
+        if let Some(cc) = send_congestion {
+            write!(f, "send-congestion={}, ", cc)?;
+        }
+
+        if !original_dst_allow.is_empty() {
+            write!(f, "original-dst-allow=")?;
+            for (i, net) in original_dst_allow.iter().enumerate() {
+                let sep = if i == 0 { "" } else { "|" };
+                write!(f, "{}{}", sep, net)?;
+            }
+            write!(f, ", ")?;
+        }
+
+        write!(f, "spoof-source={}, ", spoof_source)?;
+        write!(f, "send-mptcp={}, ", send_mptcp)?;
+        write!(f, "send-fast-open={}, ", send_fast_open)?;
+        write!(f, "send-udp-over-tcp={}; ", send_udp_over_tcp)?;
 
         #[cfg(feature = "proxy")]
         {
@@ -148,11 +514,34 @@ impl Display for ConnectOpts {
                 accept_proxy,
                 send_proxy_version,
                 accept_proxy_timeout,
+                send_proxy_tlvs,
+                send_proxy_udp,
+                accept_proxy_udp,
+                accept_proxy_optional,
+                accept_proxy_from,
             } = proxy_opts;
+            let accept_proxy = match (accept_proxy, accept_proxy_optional) {
+                (true, true) => "optional",
+                (true, false) => "true",
+                (false, _) => "false",
+            };
             write!(
                 f,
-                "send-proxy={0}, send-proxy-version={2}, accept-proxy={1}, accept-proxy-timeout={3}s; ",
-                send_proxy, accept_proxy, send_proxy_version, accept_proxy_timeout
+                "send-proxy={0}, send-proxy-version={2}, send-proxy-tlvs={4}, accept-proxy={1}, accept-proxy-timeout={3}s, ",
+                send_proxy, accept_proxy, send_proxy_version, accept_proxy_timeout, send_proxy_tlvs
+            )?;
+            if !accept_proxy_from.is_empty() {
+                write!(f, "accept-proxy-from=")?;
+                for (i, net) in accept_proxy_from.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { "|" };
+                    write!(f, "{}{}", sep, net)?;
+                }
+                write!(f, ", ")?;
+            }
+            write!(
+                f,
+                "send-proxy-udp={}, accept-proxy-udp={}; ",
+                send_proxy_udp, accept_proxy_udp
             )?;
         }
 
@@ -162,6 +551,18 @@ impl Display for ConnectOpts {
             tcp_keepalive, tcp_keepalive_probe, connect_timeout, associate_timeout
         )?;
 
+        write!(
+            f,
+            "tcp-idle-timeout={}s, tcp-max-lifetime={}s, tcp-shutdown={}; ",
+            tcp_idle_timeout, tcp_max_lifetime, tcp_shutdown
+        )?;
+
+        write!(
+            f,
+            "udp-resolve-interval={}s, udp-migrate={}, udp-packet-size={}, udp-offload={}; ",
+            udp_resolve_interval, udp_migrate, udp_packet_size, udp_offload
+        )?;
+
         #[cfg(feature = "transport")]
         if let Some((ac, cc)) = transport {
             write!(f, "transport={}||{}; ", ac, cc)?;
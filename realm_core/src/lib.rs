@@ -6,9 +6,17 @@ pub mod udp;
 pub mod time;
 pub mod trick;
 pub mod endpoint;
+pub mod upgrade;
+
+#[cfg(feature = "proxy")]
+pub mod proxy;
+
+#[cfg(unix)]
+pub mod systemd;
 
 pub use realm_io;
 pub use realm_syscall;
+pub use ipnet;
 
 #[cfg(feature = "hook")]
 pub use realm_hook as hook;
@@ -0,0 +1,245 @@
+//! PROXY protocol v1 and v2, without heap allocation.
+//!
+//! See <https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt>.
+//!
+//! [`parse`] works on a (possibly incomplete) buffer, and tells
+//! whether more bytes are required. Encoders write into a buffer
+//! provided by the caller.
+
+pub mod v1;
+pub mod v2;
+
+use std::io::{Error, ErrorKind, Result};
+use std::net::{SocketAddr, SocketAddrV6};
+
+/// The longest header accepted or produced, including TLVs.
+pub const MAX_HEADER_LEN: usize = 1024;
+
+/// Protocol version of a header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    V1,
+    V2,
+}
+
+/// Whether the addresses in a header should be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Sent by the proxy itself, e.g. health checks.
+    Local,
+    /// Relayed on behalf of another node.
+    Proxy,
+}
+
+/// Transport protocol of the original connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Unspec,
+    Stream,
+    Dgram,
+}
+
+/// Length of a unix socket path in a v2 header.
+pub const UNIX_PATH_LEN: usize = 108;
+
+/// Source and destination addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Addrs {
+    /// Unknown, or ignored.
+    None,
+    Inet {
+        src: SocketAddr,
+        dst: SocketAddr,
+    },
+    Unix {
+        src: [u8; UNIX_PATH_LEN],
+        dst: [u8; UNIX_PATH_LEN],
+    },
+}
+
+/// A parsed header, which refers to the input buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header<'a> {
+    pub version: Version,
+    pub command: Command,
+    pub protocol: Protocol,
+    pub addrs: Addrs,
+    /// Raw TLVs of a v2 header, empty for v1.
+    pub tlvs: &'a [u8],
+}
+
+impl Header<'_> {
+    /// Addresses of the original connection, if they should be used.
+    pub fn inet_addrs(&self) -> Option<(SocketAddr, SocketAddr)> {
+        match (self.command, self.addrs) {
+            (Command::Proxy, Addrs::Inet { src, dst }) => Some((src, dst)),
+            _ => None,
+        }
+    }
+
+    /// Iterate TLVs of a v2 header.
+    pub fn tlvs(&self) -> v2::Tlvs<'_> {
+        v2::Tlvs::new(self.tlvs)
+    }
+}
+
+/// Parse a v1 or v2 header at the start of `buf`.
+///
+/// Returns the header and its length, or `None` if `buf` is a prefix
+/// of a valid header, so that more bytes are required.
+pub fn parse(buf: &[u8]) -> Result<Option<(Header<'_>, usize)>> {
+    if is_prefix(buf, v2::SIGNATURE) {
+        v2::parse(buf)
+    } else if is_prefix(buf, v1::SIGNATURE) {
+        v1::parse(buf)
+    } else {
+        Err(invalid("not a proxy-protocol header"))
+    }
+}
+
+/// Whether `buf` could be the start of a header,
+/// which is false once it differs from both signatures.
+pub fn maybe_header(buf: &[u8]) -> bool {
+    is_prefix(buf, v2::SIGNATURE) || is_prefix(buf, v1::SIGNATURE)
+}
+
+/// Whether `buf` and `sig` share the same prefix, which could be either of them.
+#[inline]
+fn is_prefix(buf: &[u8], sig: &[u8]) -> bool {
+    let n = buf.len().min(sig.len());
+    buf[..n] == sig[..n]
+}
+
+/// Make both addresses of the same family,
+/// by converting an ipv4 address to an ipv4-mapped ipv6 address.
+pub fn same_family(src: SocketAddr, dst: SocketAddr) -> (SocketAddr, SocketAddr) {
+    fn to_v6(addr: SocketAddr) -> SocketAddr {
+        match addr {
+            SocketAddr::V4(x) => SocketAddr::V6(SocketAddrV6::new(x.ip().to_ipv6_mapped(), x.port(), 0, 0)),
+            x => x,
+        }
+    }
+
+    match (src, dst) {
+        (SocketAddr::V4(_), SocketAddr::V4(_)) | (SocketAddr::V6(_), SocketAddr::V6(_)) => (src, dst),
+        _ => (to_v6(src), to_v6(dst)),
+    }
+}
+
+/// Make both addresses of the same family, prefer ipv4 if both
+/// are ipv4 or ipv4-mapped, which happens on a dual-stack socket.
+pub fn canonical(src: SocketAddr, dst: SocketAddr) -> (SocketAddr, SocketAddr) {
+    fn unmap(addr: SocketAddr) -> SocketAddr {
+        match addr {
+            SocketAddr::V6(x) => match x.ip().to_ipv4_mapped() {
+                Some(ip) => SocketAddr::new(ip.into(), x.port()),
+                None => addr,
+            },
+            x => x,
+        }
+    }
+
+    same_family(unmap(src), unmap(dst))
+}
+
+#[inline]
+fn invalid(msg: &'static str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[inline]
+fn too_short() -> Error {
+    Error::new(ErrorKind::InvalidInput, "buffer is too short for proxy-protocol header")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dispatch() {
+        let v1 = b"PROXY TCP4 1.1.1.1 2.2.2.2 1000 2000\r\n";
+        let (header, n) = parse(v1).unwrap().unwrap();
+        assert_eq!(header.version, Version::V1);
+        assert_eq!(n, v1.len());
+
+        let mut buf = [0u8; MAX_HEADER_LEN];
+        let src = "1.1.1.1:1000".parse().unwrap();
+        let dst = "2.2.2.2:2000".parse().unwrap();
+        let n = v2::encode(
+            &mut buf,
+            Command::Proxy,
+            Protocol::Stream,
+            &Addrs::Inet { src, dst },
+            &[],
+        )
+        .unwrap();
+        let (header, m) = parse(&buf[..n]).unwrap().unwrap();
+        assert_eq!(header.version, Version::V2);
+        assert_eq!(header.inet_addrs(), Some((src, dst)));
+        assert_eq!(m, n);
+    }
+
+    #[test]
+    fn incomplete_signature() {
+        assert!(parse(b"").unwrap().is_none());
+        assert!(parse(b"PRO").unwrap().is_none());
+        assert!(parse(b"\r\n\r\n\0").unwrap().is_none());
+        assert!(parse(b"\r\n").unwrap().is_none());
+    }
+
+    #[test]
+    fn not_a_header() {
+        for buf in [&b"GET / HTTP/1.1\r\n"[..], b"PROXX", b"\r\n\r\n\x01", b"\x16\x03\x01"] {
+            let err = parse(buf).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn maybe_header() {
+        for buf in [
+            &b""[..],
+            b"PROX",
+            b"PROXY TCP4",
+            b"\r\n\r\n",
+            b"\r\n\r\n\0\r\nQUIT\n\x21",
+        ] {
+            assert!(super::maybe_header(buf), "{:?}", buf);
+        }
+        for buf in [&b"GET /"[..], b"PROXX", b"\r\n\r\n\x01", b"\x16\x03\x01"] {
+            assert!(!super::maybe_header(buf), "{:?}", buf);
+        }
+    }
+
+    #[test]
+    fn mixed_family() {
+        let src: SocketAddr = "1.1.1.1:1000".parse().unwrap();
+        let dst: SocketAddr = "[::1]:2000".parse().unwrap();
+        let (src, dst) = same_family(src, dst);
+        assert_eq!(src, "[::ffff:1.1.1.1]:1000".parse().unwrap());
+        assert_eq!(dst, "[::1]:2000".parse().unwrap());
+
+        let src: SocketAddr = "1.1.1.1:1000".parse().unwrap();
+        let dst: SocketAddr = "2.2.2.2:2000".parse().unwrap();
+        assert_eq!(same_family(src, dst), (src, dst));
+    }
+
+    #[test]
+    fn canonical_family() {
+        let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+
+        // dual-stack socket
+        let (src, dst) = canonical(addr("[::ffff:1.1.1.1]:1000"), addr("[::ffff:2.2.2.2]:2000"));
+        assert_eq!((src, dst), (addr("1.1.1.1:1000"), addr("2.2.2.2:2000")));
+
+        let (src, dst) = canonical(addr("[::ffff:1.1.1.1]:1000"), addr("[::1]:2000"));
+        assert_eq!((src, dst), (addr("[::ffff:1.1.1.1]:1000"), addr("[::1]:2000")));
+
+        let (src, dst) = canonical(addr("1.1.1.1:1000"), addr("[::ffff:2.2.2.2]:2000"));
+        assert_eq!((src, dst), (addr("1.1.1.1:1000"), addr("2.2.2.2:2000")));
+
+        let (src, dst) = canonical(addr("[::2]:1000"), addr("[::1]:2000"));
+        assert_eq!((src, dst), (addr("[::2]:1000"), addr("[::1]:2000")));
+    }
+}
@@ -0,0 +1,282 @@
+//! Human-readable header, e.g. `PROXY TCP4 192.0.2.1 192.0.2.2 1000 2000\r\n`.
+
+use std::io::{Result, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+
+use super::{Header, Version, Command, Protocol, Addrs};
+use super::{invalid, too_short, same_family};
+
+pub const SIGNATURE: &[u8] = b"PROXY ";
+
+/// The longest header, including the trailing CRLF.
+pub const MAX_LEN: usize = 107;
+
+/// Parse a v1 header, see [`super::parse`].
+pub fn parse(buf: &[u8]) -> Result<Option<(Header<'_>, usize)>> {
+    let buf = &buf[..buf.len().min(MAX_LEN)];
+
+    if buf.len() < SIGNATURE.len() {
+        return Ok(None);
+    }
+    if &buf[..SIGNATURE.len()] != SIGNATURE {
+        return Err(invalid("invalid proxy-protocol-v1 signature"));
+    }
+
+    let end = match buf.iter().position(|x| *x == b'\n') {
+        Some(x) => x,
+        None if buf.len() == MAX_LEN => return Err(invalid("proxy-protocol-v1 header is too long")),
+        None => return Ok(None),
+    };
+    if end == 0 || buf[end - 1] != b'\r' {
+        return Err(invalid("proxy-protocol-v1 header does not end with CRLF"));
+    }
+
+    let line = std::str::from_utf8(&buf[SIGNATURE.len()..end - 1])
+        .map_err(|_| invalid("proxy-protocol-v1 header is not ascii"))?;
+    let mut fields = line.split(' ');
+
+    let (protocol, addrs) = match fields.next() {
+        // the rest is ignored
+        Some("UNKNOWN") => (Protocol::Unspec, Addrs::None),
+        Some("TCP4") => (Protocol::Stream, parse_addrs::<Ipv4Addr>(&mut fields)?),
+        Some("TCP6") => (Protocol::Stream, parse_addrs::<Ipv6Addr>(&mut fields)?),
+        _ => return Err(invalid("invalid proxy-protocol-v1 protocol")),
+    };
+
+    let header = Header {
+        version: Version::V1,
+        command: Command::Proxy,
+        protocol,
+        addrs,
+        tlvs: &[],
+    };
+    Ok(Some((header, end + 1)))
+}
+
+fn parse_addrs<'a, T>(fields: &mut impl Iterator<Item = &'a str>) -> Result<Addrs>
+where
+    T: FromStr + Into<IpAddr>,
+{
+    let mut next = || fields.next().ok_or_else(|| invalid("missing proxy-protocol-v1 field"));
+
+    let src = T::from_str(next()?).map_err(|_| invalid("invalid proxy-protocol-v1 address"))?;
+    let dst = T::from_str(next()?).map_err(|_| invalid("invalid proxy-protocol-v1 address"))?;
+    let sport = parse_port(next()?)?;
+    let dport = parse_port(next()?)?;
+
+    if fields.next().is_some() {
+        return Err(invalid("unexpected proxy-protocol-v1 field"));
+    }
+
+    Ok(Addrs::Inet {
+        src: SocketAddr::new(src.into(), sport),
+        dst: SocketAddr::new(dst.into(), dport),
+    })
+}
+
+fn parse_port(s: &str) -> Result<u16> {
+    // u16::from_str accepts a leading '+'
+    if s.is_empty() || s.len() > 5 || !s.bytes().all(|x| x.is_ascii_digit()) {
+        return Err(invalid("invalid proxy-protocol-v1 port"));
+    }
+    s.parse().map_err(|_| invalid("invalid proxy-protocol-v1 port"))
+}
+
+/// Write a v1 header into `buf`, returns its length.
+///
+/// Addresses of different families are sent as ipv6 addresses.
+pub fn encode(buf: &mut [u8], src: SocketAddr, dst: SocketAddr) -> Result<usize> {
+    let (src, dst) = same_family(src, dst);
+    let proto = if src.is_ipv4() { "TCP4" } else { "TCP6" };
+
+    let total = buf.len();
+    let mut cursor = &mut buf[..];
+    write!(
+        cursor,
+        "PROXY {} {} {} {} {}\r\n",
+        proto,
+        src.ip(),
+        dst.ip(),
+        src.port(),
+        dst.port()
+    )
+    .map_err(|_| too_short())?;
+    Ok(total - cursor.len())
+}
+
+/// Write a v1 header of unknown addresses into `buf`, returns its length.
+pub fn encode_unknown(buf: &mut [u8]) -> Result<usize> {
+    const UNKNOWN: &[u8] = b"PROXY UNKNOWN\r\n";
+
+    let buf = buf.get_mut(..UNKNOWN.len()).ok_or_else(too_short)?;
+    buf.copy_from_slice(UNKNOWN);
+    Ok(UNKNOWN.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::ErrorKind;
+
+    fn parse_ok(buf: &[u8]) -> (Header<'_>, usize) {
+        parse(buf).unwrap().unwrap()
+    }
+
+    fn parse_err(buf: &[u8]) -> ErrorKind {
+        parse(buf).unwrap_err().kind()
+    }
+
+    #[test]
+    fn tcp4() {
+        let buf = b"PROXY TCP4 192.0.2.1 192.0.2.2 56324 443\r\nGET /";
+        let (header, n) = parse_ok(buf);
+        assert_eq!(n, buf.len() - 5);
+        assert_eq!(header.version, Version::V1);
+        assert_eq!(header.command, Command::Proxy);
+        assert_eq!(header.protocol, Protocol::Stream);
+        assert_eq!(
+            header.inet_addrs(),
+            Some(("192.0.2.1:56324".parse().unwrap(), "192.0.2.2:443".parse().unwrap()))
+        );
+        assert!(header.tlvs.is_empty());
+    }
+
+    #[test]
+    fn tcp6() {
+        let buf = b"PROXY TCP6 2001:db8::1 ::1 0 65535\r\n";
+        let (header, n) = parse_ok(buf);
+        assert_eq!(n, buf.len());
+        assert_eq!(
+            header.inet_addrs(),
+            Some(("[2001:db8::1]:0".parse().unwrap(), "[::1]:65535".parse().unwrap()))
+        );
+    }
+
+    #[test]
+    fn unknown() {
+        for buf in [
+            &b"PROXY UNKNOWN\r\n"[..],
+            b"PROXY UNKNOWN ffff:f...f:ffff ffff:f...f:ffff 65535 65535\r\n",
+        ] {
+            let (header, n) = parse_ok(buf);
+            assert_eq!(n, buf.len());
+            assert_eq!(header.protocol, Protocol::Unspec);
+            assert_eq!(header.addrs, Addrs::None);
+            assert_eq!(header.inet_addrs(), None);
+        }
+    }
+
+    #[test]
+    fn longest() {
+        let buf = b"PROXY UNKNOWN ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff 65535 65535\r\n";
+        assert_eq!(buf.len(), MAX_LEN);
+        let (_, n) = parse_ok(buf);
+        assert_eq!(n, MAX_LEN);
+
+        let buf = b"PROXY TCP6 ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff 65535 65535\r\n";
+        let (header, n) = parse_ok(buf);
+        assert_eq!(n, buf.len());
+        assert!(header.inet_addrs().is_some());
+    }
+
+    #[test]
+    fn incomplete() {
+        let buf = b"PROXY TCP4 192.0.2.1 192.0.2.2 56324 443\r\n";
+        for n in 0..buf.len() {
+            assert!(parse(&buf[..n]).unwrap().is_none(), "{}", n);
+        }
+    }
+
+    #[test]
+    fn too_long() {
+        let mut buf = [b'1'; 200];
+        buf[..SIGNATURE.len()].copy_from_slice(SIGNATURE);
+        assert_eq!(parse_err(&buf), ErrorKind::InvalidData);
+        assert_eq!(parse_err(&buf[..MAX_LEN]), ErrorKind::InvalidData);
+        assert!(parse(&buf[..MAX_LEN - 1]).unwrap().is_none());
+    }
+
+    #[test]
+    fn malformed() {
+        let cases: &[&[u8]] = &[
+            b"PROXY\r\n",
+            b"PROXY \r\n",
+            b"PROXY TCP4 192.0.2.1 192.0.2.2 56324 443\n",
+            b"PROXY TCP4 192.0.2.1 192.0.2.2 56324 443\r\r\n",
+            b"PROXY UDP4 192.0.2.1 192.0.2.2 56324 443\r\n",
+            b"PROXY tcp4 192.0.2.1 192.0.2.2 56324 443\r\n",
+            b"PROXY TCP4 192.0.2.1 192.0.2.2 56324\r\n",
+            b"PROXY TCP4 192.0.2.1 192.0.2.2 56324 443 1\r\n",
+            b"PROXY TCP4  192.0.2.1 192.0.2.2 56324 443\r\n",
+            b"PROXY TCP4 192.0.2.1 192.0.2.2 56324 443 \r\n",
+            b"PROXY TCP4 192.0.2.256 192.0.2.2 56324 443\r\n",
+            b"PROXY TCP4 ::1 192.0.2.2 56324 443\r\n",
+            b"PROXY TCP6 192.0.2.1 ::1 56324 443\r\n",
+            b"PROXY TCP4 192.0.2.1 192.0.2.2 65536 443\r\n",
+            b"PROXY TCP4 192.0.2.1 192.0.2.2 +80 443\r\n",
+            b"PROXY TCP4 192.0.2.1 192.0.2.2 -1 443\r\n",
+            b"PROXY TCP4 192.0.2.1 192.0.2.2 0x50 443\r\n",
+            b"PROXY TCP4 192.0.2.1 192.0.2.2 \xff 443\r\n",
+            b"PROXY\tTCP4 192.0.2.1 192.0.2.2 56324 443\r\n",
+        ];
+        for buf in cases {
+            assert_eq!(
+                parse_err(buf),
+                ErrorKind::InvalidData,
+                "{:?}",
+                String::from_utf8_lossy(buf)
+            );
+        }
+    }
+
+    #[test]
+    fn encode_decode() {
+        let cases = [
+            ("192.0.2.1:1000", "192.0.2.2:2000"),
+            ("[2001:db8::1]:1000", "[2001:db8::2]:2000"),
+            (
+                "[ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff]:65535",
+                "[ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff]:65535",
+            ),
+        ];
+        for (src, dst) in cases {
+            let src: SocketAddr = src.parse().unwrap();
+            let dst: SocketAddr = dst.parse().unwrap();
+
+            let mut buf = [0u8; MAX_LEN];
+            let n = encode(&mut buf, src, dst).unwrap();
+            let (header, m) = parse_ok(&buf[..n]);
+            assert_eq!(m, n);
+            assert_eq!(header.inet_addrs(), Some((src, dst)));
+        }
+    }
+
+    #[test]
+    fn encode_format() {
+        let mut buf = [0u8; MAX_LEN];
+        let n = encode(
+            &mut buf,
+            "192.0.2.1:1000".parse().unwrap(),
+            "[::1]:2000".parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(&buf[..n], b"PROXY TCP6 ::ffff:192.0.2.1 ::1 1000 2000\r\n");
+
+        let n = encode_unknown(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"PROXY UNKNOWN\r\n");
+    }
+
+    #[test]
+    fn encode_short_buffer() {
+        let mut buf = [0u8; 16];
+        let err = encode(
+            &mut buf,
+            "192.0.2.1:1000".parse().unwrap(),
+            "192.0.2.2:2000".parse().unwrap(),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(encode_unknown(&mut buf[..8]).is_err());
+    }
+}
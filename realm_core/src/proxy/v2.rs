@@ -0,0 +1,599 @@
+//! Binary header, with optional TLVs.
+
+use std::io::Result;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+use super::{Header, Version, Command, Protocol, Addrs, UNIX_PATH_LEN, MAX_HEADER_LEN};
+use super::{invalid, too_short, same_family};
+
+pub const SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Length of the fixed part, including the signature.
+pub const HEADER_LEN: usize = 16;
+
+const AF_UNSPEC: u8 = 0;
+const AF_INET: u8 = 1;
+const AF_INET6: u8 = 2;
+const AF_UNIX: u8 = 3;
+
+/// Types of TLVs.
+pub mod tlv {
+    pub const ALPN: u8 = 0x01;
+    pub const AUTHORITY: u8 = 0x02;
+    pub const CRC32C: u8 = 0x03;
+    pub const NOOP: u8 = 0x04;
+    pub const UNIQUE_ID: u8 = 0x05;
+    pub const SSL: u8 = 0x20;
+    pub const SUBTYPE_SSL_VERSION: u8 = 0x21;
+    pub const SUBTYPE_SSL_CN: u8 = 0x22;
+    pub const SUBTYPE_SSL_CIPHER: u8 = 0x23;
+    pub const SUBTYPE_SSL_SIG_ALG: u8 = 0x24;
+    pub const SUBTYPE_SSL_KEY_ALG: u8 = 0x25;
+    pub const NETNS: u8 = 0x30;
+
+    /// Flags of the client field of [`SSL`].
+    pub const CLIENT_SSL: u8 = 0x01;
+    pub const CLIENT_CERT_CONN: u8 = 0x02;
+    pub const CLIENT_CERT_SESS: u8 = 0x04;
+
+    /// The longest value of [`UNIQUE_ID`].
+    pub const UNIQUE_ID_MAX_LEN: usize = 128;
+}
+
+const INET_LEN: usize = 12;
+const INET6_LEN: usize = 36;
+const UNIX_LEN: usize = 2 * UNIX_PATH_LEN;
+
+/// The longest header of inet addresses, without TLVs.
+pub const MAX_INET_LEN: usize = HEADER_LEN + INET6_LEN;
+
+/// Room for TLVs of a header with ipv6 addresses.
+pub const MAX_TLVS_LEN: usize = MAX_HEADER_LEN - MAX_INET_LEN;
+
+/// Parse a v2 header, see [`super::parse`].
+pub fn parse(buf: &[u8]) -> Result<Option<(Header<'_>, usize)>> {
+    if buf.len() < HEADER_LEN {
+        return Ok(None);
+    }
+    if &buf[..SIGNATURE.len()] != SIGNATURE {
+        return Err(invalid("invalid proxy-protocol-v2 signature"));
+    }
+
+    let ver_cmd = buf[12];
+    if ver_cmd >> 4 != 2 {
+        return Err(invalid("invalid proxy-protocol-v2 version"));
+    }
+    let command = match ver_cmd & 0x0f {
+        0 => Command::Local,
+        1 => Command::Proxy,
+        _ => return Err(invalid("invalid proxy-protocol-v2 command")),
+    };
+
+    let fam = buf[13];
+    let (family, addr_len) = match fam >> 4 {
+        AF_UNSPEC => (AF_UNSPEC, 0),
+        AF_INET => (AF_INET, INET_LEN),
+        AF_INET6 => (AF_INET6, INET6_LEN),
+        AF_UNIX => (AF_UNIX, UNIX_LEN),
+        _ => return Err(invalid("invalid proxy-protocol-v2 address family")),
+    };
+    let protocol = match fam & 0x0f {
+        0 => Protocol::Unspec,
+        1 => Protocol::Stream,
+        2 => Protocol::Dgram,
+        _ => return Err(invalid("invalid proxy-protocol-v2 protocol")),
+    };
+
+    let len = u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    let total = HEADER_LEN + len;
+    if total > MAX_HEADER_LEN {
+        return Err(invalid("proxy-protocol-v2 header is too long"));
+    }
+    if buf.len() < total {
+        return Ok(None);
+    }
+    if len < addr_len {
+        return Err(invalid("proxy-protocol-v2 addresses are truncated"));
+    }
+
+    let body = &buf[HEADER_LEN..total];
+    let (addrs, tlvs) = body.split_at(addr_len);
+
+    // addresses of LOCAL are ignored
+    let addrs = match (command, family) {
+        (Command::Local, _) | (_, AF_UNSPEC) => Addrs::None,
+        (_, AF_INET) => {
+            let ip = |x: &[u8]| Ipv4Addr::from(<[u8; 4]>::try_from(x).unwrap());
+            let port = |x: &[u8]| u16::from_be_bytes([x[0], x[1]]);
+            Addrs::Inet {
+                src: SocketAddr::V4(SocketAddrV4::new(ip(&addrs[0..4]), port(&addrs[8..10]))),
+                dst: SocketAddr::V4(SocketAddrV4::new(ip(&addrs[4..8]), port(&addrs[10..12]))),
+            }
+        }
+        (_, AF_INET6) => {
+            let ip = |x: &[u8]| Ipv6Addr::from(<[u8; 16]>::try_from(x).unwrap());
+            let port = |x: &[u8]| u16::from_be_bytes([x[0], x[1]]);
+            Addrs::Inet {
+                src: SocketAddr::V6(SocketAddrV6::new(ip(&addrs[0..16]), port(&addrs[32..34]), 0, 0)),
+                dst: SocketAddr::V6(SocketAddrV6::new(ip(&addrs[16..32]), port(&addrs[34..36]), 0, 0)),
+            }
+        }
+        _ => Addrs::Unix {
+            src: addrs[..UNIX_PATH_LEN].try_into().unwrap(),
+            dst: addrs[UNIX_PATH_LEN..].try_into().unwrap(),
+        },
+    };
+
+    // make sure all TLVs are complete
+    let mut iter = Tlvs::new(tlvs);
+    for _ in iter.by_ref() {}
+    if !iter.rest.is_empty() {
+        return Err(invalid("proxy-protocol-v2 tlv is truncated"));
+    }
+
+    let header = Header {
+        version: Version::V2,
+        command,
+        protocol,
+        addrs,
+        tlvs,
+    };
+    Ok(Some((header, total)))
+}
+
+/// Iterator of `(type, value)` over raw TLVs.
+///
+/// It stops at a truncated TLV.
+#[derive(Debug, Clone)]
+pub struct Tlvs<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Tlvs<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { rest: buf }
+    }
+}
+
+impl<'a> Iterator for Tlvs<'a> {
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.len() < 3 {
+            return None;
+        }
+        let len = u16::from_be_bytes([self.rest[1], self.rest[2]]) as usize;
+        if self.rest.len() < 3 + len {
+            return None;
+        }
+        let ty = self.rest[0];
+        let value = &self.rest[3..3 + len];
+        self.rest = &self.rest[3 + len..];
+        Some((ty, value))
+    }
+}
+
+/// Writer of raw TLVs over a buffer provided by the caller.
+#[derive(Debug)]
+pub struct TlvWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> TlvWriter<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    /// Append a TLV.
+    pub fn push(&mut self, ty: u8, value: &[u8]) -> Result<()> {
+        self.push_with(ty, value.len(), |buf| buf.copy_from_slice(value))
+    }
+
+    /// Append a [`tlv::SSL`] TLV, which contains sub-TLVs.
+    pub fn push_ssl(&mut self, client: u8, verify: u32, subs: &[(u8, &[u8])]) -> Result<()> {
+        let len = 5 + subs.iter().map(|(_, x)| 3 + x.len()).sum::<usize>();
+        let mut res = Ok(());
+        self.push_with(tlv::SSL, len, |buf| {
+            buf[0] = client;
+            buf[1..5].copy_from_slice(&verify.to_be_bytes());
+            let mut sub = TlvWriter::new(&mut buf[5..]);
+            res = subs.iter().try_for_each(|(ty, x)| sub.push(*ty, x));
+        })?;
+        res
+    }
+
+    fn push_with(&mut self, ty: u8, len: usize, f: impl FnOnce(&mut [u8])) -> Result<()> {
+        if len > u16::MAX as usize {
+            return Err(invalid("proxy-protocol-v2 tlv is too long"));
+        }
+        let buf = self.buf.get_mut(self.len..self.len + 3 + len).ok_or_else(too_short)?;
+        buf[0] = ty;
+        buf[1..3].copy_from_slice(&(len as u16).to_be_bytes());
+        f(&mut buf[3..]);
+        self.len += 3 + len;
+        Ok(())
+    }
+
+    /// Raw TLVs written so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+/// Write a v2 header into `buf`, followed by raw `tlvs`, returns its length.
+///
+/// Addresses of different families are sent as ipv6 addresses.
+pub fn encode(buf: &mut [u8], command: Command, protocol: Protocol, addrs: &Addrs, tlvs: &[u8]) -> Result<usize> {
+    let addrs = match *addrs {
+        Addrs::Inet { src, dst } => {
+            let (src, dst) = same_family(src, dst);
+            Addrs::Inet { src, dst }
+        }
+        x => x,
+    };
+    let (family, addr_len) = match addrs {
+        Addrs::None => (AF_UNSPEC, 0),
+        Addrs::Inet { src, .. } if src.is_ipv4() => (AF_INET, INET_LEN),
+        Addrs::Inet { .. } => (AF_INET6, INET6_LEN),
+        Addrs::Unix { .. } => (AF_UNIX, UNIX_LEN),
+    };
+
+    let len = addr_len + tlvs.len();
+    let total = HEADER_LEN + len;
+    if total > MAX_HEADER_LEN {
+        return Err(invalid("proxy-protocol-v2 header is too long"));
+    }
+    let buf = buf.get_mut(..total).ok_or_else(too_short)?;
+
+    buf[..SIGNATURE.len()].copy_from_slice(SIGNATURE);
+    buf[12] = 0x20
+        | match command {
+            Command::Local => 0,
+            Command::Proxy => 1,
+        };
+    buf[13] = family << 4
+        | match protocol {
+            Protocol::Unspec => 0,
+            Protocol::Stream => 1,
+            Protocol::Dgram => 2,
+        };
+    buf[14..16].copy_from_slice(&(len as u16).to_be_bytes());
+
+    let body = &mut buf[HEADER_LEN..];
+    match addrs {
+        Addrs::None => {}
+        Addrs::Inet { src, dst } => match (src, dst) {
+            (SocketAddr::V4(src), SocketAddr::V4(dst)) => {
+                body[0..4].copy_from_slice(&src.ip().octets());
+                body[4..8].copy_from_slice(&dst.ip().octets());
+                body[8..10].copy_from_slice(&src.port().to_be_bytes());
+                body[10..12].copy_from_slice(&dst.port().to_be_bytes());
+            }
+            (SocketAddr::V6(src), SocketAddr::V6(dst)) => {
+                body[0..16].copy_from_slice(&src.ip().octets());
+                body[16..32].copy_from_slice(&dst.ip().octets());
+                body[32..34].copy_from_slice(&src.port().to_be_bytes());
+                body[34..36].copy_from_slice(&dst.port().to_be_bytes());
+            }
+            _ => unreachable!(),
+        },
+        Addrs::Unix { src, dst } => {
+            body[..UNIX_PATH_LEN].copy_from_slice(&src);
+            body[UNIX_PATH_LEN..UNIX_LEN].copy_from_slice(&dst);
+        }
+    }
+    body[addr_len..].copy_from_slice(tlvs);
+
+    Ok(total)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::ErrorKind;
+
+    fn header(cmd: u8, fam: u8, body: &[u8]) -> Vec<u8> {
+        let mut buf = SIGNATURE.to_vec();
+        buf.push(cmd);
+        buf.push(fam);
+        buf.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        buf.extend_from_slice(body);
+        buf
+    }
+
+    const INET: [u8; 12] = [192, 0, 2, 1, 192, 0, 2, 2, 0xdc, 0x04, 0x01, 0xbb];
+
+    #[test]
+    fn inet() {
+        let mut buf = header(0x21, 0x11, &INET);
+        buf.extend_from_slice(b"GET /");
+        let (header, n) = parse(&buf).unwrap().unwrap();
+        assert_eq!(n, HEADER_LEN + 12);
+        assert_eq!(header.version, Version::V2);
+        assert_eq!(header.command, Command::Proxy);
+        assert_eq!(header.protocol, Protocol::Stream);
+        assert_eq!(
+            header.inet_addrs(),
+            Some(("192.0.2.1:56324".parse().unwrap(), "192.0.2.2:443".parse().unwrap()))
+        );
+        assert!(header.tlvs.is_empty());
+    }
+
+    #[test]
+    fn inet6() {
+        let mut body = [0u8; 36];
+        body[15] = 1;
+        body[16] = 0x20;
+        body[17] = 0x01;
+        body[31] = 2;
+        body[32..34].copy_from_slice(&1000u16.to_be_bytes());
+        body[34..36].copy_from_slice(&2000u16.to_be_bytes());
+        let buf = header(0x21, 0x22, &body);
+
+        let (header, n) = parse(&buf).unwrap().unwrap();
+        assert_eq!(n, buf.len());
+        assert_eq!(header.protocol, Protocol::Dgram);
+        assert_eq!(
+            header.inet_addrs(),
+            Some(("[::1]:1000".parse().unwrap(), "[2001::2]:2000".parse().unwrap()))
+        );
+    }
+
+    #[test]
+    fn unix() {
+        let mut body = [0u8; UNIX_LEN];
+        body[..4].copy_from_slice(b"/src");
+        body[UNIX_PATH_LEN..UNIX_PATH_LEN + 4].copy_from_slice(b"/dst");
+        let buf = header(0x21, 0x31, &body);
+
+        let (header, n) = parse(&buf).unwrap().unwrap();
+        assert_eq!(n, buf.len());
+        assert_eq!(header.inet_addrs(), None);
+        match header.addrs {
+            Addrs::Unix { src, dst } => {
+                assert_eq!(&src[..5], b"/src\0");
+                assert_eq!(&dst[..5], b"/dst\0");
+            }
+            x => panic!("unexpected {:?}", x),
+        }
+    }
+
+    #[test]
+    fn local() {
+        // addresses are ignored
+        let buf = header(0x20, 0x11, &INET);
+        let (header, n) = parse(&buf).unwrap().unwrap();
+        assert_eq!(n, buf.len());
+        assert_eq!(header.command, Command::Local);
+        assert_eq!(header.addrs, Addrs::None);
+        assert_eq!(header.inet_addrs(), None);
+
+        let buf = header_unspec();
+        let (header, n) = parse(&buf).unwrap().unwrap();
+        assert_eq!(n, HEADER_LEN);
+        assert_eq!(header.command, Command::Local);
+        assert_eq!(header.protocol, Protocol::Unspec);
+    }
+
+    fn header_unspec() -> Vec<u8> {
+        header(0x20, 0x00, &[])
+    }
+
+    #[test]
+    fn unspec() {
+        // real addresses should be used
+        let buf = header(0x21, 0x00, &[]);
+        let (header, _) = parse(&buf).unwrap().unwrap();
+        assert_eq!(header.command, Command::Proxy);
+        assert_eq!(header.inet_addrs(), None);
+    }
+
+    #[test]
+    fn tlvs() {
+        let mut body = INET.to_vec();
+        body.extend_from_slice(&[0x02, 0x00, 0x0b]);
+        body.extend_from_slice(b"example.com");
+        body.extend_from_slice(&[0x04, 0x00, 0x00]);
+        body.extend_from_slice(&[0xe0, 0x00, 0x02, 0xaa, 0xbb]);
+        let buf = header(0x21, 0x11, &body);
+
+        let (header, n) = parse(&buf).unwrap().unwrap();
+        assert_eq!(n, buf.len());
+        assert_eq!(header.tlvs, &body[12..]);
+
+        let tlvs: Vec<_> = header.tlvs().collect();
+        assert_eq!(
+            tlvs,
+            vec![(0x02, &b"example.com"[..]), (0x04, &[][..]), (0xe0, &[0xaa, 0xbb][..])]
+        );
+    }
+
+    #[test]
+    fn tlv_writer() {
+        let mut buf = [0u8; 64];
+        let mut w = TlvWriter::new(&mut buf);
+        w.push(tlv::AUTHORITY, b"example.com").unwrap();
+        w.push(tlv::NOOP, &[]).unwrap();
+        w.push_ssl(
+            tlv::CLIENT_SSL,
+            0,
+            &[(tlv::SUBTYPE_SSL_VERSION, b"TLSv1.3"), (tlv::SUBTYPE_SSL_CIPHER, b"X")],
+        )
+        .unwrap();
+
+        let mut tlvs = Tlvs::new(w.as_bytes());
+        assert_eq!(tlvs.next(), Some((tlv::AUTHORITY, &b"example.com"[..])));
+        assert_eq!(tlvs.next(), Some((tlv::NOOP, &[][..])));
+        let (ty, ssl) = tlvs.next().unwrap();
+        assert_eq!(ty, tlv::SSL);
+        assert_eq!(tlvs.next(), None);
+
+        assert_eq!(&ssl[..5], &[tlv::CLIENT_SSL, 0, 0, 0, 0]);
+        let subs: Vec<_> = Tlvs::new(&ssl[5..]).collect();
+        assert_eq!(
+            subs,
+            vec![
+                (tlv::SUBTYPE_SSL_VERSION, &b"TLSv1.3"[..]),
+                (tlv::SUBTYPE_SSL_CIPHER, &b"X"[..])
+            ]
+        );
+
+        // parsed back as part of a header
+        let mut out = [0u8; MAX_HEADER_LEN];
+        let addrs = Addrs::Inet {
+            src: "192.0.2.1:1000".parse().unwrap(),
+            dst: "192.0.2.2:2000".parse().unwrap(),
+        };
+        let n = encode(&mut out, Command::Proxy, Protocol::Stream, &addrs, w.as_bytes()).unwrap();
+        let (header, _) = parse(&out[..n]).unwrap().unwrap();
+        assert_eq!(header.tlvs, w.as_bytes());
+    }
+
+    #[test]
+    fn tlv_writer_short_buffer() {
+        let mut buf = [0u8; 8];
+        let mut w = TlvWriter::new(&mut buf);
+        w.push(tlv::ALPN, b"h2").unwrap();
+        let err = w.push(tlv::ALPN, b"h2").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        // nothing is written on failure
+        assert_eq!(w.as_bytes(), &[tlv::ALPN, 0, 2, b'h', b'2']);
+        assert!(w.push_ssl(tlv::CLIENT_SSL, 0, &[]).is_err());
+        assert_eq!(w.as_bytes().len(), 5);
+    }
+
+    #[test]
+    fn incomplete() {
+        let mut body = INET.to_vec();
+        body.extend_from_slice(&[0x02, 0x00, 0x03, b'a', b'b', b'c']);
+        let buf = header(0x21, 0x11, &body);
+        for n in 0..buf.len() {
+            assert!(parse(&buf[..n]).unwrap().is_none(), "{}", n);
+        }
+        assert!(parse(&buf).unwrap().is_some());
+    }
+
+    #[test]
+    fn malformed() {
+        let truncated_tlv = {
+            let mut body = INET.to_vec();
+            body.extend_from_slice(&[0x02, 0x00, 0x04, b'a', b'b', b'c']);
+            body
+        };
+        let short_tlv = {
+            let mut body = INET.to_vec();
+            body.extend_from_slice(&[0x02, 0x00]);
+            body
+        };
+        let cases: Vec<Vec<u8>> = vec![
+            // version
+            header(0x11, 0x11, &INET),
+            header(0x31, 0x11, &INET),
+            // command
+            header(0x22, 0x11, &INET),
+            header(0x2f, 0x11, &INET),
+            // family
+            header(0x21, 0x41, &INET),
+            header(0x21, 0xf1, &INET),
+            // protocol
+            header(0x21, 0x13, &INET),
+            header(0x21, 0x1f, &INET),
+            // addresses
+            header(0x21, 0x11, &INET[..11]),
+            header(0x21, 0x21, &[0u8; 35]),
+            header(0x21, 0x31, &[0u8; 215]),
+            // tlvs
+            header(0x21, 0x11, &truncated_tlv),
+            header(0x21, 0x11, &short_tlv),
+        ];
+        for buf in cases {
+            assert_eq!(parse(&buf).unwrap_err().kind(), ErrorKind::InvalidData, "{:x?}", buf);
+        }
+
+        // signature
+        let mut buf = header(0x21, 0x11, &INET);
+        buf[11] = b'X';
+        assert_eq!(parse(&buf).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn too_long() {
+        // rejected before the whole header arrives
+        let mut buf = header(0x21, 0x11, &INET);
+        buf[14..16].copy_from_slice(&(MAX_HEADER_LEN as u16).to_be_bytes());
+        assert_eq!(parse(&buf[..HEADER_LEN]).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        let body = vec![0u8; MAX_HEADER_LEN - HEADER_LEN + 1];
+        let buf = header(0x21, 0x00, &body);
+        assert!(parse(&buf).is_err());
+
+        // empty tlvs, the longest one
+        let body = vec![0u8; MAX_HEADER_LEN - HEADER_LEN];
+        let buf = header(0x21, 0x00, &body[..body.len() / 3 * 3]);
+        assert!(parse(&buf).unwrap().is_some());
+    }
+
+    #[test]
+    fn encode_decode() {
+        let cases = [
+            ("192.0.2.1:1000", "192.0.2.2:2000"),
+            ("[2001:db8::1]:1000", "[2001:db8::2]:2000"),
+            ("192.0.2.1:1000", "[2001:db8::2]:2000"),
+        ];
+        let tlvs = [0x02, 0x00, 0x03, b'a', b'b', b'c'];
+
+        for (src, dst) in cases {
+            let src: SocketAddr = src.parse().unwrap();
+            let dst: SocketAddr = dst.parse().unwrap();
+
+            let mut buf = [0u8; MAX_HEADER_LEN];
+            for protocol in [Protocol::Stream, Protocol::Dgram] {
+                let n = encode(&mut buf, Command::Proxy, protocol, &Addrs::Inet { src, dst }, &tlvs).unwrap();
+                let (header, m) = parse(&buf[..n]).unwrap().unwrap();
+                assert_eq!(m, n);
+                assert_eq!(header.protocol, protocol);
+                assert_eq!(header.inet_addrs(), Some(same_family(src, dst)));
+                assert_eq!(header.tlvs, &tlvs);
+            }
+        }
+    }
+
+    #[test]
+    fn encode_format() {
+        let mut buf = [0u8; MAX_HEADER_LEN];
+        let addrs = Addrs::Inet {
+            src: "192.0.2.1:56324".parse().unwrap(),
+            dst: "192.0.2.2:443".parse().unwrap(),
+        };
+        let n = encode(&mut buf, Command::Proxy, Protocol::Stream, &addrs, &[]).unwrap();
+        assert_eq!(&buf[..n], &header(0x21, 0x11, &INET)[..]);
+
+        let n = encode(&mut buf, Command::Local, Protocol::Unspec, &Addrs::None, &[]).unwrap();
+        assert_eq!(&buf[..n], &header_unspec()[..]);
+
+        let addrs = Addrs::Unix {
+            src: [1; UNIX_PATH_LEN],
+            dst: [2; UNIX_PATH_LEN],
+        };
+        let n = encode(&mut buf, Command::Proxy, Protocol::Stream, &addrs, &[]).unwrap();
+        let (header, _) = parse(&buf[..n]).unwrap().unwrap();
+        assert_eq!(header.addrs, addrs);
+    }
+
+    #[test]
+    fn encode_short_buffer() {
+        let mut buf = [0u8; 20];
+        let addrs = Addrs::Inet {
+            src: "192.0.2.1:56324".parse().unwrap(),
+            dst: "192.0.2.2:443".parse().unwrap(),
+        };
+        let err = encode(&mut buf, Command::Proxy, Protocol::Stream, &addrs, &[]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+        let mut buf = [0u8; 2 * MAX_HEADER_LEN];
+        let tlvs = [0u8; MAX_HEADER_LEN];
+        let err = encode(&mut buf, Command::Proxy, Protocol::Stream, &addrs, &tlvs).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
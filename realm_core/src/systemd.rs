@@ -0,0 +1,185 @@
+//! Socket activation and readiness notification of systemd.
+//!
+//! Listeners passed by systemd are taken by endpoints instead of binding
+//! new sockets, matched by their names(`FileDescriptorName=`) if
+//! the endpoint has one, otherwise by their local addresses.
+
+use std::io::Result;
+use std::net::SocketAddr;
+use std::os::unix::io::OwnedFd;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use realm_syscall::socket2::{SockRef, Type};
+
+use crate::endpoint::UnixAddr;
+
+struct Inherited {
+    fd: OwnedFd,
+    name: Option<String>,
+}
+
+static INHERITED: Mutex<Vec<Inherited>> = Mutex::new(Vec::new());
+
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// Take listeners passed by systemd, see [`realm_syscall::listen_fds`],
+/// or by the old process on upgrade.
+///
+/// This should be called before the runtime starts.
+/// Returns the number of inherited listeners.
+pub fn inherit_listen_fds() -> Result<usize> {
+    let mut fds = realm_syscall::listen_fds()?;
+    fds.extend(crate::upgrade::take_fds()?);
+    let n = fds.len();
+    for (fd, name) in fds {
+        inherit(fd, name);
+    }
+    Ok(n)
+}
+
+/// Add a listener, which could be taken by an endpoint later.
+pub fn inherit(fd: OwnedFd, name: Option<String>) {
+    INHERITED.lock().unwrap().push(Inherited { fd, name });
+}
+
+/// Take all inherited listeners of a socket type, which match `name` or `laddr`.
+fn take<F>(ty: Type, name: Option<&str>, laddr: F) -> Vec<OwnedFd>
+where
+    F: Fn(&SockRef) -> bool,
+{
+    let mut inherited = INHERITED.lock().unwrap();
+    let mut fds = Vec::new();
+    let mut idx = 0;
+
+    while idx < inherited.len() {
+        let Inherited { fd, name: x } = &inherited[idx];
+        let sock = SockRef::from(fd);
+        let matched = sock.r#type().is_ok_and(|t| t == ty)
+            && match name {
+                Some(name) => x.as_deref() == Some(name),
+                None => laddr(&sock),
+            };
+
+        if matched {
+            let Inherited { fd, .. } = inherited.remove(idx);
+            fds.push(fd);
+        } else {
+            idx += 1;
+        }
+    }
+
+    fds
+}
+
+pub(crate) fn take_tcp(laddr: &SocketAddr, name: Option<&str>) -> Result<Vec<std::net::TcpListener>> {
+    let fds = take(Type::STREAM, name, |sock| {
+        sock.local_addr().is_ok_and(|x| x.as_socket() == Some(*laddr))
+    });
+    fds.into_iter()
+        .map(|fd| {
+            let lis = std::net::TcpListener::from(fd);
+            lis.set_nonblocking(true)?;
+            Ok(lis)
+        })
+        .collect()
+}
+
+pub(crate) fn take_udp(laddr: &SocketAddr, name: Option<&str>) -> Result<Vec<std::net::UdpSocket>> {
+    let fds = take(Type::DGRAM, name, |sock| {
+        sock.local_addr().is_ok_and(|x| x.as_socket() == Some(*laddr))
+    });
+    fds.into_iter()
+        .map(|fd| {
+            let lis = std::net::UdpSocket::from(fd);
+            lis.set_nonblocking(true)?;
+            Ok(lis)
+        })
+        .collect()
+}
+
+pub(crate) fn take_unix(laddr: &UnixAddr, name: Option<&str>) -> Result<Option<std::os::unix::net::UnixListener>> {
+    let fds = take(Type::STREAM, name, |sock| {
+        sock.local_addr()
+            .is_ok_and(|x| x.as_unix().is_some_and(|x| unix_eq(&x, laddr)))
+    });
+    match fds.into_iter().next() {
+        Some(fd) => {
+            let lis = std::os::unix::net::UnixListener::from(fd);
+            lis.set_nonblocking(true)?;
+            Ok(Some(lis))
+        }
+        None => Ok(None),
+    }
+}
+
+fn unix_eq(addr: &std::os::unix::net::SocketAddr, laddr: &UnixAddr) -> bool {
+    match laddr {
+        UnixAddr::Path(path) => addr.as_pathname() == Some(path.as_path()),
+        #[cfg(target_os = "linux")]
+        UnixAddr::Abstract(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            addr.as_abstract_name() == Some(name.as_bytes())
+        }
+        #[cfg(not(target_os = "linux"))]
+        UnixAddr::Abstract(_) => false,
+    }
+}
+
+/// Notify systemd of a state change, log if it fails.
+pub fn notify(state: &str) {
+    match realm_syscall::sd_notify(state) {
+        Ok(true) => log::debug!("[systemd]notify {}", state.replace('\n', ", ")),
+        Ok(false) => {}
+        Err(e) => log::warn!("[systemd]failed to notify {}: {}", state, e),
+    }
+}
+
+/// Expect `n` relays to start listening, then notify systemd with `READY=1`.
+pub fn expect_listening(n: usize) {
+    PENDING.store(n, Ordering::SeqCst);
+    if n == 0 {
+        ready();
+    }
+}
+
+/// Called once a relay starts listening.
+pub(crate) fn listening() {
+    let last = PENDING
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |x| x.checked_sub(1))
+        .is_ok_and(|x| x == 1);
+    if last {
+        ready();
+    }
+}
+
+fn ready() {
+    let unused = INHERITED.lock().unwrap().len();
+    if unused != 0 {
+        log::warn!("[systemd]{} inherited listeners are not taken by any endpoint", unused);
+    }
+    notify("READY=1");
+    crate::upgrade::notify_ready();
+}
+
+/// Notify systemd that the service is shutting down, with `STOPPING=1`.
+pub fn notify_stopping() {
+    notify("STOPPING=1");
+}
+
+/// Send `WATCHDOG=1` at half of the interval required by systemd.
+///
+/// Returns immediately if the watchdog is not enabled.
+pub async fn watchdog() {
+    let interval = match realm_syscall::watchdog_interval() {
+        Some(x) => x / 2,
+        None => return,
+    };
+    log::info!("[systemd]watchdog enabled, ping every {:?}", interval);
+
+    let mut timer = tokio::time::interval(interval);
+    loop {
+        timer.tick().await;
+        notify("WATCHDOG=1");
+    }
+}
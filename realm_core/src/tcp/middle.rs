@@ -1,5 +1,14 @@
 use std::io::Result;
+use std::fmt::Display;
+use std::time::Duration;
+
 use tokio::net::TcpStream;
+use realm_io::{CopyOpts, Expired};
+
+#[cfg(unix)]
+use tokio::net::UnixStream;
+#[cfg(unix)]
+use crate::endpoint::UnixAddr;
 
 use super::socket;
 use super::plain;
@@ -33,6 +42,7 @@ pub async fn connect_and_relay(
         balancer,
 
         tcp_keepalive,
+        spoof_source,
         ..
     } = conn_opts.as_ref();
 
@@ -74,36 +84,139 @@ pub async fn connect_and_relay(
     };
 
     // connect!
-    let mut remote = socket::connect(raddr, conn_opts.as_ref()).await?;
-    log::info!("[tcp]{} => {} as {}", local.peer_addr()?, raddr, remote.peer_addr()?);
+    let src = local.peer_addr()?;
+
+    #[cfg(feature = "proxy")]
+    proxy::check_peer(src, proxy_opts)?;
+
+    #[cfg(unix)]
+    if let RemoteAddr::Unix(addr) = raddr {
+        let mut remote = socket::connect_unix(addr, conn_opts.as_ref()).await?;
+        log::info!("[tcp]{} => {}", src, raddr);
+
+        #[cfg(feature = "proxy")]
+        if proxy_opts.enabled() {
+            proxy::handle_proxy(&mut local, &mut remote, proxy_opts).await?;
+        }
+
+        let res = plain::run_relay(local, remote, copy_opts(conn_opts.as_ref())).await;
+        finish(res, src, raddr);
+        return Ok(());
+    }
+
+    let mut remote = socket::connect_as(raddr, conn_opts.as_ref(), spoof_source.then_some(src)).await?;
+    log::info!("[tcp]{} => {} as {}", src, raddr, remote.peer_addr()?);
 
     // after connected
     // ..
     #[cfg(feature = "proxy")]
-    if proxy_opts.enabled() {
-        proxy::handle_proxy(&mut local, &mut remote, *proxy_opts).await?;
-    }
+    let proxy_header = match proxy_opts.enabled() {
+        true => proxy::accept_proxy(&mut local, proxy_opts).await?,
+        false => None,
+    };
+
+    // tls details are known after the incoming handshake,
+    // then the header is sent by transport
+    #[cfg(feature = "proxy")]
+    #[cfg_attr(not(feature = "transport"), allow(unused))]
+    let proxy_header: Option<proxy::ProxyHeader> = match proxy_header {
+        #[cfg(feature = "transport")]
+        Some(header) if transport.is_some() && proxy_opts.send_proxy_tlvs.need_tls() => Some(header),
+        Some(header) => {
+            header.send(&mut remote, None).await?;
+            None
+        }
+        None => None,
+    };
 
     // relay
+    let copy_opts = copy_opts(conn_opts.as_ref());
     let res = {
         #[cfg(feature = "transport")]
         {
-            if let Some((ac, cc)) = transport {
-                transport::run_relay(local, remote, ac, cc).await
-            } else {
-                plain::run_relay(local, remote).await
+            match transport {
+                #[cfg(feature = "proxy")]
+                Some((ac, cc)) if proxy_header.is_some() => {
+                    let header = proxy_header.unwrap();
+                    transport::run_relay_with_proxy(local, remote, ac, cc, header, copy_opts).await
+                }
+                Some((ac, cc)) => transport::run_relay(local, remote, ac, cc, copy_opts).await,
+                None => plain::run_relay(local, remote, copy_opts).await,
             }
         }
         #[cfg(not(feature = "transport"))]
         {
-            plain::run_relay(local, remote).await
+            plain::run_relay(local, remote, copy_opts).await
         }
     };
 
-    // ignore relay error
-    if let Err(e) = res {
-        log::debug!("[tcp]forward error: {}, ignored", e);
-    }
+    finish(res, src, raddr);
+    Ok(())
+}
 
+/// Relay a connection accepted from a unix socket listener,
+/// hooks and the client address are not available.
+#[cfg(unix)]
+pub async fn connect_and_relay_unix(
+    local: UnixStream,
+    laddr: Ref<UnixAddr>,
+    raddr: Ref<RemoteAddr>,
+    conn_opts: Ref<ConnectOpts>,
+    #[cfg(feature = "balance")] extra_raddrs: Ref<Vec<RemoteAddr>>,
+) -> Result<()> {
+    let raddr = {
+        // iphash is rejected at startup, round robin ignores the address
+        #[cfg(feature = "balance")]
+        {
+            use std::net::{IpAddr, Ipv4Addr};
+            use realm_lb::{Token, BalanceCtx};
+            let token = conn_opts.balancer.next(BalanceCtx {
+                src_ip: &IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            });
+            log::debug!("[tcp]select remote peer, token: {:?}", token);
+            match token {
+                None | Some(Token(0)) => raddr.as_ref(),
+                Some(Token(idx)) => &extra_raddrs.as_ref()[idx as usize - 1],
+            }
+        }
+
+        #[cfg(not(feature = "balance"))]
+        raddr.as_ref()
+    };
+
+    let src = laddr.as_ref();
+    let copy_opts = copy_opts(conn_opts.as_ref());
+    let res = match raddr {
+        RemoteAddr::Unix(addr) => {
+            let remote = socket::connect_unix(addr, conn_opts.as_ref()).await?;
+            log::info!("[tcp]{} => {}", src, raddr);
+            plain::run_relay(local, remote, copy_opts).await
+        }
+        _ => {
+            let remote = socket::connect(raddr, conn_opts.as_ref()).await?;
+            log::info!("[tcp]{} => {} as {}", src, raddr, remote.peer_addr()?);
+            plain::run_relay(local, remote, copy_opts).await
+        }
+    };
+
+    finish(res, src, raddr);
     Ok(())
 }
+
+fn copy_opts(conn_opts: &ConnectOpts) -> CopyOpts {
+    let secs = |x: usize| (x != 0).then(|| Duration::from_secs(x as u64));
+    CopyOpts {
+        shutdown: conn_opts.tcp_shutdown,
+        idle_timeout: secs(conn_opts.tcp_idle_timeout),
+        max_lifetime: secs(conn_opts.tcp_max_lifetime),
+    }
+}
+
+// ignore relay error
+fn finish(res: Result<()>, src: impl Display, raddr: &RemoteAddr) {
+    match res {
+        Err(e) if Expired::of(&e).is_some() => log::info!("[tcp]{} => {}, closed: {}", src, raddr, e),
+        Err(e) => log::debug!("[tcp]forward error: {}, ignored", e),
+        Ok(..) => {}
+    }
+}
@@ -1,6 +1,6 @@
 //! TCP relay entrance.
 
-mod socket;
+pub(crate) mod socket;
 mod middle;
 mod plain;
 
@@ -13,13 +13,27 @@ mod proxy;
 #[cfg(feature = "transport")]
 mod transport;
 
-use std::io::{ErrorKind, Result};
+use std::io::{Error, ErrorKind, Result};
+use std::net::SocketAddr;
+use std::sync::Arc;
 
-use crate::trick::Ref;
-use crate::endpoint::Endpoint;
+use ipnet::IpNet;
+use tokio::net::{TcpStream, TcpListener};
+use tokio::task::JoinSet;
+
+use crate::trick::{Ref, Owner, KeepAlive};
+use crate::endpoint::{Endpoint, LocalAddr, RemoteAddr, BindOpts, ConnectOpts};
+use crate::upgrade::{Active, or_drain};
+use socket::{AcceptBackoff, is_out_of_resources};
+use socket::keepalive::{SockRef, TcpKeepalive};
 
 use middle::connect_and_relay;
 
+#[cfg(unix)]
+use crate::endpoint::UnixAddr;
+#[cfg(unix)]
+use middle::connect_and_relay_unix;
+
 /// Launch a tcp relay.
 pub async fn run_tcp(endpoint: Endpoint) -> Result<()> {
     let Endpoint {
@@ -30,20 +44,114 @@ pub async fn run_tcp(endpoint: Endpoint) -> Result<()> {
         extra_raddrs,
     } = endpoint;
 
-    let raddr = Ref::new(&raddr);
-    let conn_opts = Ref::new(&conn_opts);
-    let extra_raddrs = Ref::new(&extra_raddrs);
+    check_unix(
+        &laddr,
+        &raddr,
+        #[cfg(feature = "transport")]
+        &extra_raddrs,
+        #[cfg(any(feature = "transport", feature = "proxy", feature = "balance"))]
+        &conn_opts,
+    );
+
+    let laddr = match laddr {
+        LocalAddr::SocketAddr(addr) => addr,
+        #[cfg(unix)]
+        LocalAddr::Unix(addr) => return run_unix(addr, raddr, bind_opts, conn_opts, extra_raddrs).await,
+        #[cfg(not(unix))]
+        LocalAddr::Unix(addr) => panic!("[tcp]unix socket {} is not supported", addr),
+    };
+
+    let n = socket::listeners(bind_opts.tcp_listeners);
+    let listeners =
+        socket::bind_many(&laddr, &bind_opts, n).unwrap_or_else(|e| panic!("[tcp]failed to bind {}: {}", &laddr, e));
+    log::debug!("[tcp]{} listeners on {}", listeners.len(), &laddr);
+
+    #[cfg(unix)]
+    crate::systemd::listening();
 
-    let lis = socket::bind(&laddr, bind_opts).unwrap_or_else(|e| panic!("[tcp]failed to bind {}: {}", &laddr, e));
     let keepalive = socket::keepalive::build(&conn_opts);
+    let shared = Arc::new(Shared {
+        raddr,
+        conn_opts,
+        extra_raddrs,
+        local: keepalive,
+    });
+
+    // workers are aborted once this future is dropped
+    let mut workers = JoinSet::new();
+    for lis in listeners {
+        workers.spawn(accept_and_relay(lis, bind_opts.transparent, shared.clone()));
+    }
+
+    let mut ret = Ok(());
+    while let Some(res) = workers.join_next().await {
+        if let Err(e) = res.map_err(Error::other).and_then(|x| x) {
+            ret = ret.and(Err(e));
+        }
+    }
+    ret
+}
+
+/// State of a tcp relay, owned by all of its tasks.
+struct Shared<T> {
+    raddr: RemoteAddr,
+    conn_opts: ConnectOpts,
+    extra_raddrs: Vec<RemoteAddr>,
+    /// keepalive of inet listeners, or address of a unix listener
+    local: T,
+}
+
+/// Bind listeners of a tcp relay before the runtime starts,
+/// which are taken by [`run_tcp`] later.
+///
+/// This is useful when privileges are dropped after binding.
+#[cfg(unix)]
+pub fn prebind(endpoint: &Endpoint) -> Result<()> {
+    let Endpoint { laddr, bind_opts, .. } = endpoint;
+    let name = &bind_opts.fd_name;
+
+    match laddr {
+        LocalAddr::SocketAddr(addr) => {
+            let n = socket::listeners(bind_opts.tcp_listeners);
+            for lis in socket::bind_many_std(addr, bind_opts, n)? {
+                crate::systemd::inherit(lis.into(), name.clone());
+            }
+        }
+        LocalAddr::Unix(addr) => {
+            let lis = socket::bind_unix_std(addr, bind_opts)?;
+            crate::systemd::inherit(lis.into(), name.clone());
+        }
+    }
+    Ok(())
+}
+
+async fn accept_and_relay(
+    lis: TcpListener,
+    transparent: bool,
+    shared: Arc<Shared<Option<TcpKeepalive>>>,
+) -> Result<()> {
+    let laddr = lis.local_addr()?;
+    let raddr = Ref::new(&shared.raddr);
+    let conn_opts = Ref::new(&shared.conn_opts);
+    let extra_raddrs = Ref::new(&shared.extra_raddrs);
+    let owner: Owner = shared.clone();
+    let mut backoff = AcceptBackoff::new();
 
     loop {
-        let (local, addr) = match lis.accept().await {
-            Ok(x) => x,
+        let (local, addr) = match or_drain(lis.accept()).await {
+            Ok(x) => {
+                backoff.reset();
+                x
+            }
             Err(e) if e.kind() == ErrorKind::ConnectionAborted => {
                 log::warn!("[tcp]failed to accept: {}", e);
                 continue;
             }
+            Err(e) if is_out_of_resources(&e) => {
+                log::warn!("[tcp]failed to accept: {}, retry in {:?}", e, backoff.delay());
+                backoff.wait().await;
+                continue;
+            }
             Err(e) => {
                 log::error!("[tcp]failed to accept: {}", e);
                 break;
@@ -53,18 +161,185 @@ pub async fn run_tcp(endpoint: Endpoint) -> Result<()> {
         // ignore error
         let _ = local.set_nodelay(true);
         // set tcp_keepalive
-        if let Some(kpa) = &keepalive {
-            use socket::keepalive::SockRef;
+        if let Some(kpa) = shared.local.as_ref() {
             SockRef::from(&local).set_tcp_keepalive(kpa)?;
         }
 
-        tokio::spawn(async move {
+        let active = Active::new();
+        tokio::spawn(KeepAlive::new(&owner, async move {
+            let _active = active;
+            // decided by each connection
+            let dst;
+            let raddr = match raddr.as_ref() {
+                RemoteAddr::OriginalDst => match original_dst(&local, &laddr, transparent, &conn_opts) {
+                    Ok(x) => {
+                        dst = RemoteAddr::SocketAddr(x);
+                        Ref::new(&dst)
+                    }
+                    Err(e) => {
+                        log::error!("[tcp]{} => {}, error: {}", addr, raddr.as_ref(), e);
+                        return;
+                    }
+                },
+                _ => raddr,
+            };
+
             match connect_and_relay(local, raddr, conn_opts, extra_raddrs).await {
                 Ok(..) => log::debug!("[tcp]{} => {}, finish", addr, raddr.as_ref()),
                 Err(e) => log::error!("[tcp]{} => {}, error: {}", addr, raddr.as_ref(), e),
             }
-        });
+        }));
+    }
+
+    Ok(())
+}
+
+/// Features which rely on an inet socket are not available with unix sockets.
+fn check_unix(
+    laddr: &LocalAddr,
+    raddr: &RemoteAddr,
+    #[cfg(feature = "transport")] extra_raddrs: &[RemoteAddr],
+    #[cfg(any(feature = "transport", feature = "proxy", feature = "balance"))] conn_opts: &ConnectOpts,
+) {
+    let unix_listen = matches!(laddr, LocalAddr::Unix(_));
+
+    if unix_listen && *raddr == RemoteAddr::OriginalDst {
+        panic!("[tcp]original destination of {} is not supported", laddr);
+    }
+
+    #[cfg(feature = "transport")]
+    {
+        let unix_remote = std::iter::once(raddr)
+            .chain(extra_raddrs)
+            .any(|x| matches!(x, RemoteAddr::Unix(_)));
+        if (unix_listen || unix_remote) && conn_opts.transport.is_some() {
+            panic!("[tcp]transport of {} => {} is not supported", laddr, raddr);
+        }
+    }
+
+    #[cfg(feature = "proxy")]
+    if unix_listen && conn_opts.proxy_opts.enabled() {
+        panic!("[tcp]proxy protocol of {} is not supported", laddr);
+    }
+
+    // the hook peeks the client address
+    #[cfg(feature = "hook")]
+    if unix_listen && crate::hook::pre_conn::is_loaded() {
+        panic!("[tcp]pre-connect hook of {} is not supported", laddr);
+    }
+
+    // there is no client ip to hash
+    #[cfg(feature = "balance")]
+    if unix_listen && matches!(conn_opts.balancer, realm_lb::Balancer::IpHash(_)) {
+        panic!("[tcp]iphash balance of {} is not supported", laddr);
+    }
+}
+
+/// Accept connections from a unix socket listener.
+#[cfg(unix)]
+async fn run_unix(
+    laddr: UnixAddr,
+    raddr: RemoteAddr,
+    bind_opts: BindOpts,
+    conn_opts: ConnectOpts,
+    extra_raddrs: Vec<RemoteAddr>,
+) -> Result<()> {
+    let lis = socket::bind_unix(&laddr, &bind_opts).unwrap_or_else(|e| panic!("[tcp]failed to bind {}: {}", &laddr, e));
+    crate::systemd::listening();
+
+    let shared = Arc::new(Shared {
+        raddr,
+        conn_opts,
+        extra_raddrs,
+        local: laddr,
+    });
+    let laddr = Ref::new(&shared.local);
+    let raddr = Ref::new(&shared.raddr);
+    let conn_opts = Ref::new(&shared.conn_opts);
+    #[cfg(feature = "balance")]
+    let extra_raddrs = Ref::new(&shared.extra_raddrs);
+    let owner: Owner = shared.clone();
+    let mut backoff = AcceptBackoff::new();
+
+    loop {
+        let local = match or_drain(lis.accept()).await {
+            Ok((x, _)) => {
+                backoff.reset();
+                x
+            }
+            Err(e) if is_out_of_resources(&e) => {
+                log::warn!("[tcp]failed to accept: {}, retry in {:?}", e, backoff.delay());
+                backoff.wait().await;
+                continue;
+            }
+            Err(e) => {
+                log::error!("[tcp]failed to accept: {}", e);
+                break;
+            }
+        };
+
+        let active = Active::new();
+        tokio::spawn(KeepAlive::new(&owner, async move {
+            let _active = active;
+            let relay = connect_and_relay_unix(
+                local,
+                laddr,
+                raddr,
+                conn_opts,
+                #[cfg(feature = "balance")]
+                extra_raddrs,
+            );
+            match relay.await {
+                Ok(..) => log::debug!("[tcp]{} => {}, finish", laddr.as_ref(), raddr.as_ref()),
+                Err(e) => log::error!("[tcp]{} => {}, error: {}", laddr.as_ref(), raddr.as_ref(), e),
+            }
+        }));
     }
 
     Ok(())
 }
+
+fn original_dst(
+    local: &TcpStream,
+    laddr: &SocketAddr,
+    transparent: bool,
+    conn_opts: &ConnectOpts,
+) -> Result<SocketAddr> {
+    let dst = socket::original_dst(local, laddr, transparent)?;
+    check_allowed(&dst, &conn_opts.original_dst_allow)?;
+    Ok(dst)
+}
+
+/// An empty list allows any destination.
+fn check_allowed(dst: &SocketAddr, allow: &[IpNet]) -> Result<()> {
+    if !allow.is_empty() && !allow.iter().any(|x| x.contains(&dst.ip().to_canonical())) {
+        return Err(Error::new(
+            ErrorKind::PermissionDenied,
+            format!("{} is not allowed", dst),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn original_dst_allow() {
+        let allow: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap(), "2001:db8::/32".parse().unwrap()];
+        let check = |dst: &str, allow: &[IpNet]| check_allowed(&dst.parse().unwrap(), allow).map_err(|e| e.kind());
+
+        // empty list allows anything
+        assert_eq!(check("192.0.2.1:80", &[]), Ok(()));
+
+        assert_eq!(check("10.1.2.3:80", &allow), Ok(()));
+        assert_eq!(check("[2001:db8::1]:80", &allow), Ok(()));
+        assert_eq!(check("192.0.2.1:80", &allow), Err(ErrorKind::PermissionDenied));
+        assert_eq!(check("[2001:db9::1]:80", &allow), Err(ErrorKind::PermissionDenied));
+
+        // mapped addresses are matched as ipv4
+        assert_eq!(check("[::ffff:10.1.2.3]:80", &allow), Ok(()));
+        assert_eq!(check("[::ffff:192.0.2.1]:80", &allow), Err(ErrorKind::PermissionDenied));
+    }
+}
@@ -1,15 +1,38 @@
 use std::io::Result;
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use realm_io::CopyOpts;
+
+#[cfg(target_os = "linux")]
+use realm_io::AsyncRawIO;
+
+/// Streams which could be relayed with zero copy, e.g. tcp or unix streams.
+#[cfg(target_os = "linux")]
+pub trait RelayStream: AsyncRead + AsyncWrite + AsyncRawIO + Unpin {}
+
+#[cfg(target_os = "linux")]
+impl<T: AsyncRead + AsyncWrite + AsyncRawIO + Unpin> RelayStream for T {}
+
+/// Streams which could be relayed, e.g. tcp or unix streams.
+#[cfg(not(target_os = "linux"))]
+pub trait RelayStream: AsyncRead + AsyncWrite + Unpin {}
+
+#[cfg(not(target_os = "linux"))]
+impl<T: AsyncRead + AsyncWrite + Unpin> RelayStream for T {}
 
 #[inline]
-pub async fn run_relay(mut local: TcpStream, mut remote: TcpStream) -> Result<()> {
+pub async fn run_relay<A, B>(mut local: A, mut remote: B, opts: CopyOpts) -> Result<()>
+where
+    A: RelayStream,
+    B: RelayStream,
+{
     #[cfg(target_os = "linux")]
     {
         use std::io::ErrorKind;
-        match realm_io::bidi_zero_copy(&mut local, &mut remote).await {
+        match realm_io::bidi_zero_copy(&mut local, &mut remote, opts).await {
             Ok(_) => Ok(()),
             Err(ref e) if e.kind() == ErrorKind::InvalidInput => {
-                realm_io::bidi_copy(&mut local, &mut remote).await.map(|_| ())
+                realm_io::bidi_copy(&mut local, &mut remote, opts).await.map(|_| ())
             }
             Err(e) => Err(e),
         }
@@ -17,6 +40,6 @@ pub async fn run_relay(mut local: TcpStream, mut remote: TcpStream) -> Result<()
 
     #[cfg(not(target_os = "linux"))]
     {
-        realm_io::bidi_copy(&mut local, &mut remote).await.map(|_| ())
+        realm_io::bidi_copy(&mut local, &mut remote, opts).await.map(|_| ())
     }
 }
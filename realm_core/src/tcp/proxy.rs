@@ -1,248 +1,357 @@
-use std::io::{Error, Result};
+use std::io::{Error, ErrorKind, Result};
 use std::mem::MaybeUninit;
-use std::net::{SocketAddr, IpAddr, Ipv4Addr, Ipv6Addr};
+use std::net::SocketAddr;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use log::{info, debug};
-use bytes::{BytesMut, Buf};
+use log::{info, warn, debug};
+use once_cell::sync::Lazy;
 
-use proxy_protocol::ProxyHeader;
-use proxy_protocol::{version1 as v1, version2 as v2};
-use proxy_protocol::{encode, parse};
-
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncWrite, AsyncReadExt, AsyncWriteExt, Interest};
 use tokio::net::TcpStream;
 
-use crate::endpoint::ProxyOpts;
+use realm_syscall::socket2::SockRef;
+
+use crate::endpoint::{ProxyOpts, ProxyTlvs};
+use crate::proxy::{self, v1, v2, Header, Version, Command, Protocol, Addrs, MAX_HEADER_LEN};
+use crate::proxy::v2::{tlv, Tlvs, TlvWriter};
 use crate::time::timeoutfut;
+use super::socket;
+
+/// Header to send to the server.
+pub struct ProxyHeader {
+    version: usize,
+    client_addr: SocketAddr,
+    server_addr: SocketAddr,
+    tlvs: ProxyTlvs,
+    // raw TLVs received from the client
+    fwd_tlvs: [u8; MAX_HEADER_LEN],
+    fwd_len: usize,
+}
 
-// TODO: replace the "proxy-protocol" crate, and then avoid heap allocation.
+/// Details of an incoming tls transport.
+#[cfg_attr(not(feature = "transport"), allow(dead_code))]
+#[derive(Debug, Default)]
+pub struct TlsInfo<'a> {
+    pub sni: Option<&'a str>,
+    pub alpn: Option<&'a [u8]>,
+    pub version: Option<&'static str>,
+    pub cipher: Option<&'static str>,
+}
 
 // client -> relay -> server
-pub async fn handle_proxy(src: &mut TcpStream, dst: &mut TcpStream, opts: ProxyOpts) -> Result<()> {
+pub async fn handle_proxy<W>(src: &mut TcpStream, dst: &mut W, opts: &ProxyOpts) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    if let Some(header) = accept_proxy(src, opts).await? {
+        header.send(dst, None).await?;
+    }
+    Ok(())
+}
+
+/// Refuse a peer which should send a PROXY header but is not trusted,
+/// unless the header is optional.
+pub fn check_peer(peer: SocketAddr, opts: &ProxyOpts) -> Result<()> {
+    if opts.accept_proxy && !opts.accept_proxy_optional && !opts.trusts(peer.ip()) {
+        return Err(Error::new(
+            ErrorKind::PermissionDenied,
+            format!("proxy-protocol from {} is not trusted", peer),
+        ));
+    }
+    Ok(())
+}
+
+/// Parse the PROXY header from client if required,
+/// returns the header to send if required.
+pub async fn accept_proxy(src: &mut TcpStream, opts: &ProxyOpts) -> Result<Option<ProxyHeader>> {
     let ProxyOpts {
         send_proxy,
         accept_proxy,
         send_proxy_version,
         accept_proxy_timeout,
-    } = opts;
-
-    let mut client_addr = MaybeUninit::<SocketAddr>::uninit();
-    let mut server_addr = MaybeUninit::<SocketAddr>::uninit();
-
-    // buf may not be used
-    let mut buf = MaybeUninit::<BytesMut>::uninit();
-
-    // with src and dst got from header
-    let mut fwd_hdr = false;
+        send_proxy_tlvs,
+        accept_proxy_optional,
+        ..
+    } = *opts;
+
+    let mut buf = [0u8; MAX_HEADER_LEN];
+
+    // src and dst got from header
+    let mut fwd_addrs = None;
+    let mut fwd_len = 0;
+
+    // only trusted peers could send a header, others are
+    // treated as if they do not speak proxy-protocol
+    let peer = src.peer_addr()?;
+    check_peer(peer, opts)?;
+    let accept_proxy = accept_proxy && {
+        let trusted = opts.trusts(peer.ip());
+        if !trusted {
+            debug!("[tcp]proxy-protocol from {} is not trusted, ignore", peer);
+        }
+        trusted
+    };
 
     // parse PROXY header from client and write log
     // may not get src and dst addr
     if accept_proxy {
-        let buf = buf.write(BytesMut::with_capacity(256));
-        buf.resize(256, 0);
-
-        // FIXME: may not read the entire header
-
         // The receiver may apply a short timeout and decide to
         // abort the connection if the protocol header is not seen
         // within a few seconds (at least 3 seconds to cover a TCP retransmit).
-        let peek_n = timeoutfut(src.peek(buf), accept_proxy_timeout).await??;
+        let peeked = timeoutfut(peek_header(src, &mut buf, accept_proxy_optional), accept_proxy_timeout).await;
+        let parsed_n = match peeked {
+            Ok(x) => x?,
+            // the client may wait for the server to speak first
+            Err(_) if accept_proxy_optional => None,
+            Err(e) => return Err(e),
+        };
+
+        match parsed_n {
+            Some(parsed_n) => {
+                debug!("[tcp]proxy-protocol parsed, {} bytes", parsed_n);
+
+                // handle parsed header, and print log
+                if let Some((header, _)) = proxy::parse(&buf[..parsed_n])? {
+                    fwd_addrs = handle_header(&header);
+                    fwd_len = header.tlvs.len();
+                }
+
+                // header has been parsed, remove these bytes from sock buffer.
+                // the same bytes are read again.
+                src.read_exact(&mut buf[..parsed_n]).await?;
+
+                // TLVs are at the end of header
+                buf.copy_within(parsed_n - fwd_len..parsed_n, 0);
+            }
+            None => info!("[tcp]accept proxy-protocol: none from {}", peer),
+        }
+    }
 
-        buf.truncate(peek_n);
-        debug!("[tcp]peek initial {} bytes: {:#x}", peek_n, buf);
+    // do not send header to server
+    if !send_proxy {
+        return Ok(None);
+    }
 
-        let mut slice = buf.as_ref();
+    // use real addr, the original destination if redirected,
+    // or the local address, which is so in transparent mode
+    let (client_addr, server_addr) = match fwd_addrs {
+        Some(x) => x,
+        None => proxy::canonical(peer, socket::client_dst(src)?),
+    };
 
-        // slice is advanced
-        let header = parse(&mut slice).map_err(Error::other)?;
-        let parsed_n = peek_n - slice.remaining();
-        debug!("[tcp]proxy-protocol parsed, {} bytes", parsed_n);
+    Ok(Some(ProxyHeader {
+        version: send_proxy_version,
+        client_addr,
+        server_addr,
+        tlvs: send_proxy_tlvs,
+        fwd_tlvs: buf,
+        fwd_len,
+    }))
+}
 
-        // handle parsed header, and print log
-        if let Some((src, dst)) = handle_header(header) {
-            client_addr.write(src);
-            server_addr.write(dst);
-            fwd_hdr = true;
-        }
+impl ProxyHeader {
+    /// Write header to server, TLVs from tls are skipped if `tls` is none.
+    pub async fn send<W>(&self, dst: &mut W, tls: Option<&TlsInfo<'_>>) -> Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut buf = [0u8; MAX_HEADER_LEN];
+        let n = match self.version {
+            2 => {
+                let mut tlvs = [0u8; v2::MAX_TLVS_LEN];
+                let mut writer = TlvWriter::new(&mut tlvs);
+                self.write_tlvs(&mut writer, tls)?;
+                make_header_v2(&mut buf, self.client_addr, self.server_addr, writer.as_bytes())?
+            }
+            1 => make_header_v1(&mut buf, self.client_addr, self.server_addr)?,
+            _ => unreachable!(),
+        };
+        debug!("[tcp]send initial {} bytes: {:x?}", n, &buf[..n]);
+        dst.write_all(&buf[..n]).await
+    }
 
-        // header has been parsed, remove these bytes from sock buffer.
-        buf.truncate(parsed_n);
-        src.read_exact(buf).await?;
+    fn write_tlvs(&self, writer: &mut TlvWriter, tls: Option<&TlsInfo>) -> Result<()> {
+        let ProxyTlvs {
+            forward,
+            authority,
+            alpn,
+            unique_id,
+            ssl,
+        } = self.tlvs;
+
+        if unique_id {
+            writer.push(tlv::UNIQUE_ID, &make_unique_id())?;
+        }
 
-        // do not send header to server
-        if !send_proxy {
-            return Ok(());
+        if let Some(tls) = tls {
+            if let Some(sni) = tls.sni.filter(|_| authority) {
+                writer.push(tlv::AUTHORITY, sni.as_bytes())?;
+            }
+            if let Some(proto) = tls.alpn.filter(|_| alpn) {
+                writer.push(tlv::ALPN, proto)?;
+            }
+            if ssl {
+                let mut subs: [(u8, &[u8]); 2] = [(0, &[]); 2];
+                let mut n = 0;
+                for (ty, value) in [
+                    (tlv::SUBTYPE_SSL_VERSION, tls.version),
+                    (tlv::SUBTYPE_SSL_CIPHER, tls.cipher),
+                ] {
+                    if let Some(value) = value {
+                        subs[n] = (ty, value.as_bytes());
+                        n += 1;
+                    }
+                }
+                // no client certificate is requested
+                writer.push_ssl(tlv::CLIENT_SSL, 0, &subs[..n])?;
+            }
         }
-    }
 
-    // use real addr
-    if !fwd_hdr {
-        client_addr.write(src.peer_addr()?);
-        // FIXME: what is the dst addr here? seems not defined in the doc
-        // the doc only mentions that this field is similar to X-Origin-To
-        // which is seldom used
-        server_addr.write(match unsafe { client_addr.assume_init_ref() } {
-            SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
-            SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)), 0),
-        });
+        if forward {
+            for (ty, value) in Tlvs::new(&self.fwd_tlvs[..self.fwd_len]) {
+                // the checksum no longer matches, and there is nothing to pad
+                if ty == tlv::CRC32C || ty == tlv::NOOP {
+                    continue;
+                }
+                // prefer ours
+                if Tlvs::new(writer.as_bytes()).any(|(x, _)| x == ty) {
+                    continue;
+                }
+                if writer.push(ty, value).is_err() {
+                    warn!("[tcp]proxy-protocol-v2 tlv {:#04x} is too long to forward, drop", ty);
+                }
+            }
+        }
+        Ok(())
     }
+}
 
-    // Safety: sockaddr is always initialized
-    // either parse from PROXY header or use real addr
-    let client_addr = unsafe { client_addr.assume_init() };
-    let server_addr = unsafe { server_addr.assume_init() };
-
-    // write header
-    let header = encode(make_header(client_addr, server_addr, send_proxy_version)).map_err(Error::other)?;
-    debug!("[tcp]send initial {} bytes: {:#x}", header.len(), &header);
-    dst.write_all(&header).await?;
+/// A unique id of each connection, which is made up of
+/// a random prefix of this process and a counter.
+fn make_unique_id() -> [u8; 16] {
+    static PREFIX: Lazy<u64> = Lazy::new(|| RandomState::new().build_hasher().finish());
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
 
-    Ok(())
+    let mut id = [0u8; 16];
+    id[..8].copy_from_slice(&PREFIX.to_be_bytes());
+    id[8..].copy_from_slice(&COUNTER.fetch_add(1, Ordering::Relaxed).to_be_bytes());
+    id
 }
 
-macro_rules! unpack {
-    ($addr: expr, sin4) => {
-        match $addr {
-            SocketAddr::V4(x) => x,
-            _ => unreachable!(),
+/// Peek until a complete header is received, returns its length.
+///
+/// Nothing is consumed. If the header is optional, returns none
+/// once the received bytes could not be a header.
+async fn peek_header(src: &TcpStream, buf: &mut [u8], optional: bool) -> Result<Option<usize>> {
+    let mut peek_n = 0;
+    loop {
+        peek_n = peek_more(src, buf, peek_n).await?;
+        debug!("[tcp]peek initial {} bytes", peek_n);
+
+        if optional && !proxy::maybe_header(&buf[..peek_n]) {
+            return Ok(None);
         }
-    };
-    ($addr: expr, sin6) => {
-        match $addr {
-            SocketAddr::V6(x) => x,
-            _ => unreachable!(),
+        if let Some((_, n)) = proxy::parse(&buf[..peek_n])? {
+            return Ok(Some(n));
         }
-    };
-}
-
-fn make_header(client_addr: SocketAddr, server_addr: SocketAddr, send_proxy_version: usize) -> ProxyHeader {
-    match send_proxy_version {
-        2 => make_header_v2(client_addr, server_addr),
-        1 => make_header_v1(client_addr, server_addr),
-        _ => unreachable!(),
     }
 }
 
-fn make_header_v1(client_addr: SocketAddr, server_addr: SocketAddr) -> ProxyHeader {
-    debug!("[tcp]send proxy-protocol-v1: {} => {}", &client_addr, &server_addr);
+/// Wait until more than `n` bytes could be peeked.
+async fn peek_more(src: &TcpStream, buf: &mut [u8], n: usize) -> Result<usize> {
+    // Safety: u8 and MaybeUninit<u8> share the same layout,
+    // and recv never de-initializes the buffer
+    let uninit = unsafe { &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>]) };
+
+    loop {
+        src.readable().await?;
+
+        // readiness is cleared if nothing new arrives, so that
+        // the next readable() waits for more bytes instead of spinning
+        let res = src.try_io(Interest::READABLE, || match SockRef::from(src).peek(uninit)? {
+            0 => Err(Error::new(ErrorKind::UnexpectedEof, "eof before proxy-protocol header")),
+            m if m > n => Ok(m),
+            _ => Err(ErrorKind::WouldBlock.into()),
+        });
 
-    if client_addr.is_ipv4() {
-        ProxyHeader::Version1 {
-            addresses: v1::ProxyAddresses::Ipv4 {
-                source: unpack!(client_addr, sin4),
-                destination: unpack!(server_addr, sin4),
-            },
-        }
-    } else {
-        ProxyHeader::Version1 {
-            addresses: v1::ProxyAddresses::Ipv6 {
-                source: unpack!(client_addr, sin6),
-                destination: unpack!(server_addr, sin6),
-            },
+        match res {
+            Ok(m) => return Ok(m),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => continue,
+            Err(e) => return Err(e),
         }
     }
 }
 
-fn make_header_v2(client_addr: SocketAddr, server_addr: SocketAddr) -> ProxyHeader {
+fn make_header_v2(buf: &mut [u8], client_addr: SocketAddr, server_addr: SocketAddr, tlvs: &[u8]) -> Result<usize> {
     debug!("[tcp]send proxy-protocol-v2: {} => {}", &client_addr, &server_addr);
+    let addrs = Addrs::Inet {
+        src: client_addr,
+        dst: server_addr,
+    };
+    v2::encode(buf, Command::Proxy, Protocol::Stream, &addrs, tlvs)
+}
 
-    ProxyHeader::Version2 {
-        command: v2::ProxyCommand::Proxy,
-        transport_protocol: v2::ProxyTransportProtocol::Stream,
-        addresses: if client_addr.is_ipv4() {
-            v2::ProxyAddresses::Ipv4 {
-                source: unpack!(client_addr, sin4),
-                destination: unpack!(server_addr, sin4),
-            }
-        } else {
-            v2::ProxyAddresses::Ipv6 {
-                source: unpack!(client_addr, sin6),
-                destination: unpack!(server_addr, sin6),
-            }
-        },
-    }
+fn make_header_v1(buf: &mut [u8], client_addr: SocketAddr, server_addr: SocketAddr) -> Result<usize> {
+    debug!("[tcp]send proxy-protocol-v1: {} => {}", &client_addr, &server_addr);
+    v1::encode(buf, client_addr, server_addr)
 }
 
-fn handle_header(header: ProxyHeader) -> Option<(SocketAddr, SocketAddr)> {
-    use ProxyHeader::{Version1, Version2};
-    match header {
-        Version1 { addresses } => handle_header_v1(addresses),
-        Version2 {
-            command,
-            transport_protocol,
-            addresses,
-        } => handle_header_v2(command, transport_protocol, addresses),
-        _ => {
-            info!("[tcp]accept proxy-protocol-v?");
-            None
-        }
+fn handle_header(header: &Header) -> Option<(SocketAddr, SocketAddr)> {
+    match header.version {
+        Version::V1 => handle_header_v1(header),
+        Version::V2 => handle_header_v2(header),
     }
 }
 
-fn handle_header_v1(addr: v1::ProxyAddresses) -> Option<(SocketAddr, SocketAddr)> {
-    use v1::ProxyAddresses::*;
-    match addr {
-        Unknown => {
+fn handle_header_v1(header: &Header) -> Option<(SocketAddr, SocketAddr)> {
+    match header.inet_addrs() {
+        Some((src, dst)) => {
+            info!("[tcp]accept proxy-protocol-v1: {} => {}", &src, &dst);
+            Some((src, dst))
+        }
+        None => {
             info!("[tcp]accept proxy-protocol-v1: unknown");
             None
         }
-        Ipv4 { source, destination } => {
-            info!("[tcp]accept proxy-protocol-v1: {} => {}", &source, &destination);
-            Some((SocketAddr::V4(source), SocketAddr::V4(destination)))
-        }
-        Ipv6 { source, destination } => {
-            info!("[tcp]accept proxy-protocol-v1: {} => {}", &source, &destination);
-            Some((SocketAddr::V6(source), SocketAddr::V6(destination)))
-        }
     }
 }
 
-fn handle_header_v2(
-    cmd: v2::ProxyCommand,
-    proto: v2::ProxyTransportProtocol,
-    addr: v2::ProxyAddresses,
-) -> Option<(SocketAddr, SocketAddr)> {
-    use v2::ProxyCommand as Command;
-    use v2::ProxyAddresses as Address;
-    use v2::ProxyTransportProtocol as Protocol;
-
+fn handle_header_v2(header: &Header) -> Option<(SocketAddr, SocketAddr)> {
     // The connection endpoints are the sender and the receiver.
     // Such connections exist when the proxy sends health-checks to the server.
     // The receiver must accept this connection as valid and must use the
     // real connection endpoints and discard the protocol block including the
     // family which is ignored
-    if let Command::Local = cmd {
+    if let Command::Local = header.command {
         info!("[tcp]accept proxy-protocol-v2: command = LOCAL, ignore");
         return None;
     }
 
     // only get tcp address
-    match proto {
+    match header.protocol {
         Protocol::Stream => {}
         Protocol::Unspec => {
             info!("[tcp]accept proxy-protocol-v2: protocol = UNSPEC, ignore");
             return None;
         }
-        Protocol::Datagram => {
+        Protocol::Dgram => {
             info!("[tcp]accept proxy-protocol-v2: protocol = DGRAM, ignore");
             return None;
         }
     }
 
-    match addr {
-        Address::Ipv4 { source, destination } => {
-            info!("[tcp]accept proxy-protocol-v2: {} => {}", &source, &destination);
-            Some((SocketAddr::V4(source), SocketAddr::V4(destination)))
-        }
-        Address::Ipv6 { source, destination } => {
-            info!("[tcp]accept proxy-protocol-v2: {} => {}", &source, &destination);
-            Some((SocketAddr::V6(source), SocketAddr::V6(destination)))
+    match header.addrs {
+        Addrs::Inet { src, dst } => {
+            info!("[tcp]accept proxy-protocol-v2: {} => {}", &src, &dst);
+            Some((src, dst))
         }
-        Address::Unspec => {
+        Addrs::None => {
             info!("[tcp]accept proxy-protocol-v2: af_family = AF_UNSPEC, ignore");
             None
         }
-        Address::Unix { .. } => {
+        Addrs::Unix { .. } => {
             info!("[tcp]accept proxy-protocol-v2: af_family = AF_UNIX, ignore");
             None
         }
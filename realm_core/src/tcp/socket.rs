@@ -1,5 +1,5 @@
 use std::io::{Result, Error, ErrorKind};
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::time::Duration;
 
 use realm_syscall::socket2::Socket;
@@ -7,7 +7,15 @@ use tokio::net::{TcpSocket, TcpStream, TcpListener};
 
 use crate::dns::resolve_addr;
 use crate::time::timeoutfut;
-use crate::endpoint::{RemoteAddr, BindOpts, ConnectOpts};
+use crate::endpoint::{RemoteAddr, BindOpts, ConnectOpts, DEFAULT_FAST_OPEN_QUEUE};
+
+#[cfg(unix)]
+use tokio::net::{UnixStream, UnixListener};
+#[cfg(unix)]
+use crate::endpoint::UnixAddr;
+
+#[cfg(target_os = "linux")]
+use crate::endpoint::Steering;
 
 fn new_socket(addr: &SocketAddr, mptcp: bool) -> Result<Socket> {
     #[cfg(target_os = "linux")]
@@ -26,39 +34,381 @@ fn new_socket(addr: &SocketAddr, mptcp: bool) -> Result<Socket> {
     }
 }
 
-pub fn bind(laddr: &SocketAddr, bind_opts: BindOpts) -> Result<TcpListener> {
+/// Number of listeners of an endpoint, 0 means one per worker thread.
+///
+/// Multiple listeners rely on `SO_REUSEPORT` to spread the load,
+/// which is only done on Linux.
+pub(crate) fn listeners(n: usize) -> usize {
+    #[cfg(target_os = "linux")]
+    {
+        match n {
+            0 => match tokio::runtime::Handle::try_current() {
+                Ok(handle) => handle.metrics().num_workers(),
+                // bound before the runtime starts
+                #[cfg(feature = "multi-thread")]
+                Err(_) => std::thread::available_parallelism().map_or(1, |x| x.get()),
+                #[cfg(not(feature = "multi-thread"))]
+                Err(_) => 1,
+            },
+            n => n,
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = n;
+        1
+    }
+}
+
+pub fn bind(laddr: &SocketAddr, bind_opts: &BindOpts) -> Result<TcpListener> {
+    let lis = bind_std(laddr, bind_opts)?;
+    #[cfg(unix)]
+    crate::upgrade::register(&lis, bind_opts.fd_name.as_deref());
+    TcpListener::from_std(lis)
+}
+
+/// Bind `n` listeners on the same address, see [`Steering`](crate::endpoint::Steering).
+pub fn bind_many(laddr: &SocketAddr, bind_opts: &BindOpts, n: usize) -> Result<Vec<TcpListener>> {
+    let listeners = bind_many_std(laddr, bind_opts, n)?;
+    #[cfg(unix)]
+    listeners
+        .iter()
+        .for_each(|x| crate::upgrade::register(x, bind_opts.fd_name.as_deref()));
+    listeners.into_iter().map(TcpListener::from_std).collect()
+}
+
+/// Same as [`bind`], without a runtime.
+pub(crate) fn bind_std(laddr: &SocketAddr, bind_opts: &BindOpts) -> Result<std::net::TcpListener> {
+    #[cfg(unix)]
+    if let Some(lis) = crate::systemd::take_tcp(laddr, bind_opts.fd_name.as_deref())?.pop() {
+        log::info!("[tcp]inherit listener on {}", laddr);
+        return Ok(lis);
+    }
+
+    let socket = listen(laddr, bind_opts, false)?;
+    Ok(socket.into())
+}
+
+/// Same as [`bind_many`], without a runtime.
+pub(crate) fn bind_many_std(laddr: &SocketAddr, bind_opts: &BindOpts, n: usize) -> Result<Vec<std::net::TcpListener>> {
+    // systemd decides the number of listeners
+    #[cfg(unix)]
+    {
+        let inherited = crate::systemd::take_tcp(laddr, bind_opts.fd_name.as_deref())?;
+        if !inherited.is_empty() {
+            log::info!("[tcp]inherit {} listeners on {}", inherited.len(), laddr);
+            return Ok(inherited);
+        }
+    }
+
+    let reuse_port = n > 1;
+    let mut laddr = *laddr;
+    let mut sockets = Vec::with_capacity(n);
+
+    for _idx in 0..n.max(1) {
+        let socket = listen(&laddr, bind_opts, reuse_port)?;
+
+        #[cfg(target_os = "linux")]
+        if reuse_port && bind_opts.tcp_steering == Steering::IncomingCpu {
+            socket.set_cpu_affinity(_idx)?;
+        }
+
+        // others should share the port picked by the first one
+        laddr = socket.local_addr()?.as_socket().unwrap();
+        sockets.push(socket);
+    }
+
+    #[cfg(target_os = "linux")]
+    if reuse_port && bind_opts.tcp_steering == Steering::Bpf {
+        realm_syscall::attach_reuseport_cpu_bpf(&sockets[0], sockets.len() as u32)?;
+    }
+
+    Ok(sockets.into_iter().map(|x| x.into()).collect())
+}
+
+fn listen(laddr: &SocketAddr, bind_opts: &BindOpts, reuse_port: bool) -> Result<Socket> {
     let BindOpts {
         accept_mptcp,
+        accept_fast_open,
+        fast_open_queue,
         ipv6_only,
+        transparent,
         bind_interface,
+        tcp_backlog,
+        accept_mark,
+        accept_tos,
+        accept_congestion,
+        ..
     } = bind_opts;
-    let socket = new_socket(laddr, accept_mptcp)?;
+    let socket = new_socket(laddr, *accept_mptcp)?;
 
     // ipv6_only
     if let SocketAddr::V6(_) = laddr {
-        socket.set_only_v6(ipv6_only)?;
+        socket.set_only_v6(*ipv6_only)?;
     }
 
     // bind interface
     #[cfg(target_os = "linux")]
     if let Some(iface) = bind_interface {
-        realm_syscall::bind_to_device(&socket, &iface)?;
+        realm_syscall::bind_to_device(&socket, iface)?;
+    }
+
+    // accept connections redirected by tproxy
+    if *transparent {
+        set_transparent(&socket, laddr)?;
     }
 
+    // inherited by accepted connections
+    set_qos(&socket, laddr, *accept_mark, *accept_tos, accept_congestion.as_deref())?;
+
     // ignore error
     let _ = socket.set_reuse_address(true);
 
+    #[cfg(target_os = "linux")]
+    if reuse_port {
+        socket.set_reuse_port(true)?;
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    let _ = reuse_port;
+
     socket.bind(&(*laddr).into())?;
-    socket.listen(1024)?;
 
-    TcpListener::from_std(socket.into())
+    // an optimization, ignore error
+    if *accept_fast_open {
+        if let Err(e) = set_fast_open(&socket, *fast_open_queue) {
+            log::warn!("[tcp]failed to enable fast open on {}: {}", laddr, e);
+        }
+    }
+
+    socket.listen(backlog(*tcp_backlog))?;
+
+    Ok(socket)
+}
+
+const DEFAULT_BACKLOG: i32 = 1024;
+
+/// Set `TCP_FASTOPEN` on a listener, only available on Linux.
+fn set_fast_open(socket: &Socket, queue: usize) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        let queue = match queue {
+            0 => DEFAULT_FAST_OPEN_QUEUE,
+            n => n,
+        };
+        realm_syscall::set_tcp_fastopen(socket, queue.min(u32::MAX as usize) as u32)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (socket, queue, DEFAULT_FAST_OPEN_QUEUE);
+        Err(Error::new(ErrorKind::Unsupported, "tcp fast open is not supported"))
+    }
+}
+
+/// Set `TCP_FASTOPEN_CONNECT` on an outbound socket, only available on Linux.
+fn set_fast_open_connect(socket: &Socket) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        realm_syscall::set_tcp_fastopen_connect(socket, true)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = socket;
+        Err(Error::new(ErrorKind::Unsupported, "tcp fast open is not supported"))
+    }
+}
+
+#[inline]
+fn backlog(n: usize) -> i32 {
+    match n {
+        0 => DEFAULT_BACKLOG,
+        n => n.min(i32::MAX as usize) as i32,
+    }
+}
+
+/// Delay of the accept loop, after it fails due to lack of resources,
+/// e.g. too many open files.
+///
+/// Retrying immediately would spin the cpu, and it gives existing
+/// connections a chance to finish and release their fds.
+pub(crate) struct AcceptBackoff(Duration);
+
+impl AcceptBackoff {
+    const MIN_DELAY: Duration = Duration::from_millis(5);
+    const MAX_DELAY: Duration = Duration::from_secs(1);
+
+    pub const fn new() -> Self {
+        Self(Self::MIN_DELAY)
+    }
+
+    #[inline]
+    pub fn reset(&mut self) {
+        self.0 = Self::MIN_DELAY;
+    }
+
+    #[inline]
+    pub const fn delay(&self) -> Duration {
+        self.0
+    }
+
+    /// The delay doubles each time until reset.
+    pub async fn wait(&mut self) {
+        tokio::time::sleep(self.0).await;
+        self.0 = (self.0 * 2).min(Self::MAX_DELAY);
+    }
+}
+
+/// Check if an accept error is temporary, e.g. EMFILE, ENFILE, ENOBUFS.
+#[cfg(unix)]
+pub(crate) fn is_out_of_resources(e: &Error) -> bool {
+    use libc::{EMFILE, ENFILE, ENOBUFS, ENOMEM};
+    matches!(e.raw_os_error(), Some(EMFILE | ENFILE | ENOBUFS | ENOMEM))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_out_of_resources(_: &Error) -> bool {
+    false
+}
+
+/// Set `IP_TRANSPARENT` on a socket, only available on Linux.
+pub(crate) fn set_transparent(socket: &Socket, addr: &SocketAddr) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        realm_syscall::set_ip_transparent(socket, addr.is_ipv6(), true)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (socket, addr);
+        Err(Error::new(ErrorKind::Unsupported, "transparent proxy is not supported"))
+    }
+}
+
+/// Set `SO_MARK`, `IP_TOS`(or `IPV6_TCLASS`) and `TCP_CONGESTION` on a socket,
+/// only available on Linux.
+///
+/// A zero mark or tos, or an empty congestion control is not set.
+pub(crate) fn set_qos(socket: &Socket, addr: &SocketAddr, mark: u32, tos: u8, congestion: Option<&str>) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        if mark != 0 {
+            realm_syscall::set_mark(socket, mark)?;
+        }
+        if tos != 0 {
+            realm_syscall::set_tos(socket, addr.is_ipv6(), tos)?;
+        }
+        if let Some(cc) = congestion {
+            realm_syscall::set_tcp_congestion(socket, cc)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (socket, addr);
+        if mark == 0 && tos == 0 && congestion.is_none() {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::Unsupported,
+                "socket mark, tos and congestion control are not supported",
+            ))
+        }
+    }
+}
+
+/// Original destination of a redirected connection.
+///
+/// In transparent mode, it is the local address of the connection.
+/// Otherwise it is looked up with `SO_ORIGINAL_DST`, only available on Linux.
+///
+/// A connection to the listener itself is rejected, or it would loop forever.
+/// In transparent mode, so is a connection to a local address on the listening port.
+pub fn original_dst(stream: &TcpStream, laddr: &SocketAddr, transparent: bool) -> Result<SocketAddr> {
+    let local = stream.local_addr()?;
+
+    let dst = if transparent {
+        local
+    } else {
+        #[cfg(target_os = "linux")]
+        {
+            let ipv6 = matches!(local, SocketAddr::V6(x) if x.ip().to_ipv4_mapped().is_none());
+            realm_syscall::original_dst(stream, ipv6).map_err(|e| match e.raw_os_error() {
+                // not tracked by conntrack
+                Some(libc::ENOENT) => Error::new(ErrorKind::InvalidInput, "not a redirected connection"),
+                _ => e,
+            })?
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "original destination is not supported",
+            ));
+        }
+    };
+
+    if dst == *laddr || (dst == local && !transparent) {
+        return Err(Error::new(ErrorKind::InvalidInput, "not a redirected connection"));
+    }
+
+    // a wildcard listener also takes connections to any local address
+    if transparent && dst.port() == laddr.port() && (laddr.ip().is_unspecified() || is_local_ip(dst.ip())) {
+        return Err(Error::new(ErrorKind::InvalidInput, "not a redirected connection"));
+    }
+    Ok(dst)
+}
+
+/// Destination the client connects to, which is the original destination
+/// of a connection redirected by `REDIRECT`/`DNAT` rules, or the local address.
+#[cfg(feature = "proxy")]
+pub fn client_dst(stream: &TcpStream) -> Result<SocketAddr> {
+    let local = stream.local_addr()?;
+
+    // fails if not tracked by conntrack
+    #[cfg(target_os = "linux")]
+    {
+        let ipv6 = matches!(local, SocketAddr::V6(x) if x.ip().to_ipv4_mapped().is_none());
+        if let Ok(dst) = realm_syscall::original_dst(stream, ipv6) {
+            return Ok(dst);
+        }
+    }
+
+    Ok(local)
+}
+
+/// Whether an address is assigned to this host, which could be bound without `IP_TRANSPARENT`.
+fn is_local_ip(ip: IpAddr) -> bool {
+    ip.is_loopback() || std::net::UdpSocket::bind(SocketAddr::new(ip, 0)).is_ok()
+}
+
+/// Source address of the same family as the destination.
+fn spoofed_ip(src: IpAddr, dst: &SocketAddr) -> Option<IpAddr> {
+    match (src, dst) {
+        (IpAddr::V4(_), SocketAddr::V4(_)) | (IpAddr::V6(_), SocketAddr::V6(_)) => Some(src),
+        (IpAddr::V6(ip), SocketAddr::V4(_)) => ip.to_ipv4_mapped().map(IpAddr::V4),
+        (IpAddr::V4(_), SocketAddr::V6(_)) => None,
+    }
 }
 
 pub async fn connect(raddr: &RemoteAddr, conn_opts: &ConnectOpts) -> Result<TcpStream> {
+    connect_as(raddr, conn_opts, None).await
+}
+
+/// Connect with a spoofed source address in transparent mode, if provided.
+pub async fn connect_as(raddr: &RemoteAddr, conn_opts: &ConnectOpts, src: Option<SocketAddr>) -> Result<TcpStream> {
     let ConnectOpts {
         send_mptcp,
+        send_fast_open,
         connect_timeout,
         bind_address,
+        send_mark,
+        send_tos,
+        send_congestion,
 
         #[cfg(target_os = "linux")]
         bind_interface,
@@ -77,8 +427,21 @@ pub async fn connect(raddr: &RemoteAddr, conn_opts: &ConnectOpts) -> Result<TcpS
         let _ = socket.set_tcp_nodelay(true);
         let _ = socket.set_reuse_address(true);
 
-        if let Some(addr) = *bind_address {
-            socket.bind(&addr.into())?;
+        match src {
+            Some(src) => {
+                let Some(ip) = spoofed_ip(src.ip(), &addr) else {
+                    log::warn!("[tcp]can not connect to {} as {}, try next ip", &addr, src.ip());
+                    continue;
+                };
+                set_transparent(&socket, &addr)?;
+                // an unused port is picked by the kernel
+                socket.bind(&SocketAddr::new(ip, 0).into())?;
+            }
+            None => {
+                if let Some(addr) = *bind_address {
+                    socket.bind(&addr.into())?;
+                }
+            }
         }
 
         #[cfg(target_os = "linux")]
@@ -90,6 +453,15 @@ pub async fn connect(raddr: &RemoteAddr, conn_opts: &ConnectOpts) -> Result<TcpS
             socket.set_tcp_keepalive(kpa)?;
         }
 
+        set_qos(&socket, &addr, *send_mark, *send_tos, send_congestion.as_deref())?;
+
+        // the handshake is delayed until the first write
+        if *send_fast_open {
+            if let Err(e) = set_fast_open_connect(&socket) {
+                log::debug!("[tcp]failed to enable fast open: {}", e);
+            }
+        }
+
         let socket = TcpSocket::from_std_stream(socket.into());
 
         match timeoutfut(socket.connect(addr), *connect_timeout).await {
@@ -108,6 +480,52 @@ pub async fn connect(raddr: &RemoteAddr, conn_opts: &ConnectOpts) -> Result<TcpS
     Err(last_err.unwrap_or_else(|| Error::new(ErrorKind::InvalidInput, "could not connect to any address")))
 }
 
+/// Bind a unix socket listener.
+///
+/// A stale socket file left by a previous process is removed,
+/// unless someone is still listening on it.
+#[cfg(unix)]
+pub fn bind_unix(laddr: &UnixAddr, bind_opts: &BindOpts) -> Result<UnixListener> {
+    let lis = bind_unix_std(laddr, bind_opts)?;
+    crate::upgrade::register(&lis, bind_opts.fd_name.as_deref());
+    UnixListener::from_std(lis)
+}
+
+/// Same as [`bind_unix`], without a runtime.
+#[cfg(unix)]
+pub(crate) fn bind_unix_std(laddr: &UnixAddr, bind_opts: &BindOpts) -> Result<std::os::unix::net::UnixListener> {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if let Some(lis) = crate::systemd::take_unix(laddr, bind_opts.fd_name.as_deref())? {
+        log::info!("[tcp]inherit listener on {}", laddr);
+        return Ok(lis);
+    }
+
+    let addr = laddr.to_socket_addr()?;
+    let lis = match UnixListener::bind_addr(&addr) {
+        Err(e) if e.kind() == ErrorKind::AddrInUse && matches!(laddr, UnixAddr::Path(_)) => {
+            match UnixStream::connect_addr(&addr) {
+                Err(e2) if e2.kind() == ErrorKind::ConnectionRefused => {
+                    log::warn!("[tcp]remove stale socket file {}", laddr);
+                    std::fs::remove_file(laddr.to_path())?;
+                    UnixListener::bind_addr(&addr)
+                }
+                _ => Err(e),
+            }
+        }
+        x => x,
+    }?;
+    lis.set_nonblocking(true)?;
+    Ok(lis)
+}
+
+#[cfg(unix)]
+pub async fn connect_unix(raddr: &UnixAddr, conn_opts: &ConnectOpts) -> Result<UnixStream> {
+    let stream = timeoutfut(UnixStream::connect(raddr.to_path()), conn_opts.connect_timeout).await??;
+    log::debug!("[tcp]connect to {}", raddr);
+    Ok(stream)
+}
+
 pub(super) mod keepalive {
     use super::*;
     pub use realm_syscall::socket2::{SockRef, TcpKeepalive};
@@ -4,12 +4,19 @@ use futures::try_join;
 use kaminari::{AsyncAccept, AsyncConnect, IOStream};
 use kaminari::mix::{MixAccept, MixConnect};
 
-use realm_io::{CopyBuffer, bidi_copy_buf, buf_size};
+#[cfg(feature = "proxy")]
+use tokio::net::TcpStream;
+#[cfg(feature = "proxy")]
+use kaminari::mix::MixServerStream;
+#[cfg(feature = "proxy")]
+use super::proxy::{ProxyHeader, TlsInfo};
 
-pub async fn run_relay<S: IOStream>(src: S, dst: S, ac: &MixAccept, cc: &MixConnect) -> Result<()> {
+use realm_io::{CopyBuffer, CopyOpts, bidi_copy_buf, buf_size};
+
+pub async fn run_relay<S: IOStream>(src: S, dst: S, ac: &MixAccept, cc: &MixConnect, opts: CopyOpts) -> Result<()> {
     macro_rules! hs_relay {
         ($ac: expr, $cc: expr) => {
-            handshake_and_relay(src, dst, $ac, $cc).await
+            handshake_and_relay(src, dst, $ac, $cc, opts).await
         };
     }
 
@@ -42,7 +49,7 @@ pub async fn run_relay<S: IOStream>(src: S, dst: S, ac: &MixAccept, cc: &MixConn
     hs_relay!(ac, cc)
 }
 
-async fn handshake_and_relay<S, AC, CC>(src: S, dst: S, ac: &AC, cc: &CC) -> Result<()>
+async fn handshake_and_relay<S, AC, CC>(src: S, dst: S, ac: &AC, cc: &CC, opts: CopyOpts) -> Result<()>
 where
     S: IOStream,
     AC: AsyncAccept<S>,
@@ -56,5 +63,50 @@ where
     let buf1 = CopyBuffer::new(buf1);
     let buf2 = CopyBuffer::new(buf2);
 
-    bidi_copy_buf(&mut src, &mut dst, buf1, buf2).await.map(|_| ())
+    bidi_copy_buf(&mut src, &mut dst, buf1, buf2, opts).await.map(|_| ())
+}
+
+/// Send the PROXY header after the incoming handshake, which provides tls details.
+///
+/// The outgoing handshake waits for the header.
+#[cfg(feature = "proxy")]
+pub async fn run_relay_with_proxy(
+    src: TcpStream,
+    mut dst: TcpStream,
+    ac: &MixAccept,
+    cc: &MixConnect,
+    header: ProxyHeader,
+    opts: CopyOpts,
+) -> Result<()> {
+    let mut buf1 = vec![0; buf_size()];
+    let mut buf2 = vec![0; buf_size()];
+
+    let mut src = ac.accept(src, &mut buf1).await?;
+    header.send(&mut dst, Some(&tls_info(&src))).await?;
+    let mut dst = cc.connect(dst, &mut buf2).await?;
+
+    let buf1 = CopyBuffer::new(buf1);
+    let buf2 = CopyBuffer::new(buf2);
+
+    bidi_copy_buf(&mut src, &mut dst, buf1, buf2, opts).await.map(|_| ())
+}
+
+#[cfg(feature = "proxy")]
+fn tls_info<S>(stream: &MixServerStream<S>) -> TlsInfo<'_> {
+    let conn = match stream {
+        MixServerStream::Tls(x) => x.get_ref().1,
+        MixServerStream::Wss(x) => x.as_ref().get_ref().1,
+        _ => return TlsInfo::default(),
+    };
+
+    TlsInfo {
+        sni: conn.server_name(),
+        alpn: conn.alpn_protocol(),
+        version: conn.protocol_version().and_then(|x| match u16::from(x) {
+            0x0304 => Some("TLSv1.3"),
+            0x0303 => Some("TLSv1.2"),
+            _ => x.as_str(),
+        }),
+        cipher: conn.negotiated_cipher_suite().and_then(|x| x.suite().as_str()),
+    }
 }
@@ -1,6 +1,13 @@
 //! Unsafe tricks.
 
+use core::any::Any;
 use core::ops::Deref;
+use core::pin::Pin;
+use core::future::Future;
+use core::task::{Poll, Context};
+use std::sync::Arc;
+
+use pin_project::pin_project;
 
 /// A reference wrapper with static lifetime.
 ///
@@ -9,6 +16,8 @@ use core::ops::Deref;
 /// Inner pointer comes from a immutable reference, which is not null.
 ///
 /// Pointee memory must remain valid during the eventloop.
+/// A task which outlives its spawner should hold the owner
+/// of the pointee, see [`KeepAlive`].
 pub struct Ref<T>(*const T);
 
 unsafe impl<T: Send + Sync> Send for Ref<T> {}
@@ -51,3 +60,36 @@ impl<T> Ref<T> {
         Self(x as *const _)
     }
 }
+
+/// Shared owner of the memory pointed by some [`Ref`]s.
+pub type Owner = Arc<dyn Any + Send + Sync>;
+
+/// A future which keeps an [`Owner`] alive until it is dropped.
+///
+/// The inner future is dropped before the owner.
+#[pin_project]
+pub struct KeepAlive<F> {
+    #[pin]
+    fut: F,
+
+    _owner: Owner,
+}
+
+impl<F> KeepAlive<F> {
+    #[inline]
+    pub fn new(owner: &Owner, fut: F) -> Self {
+        Self {
+            fut,
+            _owner: owner.clone(),
+        }
+    }
+}
+
+impl<F: Future> Future for KeepAlive<F> {
+    type Output = F::Output;
+
+    #[inline]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project().fut.poll(cx)
+    }
+}
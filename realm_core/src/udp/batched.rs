@@ -1,10 +1,49 @@
 use std::io::Result;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::net::UdpSocket;
 
 pub const PACKET_SIZE: usize = 1500;
+pub const MAX_PACKET_SIZE: usize = u16::MAX as usize;
 pub const MAX_PACKETS: usize = 128;
 
+/// Buffer size to receive coalesced packets.
+pub const GRO_BUF_SIZE: usize = MAX_PACKET_SIZE + 1;
+
+/// Coalesced buffers received at once, each contains at most 64 packets.
+pub const GRO_SLOTS: usize = MAX_PACKETS / 64;
+
+/// Packet size in use, 0 means the default size.
+#[inline]
+pub const fn packet_size(size: usize) -> usize {
+    match size {
+        0 => PACKET_SIZE,
+        n if n > MAX_PACKET_SIZE => MAX_PACKET_SIZE,
+        n => n,
+    }
+}
+
+/// GSO state of a socket, turned off once the kernel or device rejects it.
+#[derive(Debug)]
+pub struct Gso(AtomicBool);
+
+#[cfg_attr(not(all(target_os = "linux", feature = "batched-udp")), allow(dead_code))]
+impl Gso {
+    pub const fn new(enabled: bool) -> Self {
+        Self(AtomicBool::new(enabled))
+    }
+
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn disable(&self) {
+        self.0.store(false, Ordering::Relaxed)
+    }
+}
+
 #[repr(transparent)]
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct SockAddrStore {
@@ -50,9 +89,11 @@ impl From<SockAddrStore> for SocketAddr {
 
 #[derive(Debug, Clone)]
 pub struct Packet {
-    pub(super) buf: [u8; PACKET_SIZE],
+    // one more byte to detect truncation
+    pub(super) buf: Box<[u8]>,
     pub(super) addr: SockAddrStore,
     pub(super) cursor: u16,
+    pub(super) truncated: bool,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -62,14 +103,25 @@ pub struct PacketRef<'buf, 'addr> {
 }
 
 impl Packet {
-    pub fn new() -> Self {
+    pub fn new(size: usize) -> Self {
         Self {
-            buf: [0u8; PACKET_SIZE],
+            buf: vec![0u8; packet_size(size) + 1].into_boxed_slice(),
             addr: SockAddrStore::new(),
             cursor: 0u16,
+            truncated: false,
         }
     }
 
+    /// Max size of a datagram that could be received.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buf.len() - 1
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.buf[..self.cursor as usize]
+    }
+
     pub fn ref_with_addr<'a>(&self, addr: &'a SockAddrStore) -> PacketRef<'_, 'a> {
         PacketRef {
             buf: &self.buf[..self.cursor as usize],
@@ -78,21 +130,89 @@ impl Packet {
     }
 }
 
+#[cfg(target_os = "linux")]
+#[cfg_attr(not(feature = "proxy"), allow(unused))]
+pub use pktinfo::{enable_dst, dst_enabled, recv_with_dst};
+#[cfg(target_os = "linux")]
+mod pktinfo {
+    use super::*;
+    use std::net::IpAddr;
+    use tokio::io::Interest;
+
+    /// Receive destination addresses, which are unknown to a wildcard listener.
+    pub fn enable_dst(sock: &UdpSocket) -> Result<()> {
+        realm_syscall::set_recv_pktinfo(sock, sock.local_addr()?.is_ipv6(), true)
+    }
+
+    pub fn dst_enabled(sock: &UdpSocket) -> bool {
+        sock.local_addr()
+            .is_ok_and(|x| realm_syscall::recv_pktinfo_enabled(sock, x.is_ipv6()))
+    }
+
+    /// Receive one packet, returns its destination address if enabled.
+    pub async fn recv_with_dst(sock: &UdpSocket, pkt: &mut Packet) -> Result<Option<IpAddr>> {
+        let (bytes, addr, dst) = sock
+            .async_io(Interest::READABLE, || realm_syscall::recv_from_to(sock, &mut pkt.buf))
+            .await?;
+        pkt.addr = addr.into();
+        pkt.truncated = bytes > pkt.capacity();
+        pkt.cursor = bytes.min(pkt.capacity()) as u16;
+        Ok(dst)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+#[cfg_attr(not(feature = "proxy"), allow(unused))]
+pub use pktinfo::{enable_dst, dst_enabled, recv_with_dst};
+#[cfg(not(target_os = "linux"))]
+mod pktinfo {
+    use super::*;
+    use std::io::ErrorKind;
+    use std::net::IpAddr;
+
+    pub fn enable_dst(_: &UdpSocket) -> Result<()> {
+        Err(ErrorKind::Unsupported.into())
+    }
+
+    pub fn dst_enabled(_: &UdpSocket) -> bool {
+        false
+    }
+
+    pub async fn recv_with_dst(sock: &UdpSocket, pkt: &mut Packet) -> Result<Option<IpAddr>> {
+        recv_some(sock, std::slice::from_mut(pkt)).await.map(|_| None)
+    }
+}
+
 #[cfg(not(all(target_os = "linux", feature = "batched-udp")))]
-pub use common::{recv_some, send_all};
+pub use common::{recv_some, recv_coalesced, send_all, enable_gro, gro_enabled};
 #[cfg(not(all(target_os = "linux", feature = "batched-udp")))]
 mod common {
     use super::*;
+    use std::io::ErrorKind;
+
+    pub fn enable_gro(_: &UdpSocket) -> Result<()> {
+        Err(ErrorKind::Unsupported.into())
+    }
+
+    pub fn gro_enabled(_: &UdpSocket) -> bool {
+        false
+    }
+
     pub async fn recv_some(sock: &UdpSocket, pkts: &mut [Packet]) -> Result<usize> {
         debug_assert!(!pkts.is_empty());
         let pkt = &mut pkts[0];
         let (bytes, addr) = sock.recv_from(&mut pkt.buf).await?;
         pkt.addr.inner = addr;
-        pkt.cursor = bytes as u16;
+        pkt.truncated = bytes > pkt.capacity();
+        pkt.cursor = bytes.min(pkt.capacity()) as u16;
         Ok(1)
     }
 
-    pub async fn send_all<'a, 'b, I>(sock: &UdpSocket, pkts: I) -> Result<()>
+    pub async fn recv_coalesced(sock: &UdpSocket, pkts: &mut [Packet], _: &mut [u8]) -> Result<usize> {
+        recv_some(sock, pkts).await
+    }
+
+    pub async fn send_all<'a, 'b, I>(sock: &UdpSocket, pkts: I, _gso: &Gso) -> Result<()>
     where
         I: ExactSizeIterator<Item = PacketRef<'a, 'b>>,
     {
@@ -104,7 +224,7 @@ mod common {
 }
 
 #[cfg(all(target_os = "linux", feature = "batched-udp"))]
-pub use linux::{recv_some, send_all};
+pub use linux::{recv_some, recv_coalesced, send_all, enable_gro, gro_enabled};
 #[cfg(all(target_os = "linux", feature = "batched-udp"))]
 mod linux {
     use super::*;
@@ -112,6 +232,15 @@ mod linux {
     use std::mem::MaybeUninit;
     use realm_io::mmsg::{MmsgHdr, MmsgHdrMut};
     use realm_io::mmsg::{send_mul_pkts, recv_mul_pkts};
+    use realm_io::offload::{self, SegmentCmsg};
+
+    pub fn enable_gro(sock: &UdpSocket) -> Result<()> {
+        offload::set_gro(sock, true)
+    }
+
+    pub fn gro_enabled(sock: &UdpSocket) -> bool {
+        offload::gro_enabled(sock)
+    }
 
     pub async fn recv_some(sock: &UdpSocket, pkts: &mut [Packet]) -> Result<usize> {
         const MAX_PKTS: usize = MAX_PACKETS;
@@ -124,7 +253,8 @@ mod linux {
         let msgs = unsafe { msgs.assume_init_mut() };
 
         for ((pkt, iov), msg) in pkts.iter_mut().zip(iovs.iter_mut()).zip(msgs.iter_mut()) {
-            *iov = IoSliceMut::new(&mut pkt.buf);
+            let cap = pkt.capacity();
+            *iov = IoSliceMut::new(&mut pkt.buf[..cap]);
             *msg = MmsgHdrMut::new()
                 .with_addr(&mut pkt.addr.inner)
                 .with_iovec(std::slice::from_mut(iov))
@@ -132,19 +262,177 @@ mod linux {
 
         let pkt_amt = recv_mul_pkts(sock, &mut msgs[..pkt_amt]).await?;
         {
-            let mut bytes: [u16; MAX_PKTS] = unsafe { std::mem::zeroed() };
+            let mut bytes: [(u16, bool); MAX_PKTS] = unsafe { std::mem::zeroed() };
             for (msg, byte) in msgs.iter().zip(bytes.iter_mut()).take(pkt_amt) {
-                *byte = msg.get_ref().nbytes() as u16
+                let msg = msg.get_ref();
+                *byte = (msg.nbytes() as u16, **msg.flags() & libc::MSG_TRUNC != 0)
             }
 
-            for (pkt, byte) in pkts.iter_mut().zip(bytes).take(pkt_amt) {
-                pkt.cursor = byte
+            for (pkt, (byte, truncated)) in pkts.iter_mut().zip(bytes).take(pkt_amt) {
+                pkt.cursor = byte;
+                pkt.truncated = truncated;
             }
         }
         Ok(pkt_amt)
     }
 
-    pub async fn send_all<'a, 'b, I>(sock: &UdpSocket, pkts: I) -> Result<()>
+    /// Receive coalesced buffers, then split them into packets.
+    pub async fn recv_coalesced(sock: &UdpSocket, pkts: &mut [Packet], buf: &mut [u8]) -> Result<usize> {
+        const SLOTS: usize = GRO_SLOTS;
+        debug_assert!(pkts.len() >= SLOTS * offload::MAX_SEGMENTS);
+        debug_assert!(buf.len() >= SLOTS * GRO_BUF_SIZE);
+
+        let mut addrs: [realm_io::mmsg::SockAddrStore; SLOTS] = Default::default();
+        let mut cmsgs = [SegmentCmsg::new(); SLOTS];
+        let mut iovs: MaybeUninit<[IoSliceMut; SLOTS]> = MaybeUninit::uninit();
+        let mut msgs: MaybeUninit<[MmsgHdrMut; SLOTS]> = MaybeUninit::uninit();
+        let iovs = unsafe { iovs.assume_init_mut() };
+        let msgs = unsafe { msgs.assume_init_mut() };
+
+        let slots = buf.chunks_mut(GRO_BUF_SIZE).zip(addrs.iter_mut()).zip(cmsgs.iter_mut());
+        for (((slot, addr), cmsg), (iov, msg)) in slots.zip(iovs.iter_mut().zip(msgs.iter_mut())) {
+            *iov = IoSliceMut::new(slot);
+            *msg = MmsgHdrMut::new()
+                .with_addr(addr)
+                .with_iovec(std::slice::from_mut(iov))
+                .with_control(cmsg.as_mut_slice());
+        }
+
+        let msg_amt = recv_mul_pkts(sock, &mut msgs[..]).await?;
+        let mut metas = [(0usize, false, None); SLOTS];
+        for (msg, meta) in msgs.iter().zip(metas.iter_mut()).take(msg_amt) {
+            let msg = msg.get_ref();
+            let truncated = **msg.flags() & libc::MSG_TRUNC != 0;
+            *meta = (msg.nbytes() as usize, truncated, offload::gro_segment(msg.control()));
+        }
+
+        let mut pkt_amt = 0;
+        for ((iov, addr), (bytes, truncated, segment)) in iovs.iter().zip(addrs).zip(metas).take(msg_amt) {
+            let pkts = &mut pkts[pkt_amt..];
+            let n = split_segments(&iov[..bytes], segment.unwrap_or(bytes), addr, pkts);
+            if truncated {
+                pkts[n - 1].truncated = true;
+            }
+            pkt_amt += n;
+        }
+        Ok(pkt_amt)
+    }
+
+    fn split_segments(data: &[u8], segment: usize, addr: realm_io::mmsg::SockAddrStore, pkts: &mut [Packet]) -> usize {
+        // empty datagram
+        if data.is_empty() {
+            pkts[0].cursor = 0;
+            pkts[0].truncated = false;
+            pkts[0].addr.inner = addr;
+            return 1;
+        }
+
+        let mut pkt_amt = 0;
+        for (pkt, seg) in pkts.iter_mut().zip(data.chunks(segment.max(1))) {
+            let len = seg.len().min(pkt.capacity());
+            pkt.buf[..len].copy_from_slice(&seg[..len]);
+            pkt.cursor = len as u16;
+            pkt.truncated = seg.len() > len;
+            pkt.addr.inner = addr.clone();
+            pkt_amt += 1;
+        }
+        pkt_amt
+    }
+
+    pub async fn send_all<'a, 'b, I>(sock: &UdpSocket, pkts: I, gso: &Gso) -> Result<()>
+    where
+        I: ExactSizeIterator<Item = PacketRef<'a, 'b>>,
+    {
+        if pkts.len() > 1 && gso.enabled() {
+            send_segments(sock, pkts, gso).await
+        } else {
+            send_each(sock, pkts).await
+        }
+    }
+
+    /// Send packets of the same peer and size in one message with GSO,
+    /// fallback to [`send_each`] if not supported.
+    async fn send_segments<'a, 'b, I>(sock: &UdpSocket, pkts: I, gso: &Gso) -> Result<()>
+    where
+        I: ExactSizeIterator<Item = PacketRef<'a, 'b>>,
+    {
+        const MAX_PKTS: usize = MAX_PACKETS;
+        debug_assert!(pkts.len() <= MAX_PKTS);
+
+        let pkt_amt = pkts.len();
+        let mut refs: MaybeUninit<[PacketRef; MAX_PKTS]> = MaybeUninit::uninit();
+        let mut iovs: MaybeUninit<[IoSlice; MAX_PKTS]> = MaybeUninit::uninit();
+        let mut msgs: MaybeUninit<[MmsgHdr; MAX_PKTS]> = MaybeUninit::uninit();
+        let mut cmsgs = [SegmentCmsg::new(); MAX_PKTS];
+        let mut groups = [(0usize, 0usize); MAX_PKTS];
+        let refs = unsafe { refs.assume_init_mut() };
+        let iovs = unsafe { iovs.assume_init_mut() };
+        let msgs = unsafe { msgs.assume_init_mut() };
+
+        for ((pkt, r), iov) in pkts.zip(refs.iter_mut()).zip(iovs.iter_mut()) {
+            *r = pkt;
+            *iov = IoSlice::new(pkt.buf);
+        }
+
+        // [beg, end), the last one could be shorter
+        let mut group_amt = 0;
+        let mut beg = 0;
+        while beg < pkt_amt {
+            let size = refs[beg].buf.len();
+            let mut total = size;
+            let mut end = beg + 1;
+            while end < pkt_amt
+                && end - beg < offload::MAX_SEGMENTS
+                && refs[end].addr == refs[beg].addr
+                && refs[end].buf.len() <= size
+                && total + refs[end].buf.len() <= offload::MAX_PAYLOAD
+            {
+                total += refs[end].buf.len();
+                end += 1;
+                if refs[end - 1].buf.len() < size {
+                    break;
+                }
+            }
+            groups[group_amt] = (beg, end);
+            group_amt += 1;
+            beg = end;
+        }
+
+        for (((beg, end), cmsg), msg) in groups.iter().zip(cmsgs.iter_mut()).zip(msgs.iter_mut()).take(group_amt) {
+            let hdr = MmsgHdr::new()
+                .with_addr(&refs[*beg].addr.inner)
+                .with_iovec(&iovs[*beg..*end]);
+            *msg = if end - beg > 1 {
+                *cmsg = SegmentCmsg::with_segment(refs[*beg].buf.len() as u16);
+                hdr.with_control(cmsg.as_slice())
+            } else {
+                hdr
+            };
+        }
+
+        let mut cursor = 0;
+        while cursor < group_amt {
+            match send_mul_pkts(sock, &mut msgs[cursor..group_amt]).await {
+                Ok(n) => cursor += n,
+                Err(e) if offload::is_gso_unsupported(&e) && groups[cursor].1 - groups[cursor].0 > 1 => {
+                    gso.disable();
+                    log::warn!("[udp]gso is not supported, fallback: {}", e);
+                    let beg = groups[cursor].0;
+                    return send_each(sock, refs[beg..pkt_amt].iter().copied()).await;
+                }
+                // e.g. the segment size exceeds the mtu, only this batch is affected
+                Err(e) if e.raw_os_error() == Some(libc::EINVAL) && groups[cursor].1 - groups[cursor].0 > 1 => {
+                    log::debug!("[udp]gso is rejected, fallback: {}", e);
+                    let beg = groups[cursor].0;
+                    return send_each(sock, refs[beg..pkt_amt].iter().copied()).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    async fn send_each<'a, 'b, I>(sock: &UdpSocket, pkts: I) -> Result<()>
     where
         I: ExactSizeIterator<Item = PacketRef<'a, 'b>>,
     {
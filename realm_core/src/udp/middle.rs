@@ -1,18 +1,24 @@
 use std::io::Result;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::net::UdpSocket;
 
-use super::SockMap;
+use super::{SockMap, Association, AssocStat};
 use super::{socket, batched};
 
-use crate::trick::Ref;
+use crate::trick::{Ref, Owner, KeepAlive};
 use crate::time::timeoutfut;
-use crate::dns::resolve_addr;
-use crate::endpoint::{RemoteAddr, ConnectOpts};
+use crate::dns::CachedAddr;
+use crate::endpoint::ConnectOpts;
+use crate::upgrade::{Active, or_drain};
 
-use batched::{Packet, SockAddrStore};
-use registry::Registry;
+use batched::{Packet, SockAddrStore, Gso};
+
+#[cfg(feature = "proxy")]
+use super::proxy;
+
+pub(super) use registry::Registry;
 mod registry {
     use super::*;
     type Range = std::ops::Range<u16>;
@@ -21,22 +27,55 @@ mod registry {
         pkts: Box<[Packet]>,
         groups: Vec<Range>,
         cursor: u16,
+        gro: Option<Box<[u8]>>,
+        recv_dst: bool,
+        dst: Option<IpAddr>,
     }
 
     impl Registry {
-        pub fn new(npkts: usize) -> Self {
+        pub fn new(npkts: usize, size: usize) -> Self {
             debug_assert!(npkts <= batched::MAX_PACKETS);
             Self {
-                pkts: vec![Packet::new(); npkts].into_boxed_slice(),
+                pkts: vec![Packet::new(size); npkts].into_boxed_slice(),
                 groups: Vec::with_capacity(npkts),
                 cursor: 0u16,
+                gro: None,
+                recv_dst: false,
+                dst: None,
             }
         }
 
-        pub async fn batched_recv_on(&mut self, sock: &UdpSocket) -> Result<()> {
-            let n = batched::recv_some(sock, &mut self.pkts).await?;
-            self.cursor = n as u16;
-            Ok(())
+        /// Receive coalesced packets if GRO is enabled on the socket.
+        pub fn with_gro(mut self, sock: &UdpSocket) -> Self {
+            if batched::gro_enabled(sock) {
+                self.gro = Some(vec![0u8; batched::GRO_BUF_SIZE * batched::GRO_SLOTS].into_boxed_slice());
+            }
+            self
+        }
+
+        /// Receive packets one by one with their destination addresses,
+        /// if enabled on the socket.
+        pub fn with_dst(mut self, sock: &UdpSocket) -> Self {
+            self.recv_dst = batched::dst_enabled(sock);
+            self
+        }
+
+        /// Receive at least one packet, truncated packets are dropped and counted.
+        pub async fn batched_recv_on(&mut self, sock: &UdpSocket, truncated: &AtomicU64) -> Result<()> {
+            loop {
+                let n = match &mut self.gro {
+                    Some(buf) => batched::recv_coalesced(sock, &mut self.pkts, buf).await?,
+                    None if self.recv_dst => {
+                        self.dst = batched::recv_with_dst(sock, &mut self.pkts[0]).await?;
+                        1
+                    }
+                    None => batched::recv_some(sock, &mut self.pkts).await?,
+                };
+                self.cursor = drop_truncated(&mut self.pkts[..n], truncated) as u16;
+                if self.cursor != 0 {
+                    return Ok(());
+                }
+            }
         }
 
         pub fn group_by_addr(&mut self) {
@@ -59,6 +98,12 @@ mod registry {
         pub const fn count(&self) -> usize {
             self.cursor as usize
         }
+
+        /// Destination of the last packet, see [`Registry::with_dst`].
+        #[cfg_attr(not(feature = "proxy"), allow(unused))]
+        pub const fn dst(&self) -> Option<IpAddr> {
+            self.dst
+        }
     }
 
     use std::slice::Iter;
@@ -78,6 +123,31 @@ mod registry {
         }
     }
 
+    fn drop_truncated(pkts: &mut [Packet], truncated: &AtomicU64) -> usize {
+        let mut n = 0;
+        for i in 0..pkts.len() {
+            if !pkts[i].truncated {
+                pkts.swap(n, i);
+                n += 1;
+                continue;
+            }
+            // anyone could send oversized packets, do not flood the log
+            let total = truncated.fetch_add(1, Ordering::Relaxed) + 1;
+            let level = match total.is_power_of_two() {
+                true => log::Level::Warn,
+                false => log::Level::Debug,
+            };
+            log::log!(
+                level,
+                "[udp]drop truncated packet from {}, larger than {} bytes, total: {}",
+                SocketAddr::from(pkts[i].addr.clone()),
+                pkts[i].capacity(),
+                total
+            );
+        }
+        n
+    }
+
     fn group_by_inner<T, F>(data: &mut [T], groups: &mut Vec<Range>, eq: F)
     where
         F: Fn(&T, &T) -> bool,
@@ -108,46 +178,192 @@ mod registry {
 
 pub async fn associate_and_relay(
     lis: Ref<UdpSocket>,
-    rname: Ref<RemoteAddr>,
+    rname: Ref<CachedAddr>,
     conn_opts: Ref<ConnectOpts>,
-    sockmap: Ref<SockMap>,
+    sockmap: Ref<SockMap<Association>>,
+    owner: &Owner,
 ) -> Result<()> {
-    let mut registry = Registry::new(batched::MAX_PACKETS);
+    let mut registry = Registry::new(batched::MAX_PACKETS, conn_opts.udp_packet_size)
+        .with_gro(&lis)
+        .with_dst(&lis);
+    // replies to clients are sent by the listener
+    let lis_gso = Arc::new(Gso::new(conn_opts.udp_offload));
+
+    // datagrams with PROXY headers are sent one by one
+    #[cfg(feature = "proxy")]
+    let mut proxy_buf = conn_opts.proxy_opts.udp_enabled().then(|| {
+        let size = batched::packet_size(conn_opts.udp_packet_size) + crate::proxy::v2::MAX_INET_LEN;
+        vec![0u8; size].into_boxed_slice()
+    });
 
     loop {
-        registry.batched_recv_on(&lis).await?;
+        or_drain(registry.batched_recv_on(&lis, sockmap.truncated_counter())).await?;
         log::debug!("[udp]entry batched recvfrom[{}]", registry.count());
-        let raddr = resolve_addr(&rname).await?.iter().next().unwrap();
-        log::debug!("[udp]{} resolved as {}", *rname, raddr);
+        let raddr = rname.resolve().await?;
 
         registry.group_by_addr();
         for pkts in registry.group_iter() {
             let laddr = pkts[0].addr.clone().into();
-            let rsock = sockmap.find_or_insert(&laddr, || {
-                let s = Arc::new(socket::associate(&raddr, &conn_opts)?);
-                tokio::spawn(send_back(lis, laddr, s.clone(), conn_opts, sockmap));
-                log::info!("[udp]new association {} => {} as {}", laddr, *rname, raddr);
-                Result::Ok(s)
+            #[cfg_attr(not(feature = "proxy"), allow(unused))]
+            let mut created = false;
+            let (mut assoc, stat) = sockmap.find_or_insert(&laddr, |stat| {
+                let sock = Arc::new(socket::associate(&raddr, &conn_opts)?);
+                let lis_gso = lis_gso.clone();
+                tokio::spawn(KeepAlive::new(
+                    owner,
+                    send_back(lis, lis_gso, laddr, sock.clone(), stat.clone(), conn_opts, sockmap),
+                ));
+                log::info!("[udp]new association {} => {} as {}", laddr, rname.remote(), raddr);
+                created = true;
+                Result::Ok(Association {
+                    sock,
+                    gso: Arc::new(Gso::new(conn_opts.udp_offload)),
+                    raddr,
+                    #[cfg(feature = "proxy")]
+                    origin: None,
+                })
             })?;
-            let raddr: SockAddrStore = raddr.into();
-            batched::send_all(&rsock, pkts.iter().map(|x| x.ref_with_addr(&raddr))).await?;
+
+            // an association sticks to its first remote address,
+            // unless asked to follow the dns record
+            if conn_opts.udp_migrate && assoc.raddr != raddr {
+                if can_migrate(&assoc.raddr, &raddr) {
+                    log::info!("[udp]migrate association {} => {} to {}", laddr, assoc.raddr, raddr);
+                    sockmap.update(&laddr, |x| x.raddr = raddr);
+                    assoc.raddr = raddr;
+                } else {
+                    log::warn!(
+                        "[udp]can not migrate association {} => {} to {}",
+                        laddr,
+                        assoc.raddr,
+                        raddr
+                    );
+                }
+            }
+
+            #[cfg(feature = "proxy")]
+            if let Some(buf) = proxy_buf.as_deref_mut() {
+                let ctx = ProxyCtx {
+                    lis: &lis,
+                    laddr,
+                    dst: registry.dst(),
+                    created,
+                    conn_opts: &conn_opts,
+                    sockmap: &sockmap,
+                };
+                let bytes = send_with_proxy(ctx, &mut assoc, pkts, buf).await?;
+                stat.on_sent(bytes);
+                continue;
+            }
+
+            let raddr: SockAddrStore = assoc.raddr.into();
+            let bytes = pkts.iter().map(|x| x.payload().len()).sum();
+            let pkts = pkts.iter().map(|x| x.ref_with_addr(&raddr));
+            batched::send_all(&assoc.sock, pkts, &assoc.gso).await?;
+            stat.on_sent(bytes);
+        }
+    }
+}
+
+/// The socket of an association is bound to the family of its first remote address.
+#[inline]
+fn can_migrate(from: &SocketAddr, to: &SocketAddr) -> bool {
+    from.is_ipv4() == to.is_ipv4()
+}
+
+#[cfg(feature = "proxy")]
+struct ProxyCtx<'a> {
+    lis: &'a UdpSocket,
+    laddr: SocketAddr,
+    dst: Option<IpAddr>,
+    created: bool,
+    conn_opts: &'a ConnectOpts,
+    sockmap: &'a SockMap<Association>,
+}
+
+/// Strip or prepend PROXY headers, returns bytes of payloads.
+#[cfg(feature = "proxy")]
+async fn send_with_proxy(ctx: ProxyCtx<'_>, assoc: &mut Association, pkts: &[Packet], buf: &mut [u8]) -> Result<usize> {
+    use crate::endpoint::UdpProxy;
+
+    let ProxyCtx {
+        lis,
+        laddr,
+        dst,
+        created,
+        conn_opts,
+        sockmap,
+    } = ctx;
+    let opts = &conn_opts.proxy_opts;
+    let accept_proxy = opts.accept_proxy_udp && opts.trusts(laddr.ip());
+    let mut bytes = 0;
+
+    for (i, pkt) in pkts.iter().enumerate() {
+        let mut payload = pkt.payload();
+
+        if accept_proxy {
+            match proxy::strip_header(payload) {
+                Ok((x, origin)) => {
+                    payload = x;
+                    if let Some((src, dst)) = origin.filter(|x| assoc.origin != Some(*x)) {
+                        log::info!("[udp]accept proxy-protocol-v2: {} => {}", src, dst);
+                        sockmap.update(&laddr, |x| x.origin = origin);
+                        assoc.origin = origin;
+                    }
+                }
+                Err(e) => {
+                    log::warn!("[udp]drop packet from {}: {}", laddr, e);
+                    continue;
+                }
+            }
         }
+
+        let with_header = match opts.send_proxy_udp {
+            UdpProxy::Off => false,
+            UdpProxy::First => created && i == 0,
+            UdpProxy::Every => true,
+        };
+
+        let dgram = if with_header {
+            let (src, dst) = match assoc.origin {
+                Some(x) => x,
+                None => {
+                    // the local address of a wildcard listener is not the destination
+                    let mut local = lis.local_addr()?;
+                    if let Some(ip) = dst {
+                        local.set_ip(ip);
+                    }
+                    crate::proxy::canonical(laddr, local)
+                }
+            };
+            log::debug!("[udp]send proxy-protocol-v2: {} => {}", src, dst);
+            proxy::prepend_header(buf, src, dst, payload)?
+        } else {
+            payload
+        };
+
+        assoc.sock.send_to(dgram, assoc.raddr).await?;
+        bytes += payload.len();
     }
+    Ok(bytes)
 }
 
 async fn send_back(
     lsock: Ref<UdpSocket>,
+    lgso: Arc<Gso>,
     laddr: SocketAddr,
     rsock: Arc<UdpSocket>,
+    stat: Arc<AssocStat>,
     conn_opts: Ref<ConnectOpts>,
-    sockmap: Ref<SockMap>,
+    sockmap: Ref<SockMap<Association>>,
 ) {
-    let mut registry = Registry::new(batched::MAX_PACKETS);
+    let _active = Active::new();
+    let mut registry = Registry::new(batched::MAX_PACKETS, conn_opts.udp_packet_size).with_gro(&rsock);
     let timeout = conn_opts.associate_timeout;
     let laddr_s: SockAddrStore = laddr.into();
 
     loop {
-        match timeoutfut(registry.batched_recv_on(&rsock), timeout).await {
+        match timeoutfut(registry.batched_recv_on(&rsock, sockmap.truncated_counter()), timeout).await {
             Err(_) => {
                 log::debug!("[udp]rear recvfrom timeout");
                 break;
@@ -161,13 +377,38 @@ async fn send_back(
             }
         };
 
+        let bytes = registry.iter().map(|pkt| pkt.payload().len()).sum();
         let pkts = registry.iter().map(|pkt| pkt.ref_with_addr(&laddr_s));
-        if let Err(e) = batched::send_all(&lsock, pkts).await {
+        if let Err(e) = batched::send_all(&lsock, pkts, &lgso).await {
             log::error!("[udp]failed to sendto client{}: {}", &laddr, e);
             break;
         }
+        stat.on_recv(bytes);
     }
 
     sockmap.remove(&laddr);
     log::debug!("[udp]remove association for {}", &laddr);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn migrate() {
+        let sockmap = SockMap::<SocketAddr>::new();
+        let laddr: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+        let raddr1: SocketAddr = "192.0.2.1:53".parse().unwrap();
+        let raddr2: SocketAddr = "192.0.2.2:53".parse().unwrap();
+        let raddr6: SocketAddr = "[2001:db8::1]:53".parse().unwrap();
+
+        let (assoc, _) = sockmap.find_or_insert(&laddr, |_| Result::Ok(raddr1)).unwrap();
+        assert!(can_migrate(&assoc, &raddr2));
+        assert!(!can_migrate(&assoc, &raddr6));
+
+        // later datagrams follow the new address
+        sockmap.update(&laddr, |x| *x = raddr2);
+        let (assoc, _) = sockmap.find_or_insert(&laddr, |_| Result::Ok(raddr1)).unwrap();
+        assert_eq!(assoc, raddr2);
+    }
+}
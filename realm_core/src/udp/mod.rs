@@ -4,14 +4,26 @@ mod socket;
 mod sockmap;
 mod middle;
 mod batched;
+mod tunnel;
+
+#[cfg(feature = "proxy")]
+mod proxy;
 
 use std::io::Result;
+use std::net::SocketAddr;
+use std::future::Future;
+use std::sync::Arc;
+
+use tokio::task::JoinSet;
 
-use crate::trick::Ref;
-use crate::endpoint::Endpoint;
+use crate::trick::{Ref, Owner};
+use crate::dns::CachedAddr;
+use crate::endpoint::{Endpoint, LocalAddr, RemoteAddr, ConnectOpts};
 
-use sockmap::SockMap;
+use sockmap::Association;
+pub use sockmap::{SockMap, AssocStat, Associations, associations};
 use middle::associate_and_relay;
+use tunnel::{Tunnel, associate_and_tunnel, accept_and_relay};
 
 /// Launch a udp relay.
 pub async fn run_udp(endpoint: Endpoint) -> Result<()> {
@@ -23,17 +35,178 @@ pub async fn run_udp(endpoint: Endpoint) -> Result<()> {
         ..
     } = endpoint;
 
-    let sockmap = SockMap::new();
+    if raddr == RemoteAddr::OriginalDst {
+        panic!("[udp]original destination of {} is not supported", laddr);
+    }
+
+    let laddr = match laddr {
+        LocalAddr::SocketAddr(addr) if !matches!(raddr, RemoteAddr::Unix(_)) => addr,
+        _ => panic!("[udp]unix socket of {} => {} is not supported", laddr, raddr),
+    };
+
+    let raddr = CachedAddr::new(raddr, conn_opts.udp_resolve_interval);
+
+    // receive udp over tcp
+    if bind_opts.accept_udp_over_tcp {
+        let lis = crate::tcp::socket::bind(&laddr, &bind_opts)
+            .unwrap_or_else(|e| panic!("[udp]failed to bind {}: {}", laddr, e));
+
+        #[cfg(unix)]
+        crate::systemd::listening();
+
+        // there is no association table, only the counter is used
+        let shared = Shared::new(vec![lis], raddr, conn_opts, SockMap::<()>::new(), laddr);
+        run_each(shared, |shared, i| async move {
+            let owner: Owner = shared.clone();
+            let (lis, raddr, conn_opts, sockmap) = shared.refs(i);
+            let truncated = Ref::new(sockmap.truncated_counter());
+            loop {
+                if let Err(e) = accept_and_relay(lis, raddr, conn_opts, truncated, &owner).await {
+                    log::error!("[udp]error: {}", e);
+                }
+            }
+        })
+        .await;
+        return Ok(());
+    }
 
-    let lis = socket::bind(&laddr, bind_opts).unwrap_or_else(|e| panic!("[udp]failed to bind {}: {}", laddr, e));
+    let n = crate::tcp::socket::listeners(bind_opts.udp_listeners);
+    let listeners =
+        socket::bind_many(&laddr, &bind_opts, n).unwrap_or_else(|e| panic!("[udp]failed to bind {}: {}", laddr, e));
+    log::debug!("[udp]{} listeners on {}", listeners.len(), laddr);
 
-    let lis = Ref::new(&lis);
-    let raddr = Ref::new(&raddr);
-    let conn_opts = Ref::new(&conn_opts);
-    let sockmap = Ref::new(&sockmap);
-    loop {
-        if let Err(e) = associate_and_relay(lis, raddr, conn_opts, sockmap).await {
-            log::error!("[udp]error: {}", e);
+    #[cfg(unix)]
+    crate::systemd::listening();
+
+    // the destination in a proxy header is unknown to a wildcard
+    // listener, unless packets are received one by one
+    #[cfg(feature = "proxy")]
+    let recv_dst = !conn_opts.send_udp_over_tcp
+        && conn_opts.proxy_opts.send_proxy_udp != crate::endpoint::UdpProxy::Off
+        && laddr.ip().is_unspecified()
+        && listeners.iter().all(socket::try_recv_dst);
+
+    #[cfg(not(feature = "proxy"))]
+    let recv_dst = false;
+
+    if conn_opts.udp_offload && !recv_dst {
+        listeners.iter().for_each(socket::try_gro);
+    }
+
+    // send udp over tcp
+    if conn_opts.send_udp_over_tcp {
+        let shared = Shared::new(listeners, raddr, conn_opts, SockMap::<Tunnel>::new(), laddr);
+        run_each(shared, |shared, i| async move {
+            let owner: Owner = shared.clone();
+            let (lis, raddr, conn_opts, tunnels) = shared.refs(i);
+            loop {
+                if let Err(e) = associate_and_tunnel(lis, raddr, conn_opts, tunnels, &owner).await {
+                    log::error!("[udp]error: {}", e);
+                }
+            }
+        })
+        .await;
+        return Ok(());
+    }
+
+    // associations are shared by all listeners,
+    // any of them could send replies back to a client
+    let shared = Shared::new(listeners, raddr, conn_opts, SockMap::<Association>::new(), laddr);
+    run_each(shared, |shared, i| async move {
+        let owner: Owner = shared.clone();
+        let (lis, raddr, conn_opts, sockmap) = shared.refs(i);
+        loop {
+            if let Err(e) = associate_and_relay(lis, raddr, conn_opts, sockmap, &owner).await {
+                log::error!("[udp]error: {}", e);
+            }
         }
+    })
+    .await;
+    Ok(())
+}
+
+/// Bind listeners of a udp relay before the runtime starts,
+/// which are taken by [`run_udp`] later.
+///
+/// This is useful when privileges are dropped after binding.
+#[cfg(unix)]
+pub fn prebind(endpoint: &Endpoint) -> Result<()> {
+    let Endpoint { laddr, bind_opts, .. } = endpoint;
+    let name = &bind_opts.fd_name;
+
+    // unsupported, reported by run_udp
+    let LocalAddr::SocketAddr(laddr) = laddr else {
+        return Ok(());
+    };
+
+    if bind_opts.accept_udp_over_tcp {
+        let lis = crate::tcp::socket::bind_std(laddr, bind_opts)?;
+        crate::systemd::inherit(lis.into(), name.clone());
+        return Ok(());
+    }
+
+    let n = crate::tcp::socket::listeners(bind_opts.udp_listeners);
+    for lis in socket::bind_many_std(laddr, bind_opts, n)? {
+        crate::systemd::inherit(lis.into(), name.clone());
+    }
+    Ok(())
+}
+
+/// State of a udp relay, owned by all of its tasks.
+struct Shared<L, T> {
+    listeners: Vec<L>,
+    raddr: CachedAddr,
+    conn_opts: ConnectOpts,
+    sockmap: Arc<SockMap<T>>,
+}
+
+type Refs<L, T> = (Ref<L>, Ref<CachedAddr>, Ref<ConnectOpts>, Ref<SockMap<T>>);
+
+impl<L, T> Shared<L, T>
+where
+    L: Send + Sync + 'static,
+    T: Clone + Send + Sync + 'static,
+{
+    fn new(
+        listeners: Vec<L>,
+        raddr: CachedAddr,
+        conn_opts: ConnectOpts,
+        sockmap: SockMap<T>,
+        laddr: SocketAddr,
+    ) -> Arc<Self> {
+        let sockmap = Arc::new(sockmap);
+        sockmap::register(laddr, &(sockmap.clone() as Arc<dyn Associations>));
+        Arc::new(Self {
+            listeners,
+            raddr,
+            conn_opts,
+            sockmap,
+        })
+    }
+
+    /// Pointers are valid as long as the shared state is alive.
+    fn refs(&self, i: usize) -> Refs<L, T> {
+        (
+            Ref::new(&self.listeners[i]),
+            Ref::new(&self.raddr),
+            Ref::new(&self.conn_opts),
+            Ref::new(self.sockmap.as_ref()),
+        )
+    }
+}
+
+/// Run a loop on each listener.
+///
+/// Workers are aborted once this future is dropped, and any
+/// task spawned by them should hold the shared state.
+async fn run_each<L, T, F, Fut>(shared: Arc<Shared<L, T>>, f: F)
+where
+    F: Fn(Arc<Shared<L, T>>, usize) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut workers = JoinSet::new();
+    for i in 0..shared.listeners.len() {
+        workers.spawn(f(shared.clone(), i));
     }
+    while workers.join_next().await.is_some() {}
 }
@@ -0,0 +1,103 @@
+//! PROXY protocol v2 over datagrams.
+//!
+//! A header is placed at the start of a datagram, which is
+//! sent to the remote peer. Replies never carry a header.
+
+use std::io::{Error, ErrorKind, Result};
+use std::net::SocketAddr;
+
+use crate::proxy::{v2, Command, Protocol, Addrs};
+
+/// Original source and destination of datagrams.
+pub type Origin = (SocketAddr, SocketAddr);
+
+/// Strip the header at the start of a datagram if present.
+///
+/// Returns the payload, and the original addresses if provided.
+pub fn strip_header(buf: &[u8]) -> Result<(&[u8], Option<Origin>)> {
+    if !buf.starts_with(v2::SIGNATURE) {
+        return Ok((buf, None));
+    }
+
+    let (header, n) =
+        v2::parse(buf)?.ok_or_else(|| Error::new(ErrorKind::InvalidData, "truncated proxy-protocol-v2 header"))?;
+
+    // addresses of a stream are meaningless here
+    let addrs = match header.protocol {
+        Protocol::Dgram => header.inet_addrs(),
+        _ => None,
+    };
+    Ok((&buf[n..], addrs))
+}
+
+/// Write a header followed by the payload into `buf`, returns the datagram.
+pub fn prepend_header<'a>(buf: &'a mut [u8], src: SocketAddr, dst: SocketAddr, payload: &[u8]) -> Result<&'a [u8]> {
+    let addrs = Addrs::Inet { src, dst };
+    let n = v2::encode(buf, Command::Proxy, Protocol::Dgram, &addrs, &[])?;
+    let total = n + payload.len();
+    buf.get_mut(n..total)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "buffer is too short for datagram"))?
+        .copy_from_slice(payload);
+    Ok(&buf[..total])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prepend_strip() {
+        let src: SocketAddr = "192.0.2.1:1000".parse().unwrap();
+        let dst: SocketAddr = "[2001:db8::2]:53".parse().unwrap();
+
+        let mut buf = [0u8; v2::MAX_INET_LEN + 16];
+        let dgram = prepend_header(&mut buf, src, dst, b"payload").unwrap();
+        assert_eq!(dgram.len(), v2::MAX_INET_LEN + 7);
+
+        let (payload, addrs) = strip_header(dgram).unwrap();
+        assert_eq!(payload, b"payload");
+        let (src2, dst2) = addrs.unwrap();
+        assert_eq!(crate::proxy::same_family(src, dst), (src2, dst2));
+
+        let mut buf = [0u8; v2::MAX_INET_LEN];
+        assert!(prepend_header(&mut buf, src, dst, b"payload").is_err());
+    }
+
+    #[test]
+    fn strip_none() {
+        let (payload, addrs) = strip_header(b"\x12\x34\x01\x00").unwrap();
+        assert_eq!(payload, b"\x12\x34\x01\x00");
+        assert_eq!(addrs, None);
+
+        // an empty datagram is valid
+        let (payload, addrs) = strip_header(b"").unwrap();
+        assert!(payload.is_empty());
+        assert_eq!(addrs, None);
+    }
+
+    #[test]
+    fn strip_invalid() {
+        let mut buf = [0u8; v2::MAX_INET_LEN];
+        let src = "192.0.2.1:1000".parse().unwrap();
+        let dst = "192.0.2.2:53".parse().unwrap();
+        let dgram = prepend_header(&mut buf, src, dst, &[]).unwrap();
+        let n = dgram.len();
+
+        assert!(strip_header(&buf[..n - 1]).is_err());
+        buf[12] = 0x31;
+        assert!(strip_header(&buf[..n]).is_err());
+    }
+
+    #[test]
+    fn strip_stream() {
+        let mut buf = [0u8; v2::MAX_INET_LEN];
+        let addrs = Addrs::Inet {
+            src: "192.0.2.1:1000".parse().unwrap(),
+            dst: "192.0.2.2:53".parse().unwrap(),
+        };
+        let n = v2::encode(&mut buf, Command::Proxy, Protocol::Stream, &addrs, &[]).unwrap();
+        let (payload, addrs) = strip_header(&buf[..n]).unwrap();
+        assert!(payload.is_empty());
+        assert_eq!(addrs, None);
+    }
+}
@@ -4,37 +4,95 @@ use std::net::SocketAddr;
 use tokio::net::UdpSocket;
 use realm_syscall::new_udp_socket;
 
+use super::batched;
+use crate::tcp::socket::{set_transparent, set_qos};
 use crate::endpoint::{BindOpts, ConnectOpts};
 
-pub fn bind(laddr: &SocketAddr, bind_opts: BindOpts) -> Result<UdpSocket> {
+/// Bind `n` listeners on the same address.
+pub fn bind_many(laddr: &SocketAddr, bind_opts: &BindOpts, n: usize) -> Result<Vec<UdpSocket>> {
+    let listeners = bind_many_std(laddr, bind_opts, n)?;
+    #[cfg(unix)]
+    listeners
+        .iter()
+        .for_each(|x| crate::upgrade::register(x, bind_opts.fd_name.as_deref()));
+    listeners.into_iter().map(UdpSocket::from_std).collect()
+}
+
+/// Same as [`bind_many`], without a runtime.
+pub(crate) fn bind_many_std(laddr: &SocketAddr, bind_opts: &BindOpts, n: usize) -> Result<Vec<std::net::UdpSocket>> {
+    // systemd decides the number of listeners
+    #[cfg(unix)]
+    {
+        let inherited = crate::systemd::take_udp(laddr, bind_opts.fd_name.as_deref())?;
+        if !inherited.is_empty() {
+            log::info!("[udp]inherit {} listeners on {}", inherited.len(), laddr);
+            return Ok(inherited);
+        }
+    }
+
+    let reuse_port = n > 1;
+    let mut laddr = *laddr;
+    let mut listeners = Vec::with_capacity(n);
+
+    for _ in 0..n.max(1) {
+        let lis = bind(&laddr, bind_opts, reuse_port)?;
+        // others should share the port picked by the first one
+        laddr = lis.local_addr()?;
+        listeners.push(lis);
+    }
+
+    Ok(listeners)
+}
+
+fn bind(laddr: &SocketAddr, bind_opts: &BindOpts, reuse_port: bool) -> Result<std::net::UdpSocket> {
     let BindOpts {
         ipv6_only,
+        transparent,
         bind_interface,
+        accept_mark,
+        accept_tos,
         ..
     } = bind_opts;
     let socket = new_udp_socket(laddr)?;
 
     // ipv6_only
     if let SocketAddr::V6(_) = laddr {
-        socket.set_only_v6(ipv6_only)?;
+        socket.set_only_v6(*ipv6_only)?;
     }
 
     #[cfg(target_os = "linux")]
     if let Some(iface) = bind_interface {
-        realm_syscall::bind_to_device(&socket, &iface)?;
+        realm_syscall::bind_to_device(&socket, iface)?;
     }
 
+    if *transparent {
+        set_transparent(&socket, laddr)?;
+    }
+
+    set_qos(&socket, laddr, *accept_mark, *accept_tos, None)?;
+
     // ignore error
     let _ = socket.set_reuse_address(true);
 
+    #[cfg(target_os = "linux")]
+    if reuse_port {
+        socket.set_reuse_port(true)?;
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    let _ = reuse_port;
+
     socket.bind(&(*laddr).into())?;
 
-    UdpSocket::from_std(socket.into())
+    Ok(socket.into())
 }
 
 pub fn associate(raddr: &SocketAddr, conn_opts: &ConnectOpts) -> Result<UdpSocket> {
     let ConnectOpts {
         bind_address,
+        udp_offload,
+        send_mark,
+        send_tos,
 
         #[cfg(target_os = "linux")]
         bind_interface,
@@ -46,6 +104,8 @@ pub fn associate(raddr: &SocketAddr, conn_opts: &ConnectOpts) -> Result<UdpSocke
     // ignore error
     let _ = socket.set_reuse_address(true);
 
+    set_qos(&socket, raddr, *send_mark, *send_tos, None)?;
+
     if let Some(addr) = *bind_address {
         socket.bind(&addr.into())?;
     }
@@ -55,5 +115,30 @@ pub fn associate(raddr: &SocketAddr, conn_opts: &ConnectOpts) -> Result<UdpSocke
         realm_syscall::bind_to_device(&socket, iface)?;
     }
 
-    UdpSocket::from_std(socket.into())
+    let socket = UdpSocket::from_std(socket.into())?;
+
+    if *udp_offload {
+        try_gro(&socket);
+    }
+
+    Ok(socket)
+}
+
+/// Try to receive coalesced packets, fallback if not supported.
+pub fn try_gro(sock: &UdpSocket) {
+    if let Err(e) = batched::enable_gro(sock) {
+        log::debug!("[udp]failed to enable gro: {}", e);
+    }
+}
+
+/// Try to receive destination addresses, fallback to the local address if not supported.
+#[cfg(feature = "proxy")]
+pub fn try_recv_dst(sock: &UdpSocket) -> bool {
+    match batched::enable_dst(sock) {
+        Ok(()) => true,
+        Err(e) => {
+            log::warn!("[udp]failed to receive destination addresses: {}", e);
+            false
+        }
+    }
 }
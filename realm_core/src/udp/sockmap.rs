@@ -1,59 +1,338 @@
 use std::net::SocketAddr;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::time::{Duration, Instant};
 
+use once_cell::sync::Lazy;
 use tokio::net::UdpSocket;
 
-pub struct SockMap(RwLock<HashMap<SocketAddr, Arc<UdpSocket>>>);
+/// Outbound socket and remote peer of a client.
+#[derive(Clone)]
+pub struct Association {
+    pub sock: Arc<UdpSocket>,
+    pub(super) gso: Arc<super::batched::Gso>,
+    pub raddr: SocketAddr,
+    /// Addresses received from a PROXY header.
+    #[cfg(feature = "proxy")]
+    pub origin: Option<super::proxy::Origin>,
+}
+
+/// Metadata of an association.
+#[derive(Debug)]
+pub struct AssocStat {
+    created_at: Instant,
+    // millis since created
+    last_seen: AtomicU64,
+    sent_bytes: AtomicU64,
+    recv_bytes: AtomicU64,
+}
 
-impl SockMap {
+impl AssocStat {
     pub fn new() -> Self {
-        Self(RwLock::new(HashMap::new()))
+        Self {
+            created_at: Instant::now(),
+            last_seen: AtomicU64::new(0),
+            sent_bytes: AtomicU64::new(0),
+            recv_bytes: AtomicU64::new(0),
+        }
     }
 
     #[inline]
-    pub fn find(&self, addr: &SocketAddr) -> Option<Arc<UdpSocket>> {
-        // fetch the lock
+    pub const fn created_at(&self) -> Instant {
+        self.created_at
+    }
+
+    #[inline]
+    pub fn last_seen(&self) -> Instant {
+        self.created_at + Duration::from_millis(self.last_seen.load(Ordering::Relaxed))
+    }
+
+    /// Bytes sent from the client to the remote peer.
+    #[inline]
+    pub fn sent_bytes(&self) -> u64 {
+        self.sent_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Bytes sent from the remote peer to the client.
+    #[inline]
+    pub fn recv_bytes(&self) -> u64 {
+        self.recv_bytes.load(Ordering::Relaxed)
+    }
 
-        let sockmap = self.0.read().unwrap();
+    #[inline]
+    fn touch(&self) {
+        let millis = self.created_at.elapsed().as_millis() as u64;
+        self.last_seen.fetch_max(millis, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn on_sent(&self, bytes: usize) {
+        self.sent_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.touch();
+    }
 
-        sockmap.get(addr).cloned()
+    #[inline]
+    pub fn on_recv(&self, bytes: usize) {
+        self.recv_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.touch();
+    }
+}
+
+impl Default for AssocStat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+type Shard<T> = RwLock<HashMap<SocketAddr, (T, Arc<AssocStat>)>>;
+
+const SHARDS: usize = 32;
+
+/// Association table, sharded by client address.
+pub struct SockMap<T> {
+    shards: Box<[Shard<T>]>,
+    hasher: RandomState,
+    truncated: AtomicU64,
+}
+
+impl<T: Clone> SockMap<T> {
+    pub fn new() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| RwLock::new(HashMap::new())).collect(),
+            hasher: RandomState::new(),
+            truncated: AtomicU64::new(0),
+        }
+    }
+
+    #[inline]
+    fn shard(&self, addr: &SocketAddr) -> &Shard<T> {
+        let idx = self.hasher.hash_one(addr) as usize % SHARDS;
+        &self.shards[idx]
+    }
+
+    #[inline]
+    pub fn find(&self, addr: &SocketAddr) -> Option<(T, Arc<AssocStat>)> {
+        // fetch the lock
+        let shard = self.shard(addr).read().unwrap();
+
+        shard.get(addr).cloned()
 
         // drop the lock
     }
 
+    /// Get an association, or create one with `f` if not present.
+    ///
+    /// `f` is called at most once for the same address, even if there
+    /// are concurrent callers.
     #[inline]
-    pub fn insert(&self, addr: SocketAddr, socket: Arc<UdpSocket>) {
+    pub fn find_or_insert<E, F>(&self, addr: &SocketAddr, f: F) -> Result<(T, Arc<AssocStat>), E>
+    where
+        F: FnOnce(&Arc<AssocStat>) -> Result<T, E>,
+    {
+        if let Some(x) = self.find(addr) {
+            return Ok(x);
+        }
+
         // fetch the lock
-        let mut sockmap = self.0.write().unwrap();
+        let mut shard = self.shard(addr).write().unwrap();
 
-        let _ = sockmap.insert(addr, socket);
+        // check again, someone else may have inserted it
+        if let Some(x) = shard.get(addr) {
+            return Ok(x.clone());
+        }
+
+        let stat = Arc::new(AssocStat::new());
+        let assoc = f(&stat)?;
+        shard.insert(*addr, (assoc.clone(), stat.clone()));
+        Ok((assoc, stat))
 
         // drop the lock
     }
 
+    /// Modify an existing association, do nothing if it has been removed.
     #[inline]
-    pub fn find_or_insert<E, F>(&self, addr: &SocketAddr, f: F) -> Result<Arc<UdpSocket>, E>
+    pub fn update<F>(&self, addr: &SocketAddr, f: F)
     where
-        F: Fn() -> Result<Arc<UdpSocket>, E>,
+        F: FnOnce(&mut T),
     {
-        match self.find(addr) {
-            Some(x) => Ok(x),
-            None => {
-                let socket = f()?;
-                self.insert(*addr, Arc::clone(&socket));
-                Ok(socket)
-            }
+        // fetch the lock
+        let mut shard = self.shard(addr).write().unwrap();
+
+        if let Some((assoc, _)) = shard.get_mut(addr) {
+            f(assoc);
         }
+
+        // drop the lock
     }
 
     #[inline]
     pub fn remove(&self, addr: &SocketAddr) {
         // fetch the lock
-        let mut sockmap = self.0.write().unwrap();
+        let mut shard = self.shard(addr).write().unwrap();
 
-        let _ = sockmap.remove(addr);
+        let _ = shard.remove(addr);
 
         // drop the lock
     }
+
+    /// Count of associations.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|x| x.read().unwrap().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Metadata of all associations.
+    pub fn stats(&self) -> Vec<(SocketAddr, Arc<AssocStat>)> {
+        let mut stats = Vec::new();
+        for shard in self.shards.iter() {
+            let shard = shard.read().unwrap();
+            stats.extend(shard.iter().map(|(addr, (_, stat))| (*addr, stat.clone())));
+        }
+        stats
+    }
+
+    /// Count of datagrams dropped for exceeding the packet size.
+    pub fn truncated(&self) -> u64 {
+        self.truncated.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) const fn truncated_counter(&self) -> &AtomicU64 {
+        &self.truncated
+    }
+}
+
+impl<T: Clone> Default for SockMap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Associations of a running udp relay, see [`associations`].
+pub trait Associations: Send + Sync {
+    /// Count of associations.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Metadata of all associations.
+    fn stats(&self) -> Vec<(SocketAddr, Arc<AssocStat>)>;
+
+    /// Count of datagrams dropped for exceeding the packet size.
+    fn truncated(&self) -> u64;
+}
+
+impl<T: Clone + Send + Sync> Associations for SockMap<T> {
+    fn len(&self) -> usize {
+        SockMap::len(self)
+    }
+
+    fn stats(&self) -> Vec<(SocketAddr, Arc<AssocStat>)> {
+        SockMap::stats(self)
+    }
+
+    fn truncated(&self) -> u64 {
+        SockMap::truncated(self)
+    }
+}
+
+type Registry = HashMap<SocketAddr, Weak<dyn Associations>>;
+
+static REGISTRY: Lazy<Mutex<Registry>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Get associations of the udp relay listening on `laddr`,
+/// returns none if there is no such relay, or it has exited.
+pub fn associations(laddr: &SocketAddr) -> Option<Arc<dyn Associations>> {
+    REGISTRY.lock().unwrap().get(laddr).and_then(Weak::upgrade)
+}
+
+/// Make associations of a relay visible until they are dropped.
+pub(crate) fn register(laddr: SocketAddr, map: &Arc<dyn Associations>) {
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.retain(|_, x| x.strong_count() != 0);
+    registry.insert(laddr, Arc::downgrade(map));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn find_or_insert_once() {
+        let map = Arc::new(SockMap::<usize>::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let addr: SocketAddr = "127.0.0.1:10000".parse().unwrap();
+
+        let workers: Vec<_> = (0..8)
+            .map(|i| {
+                let map = map.clone();
+                let calls = calls.clone();
+                std::thread::spawn(move || {
+                    let (x, _) = map
+                        .find_or_insert(&addr, |_| {
+                            calls.fetch_add(1, Ordering::Relaxed);
+                            Ok::<_, ()>(i)
+                        })
+                        .unwrap();
+                    x
+                })
+            })
+            .collect();
+
+        let values: Vec<_> = workers.into_iter().map(|x| x.join().unwrap()).collect();
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert!(values.iter().all(|x| *x == values[0]));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn stat() {
+        let map = SockMap::<()>::new();
+        let addr: SocketAddr = "127.0.0.1:10000".parse().unwrap();
+
+        let (_, stat) = map.find_or_insert(&addr, |_| Ok::<_, ()>(())).unwrap();
+        stat.on_sent(100);
+        stat.on_recv(200);
+
+        let stats = map.stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].0, addr);
+        assert_eq!(stats[0].1.sent_bytes(), 100);
+        assert_eq!(stats[0].1.recv_bytes(), 200);
+        assert!(stats[0].1.last_seen() >= stats[0].1.created_at());
+
+        map.remove(&addr);
+        assert!(map.find(&addr).is_none());
+        assert_eq!(map.len(), 0);
+    }
+
+    #[test]
+    fn registry() {
+        let laddr: SocketAddr = "127.0.0.1:10001".parse().unwrap();
+        let addr: SocketAddr = "127.0.0.1:10000".parse().unwrap();
+        assert!(associations(&laddr).is_none());
+
+        let map = Arc::new(SockMap::<()>::new());
+        map.find_or_insert(&addr, |_| Ok::<_, ()>(())).unwrap();
+        let dyn_map: Arc<dyn Associations> = map.clone();
+        register(laddr, &dyn_map);
+        drop(dyn_map);
+
+        let found = associations(&laddr).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found.stats()[0].0, addr);
+        drop(found);
+
+        // gone with the relay
+        drop(map);
+        assert!(associations(&laddr).is_none());
+    }
 }
@@ -0,0 +1,338 @@
+//! UDP over TCP.
+//!
+//! Each client association is carried by its own stream, where
+//! every datagram is prefixed with its length(u16, big endian).
+//!
+//! The stream could be further wrapped by a transport(ws, tls, wss),
+//! and is relayed as is if both sides are tunnels.
+
+use std::io::Result;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::pin::pin;
+
+use futures::future::select;
+use tokio::io::{AsyncRead, AsyncWrite, BufReader};
+use tokio::net::{UdpSocket, TcpStream, TcpListener};
+use tokio::sync::mpsc::{self, Sender, Receiver};
+
+use super::{SockMap, AssocStat};
+use super::{socket, batched};
+use super::middle::Registry;
+
+use crate::trick::{Ref, Owner, KeepAlive};
+use crate::time::timeoutfut;
+use crate::dns::CachedAddr;
+use crate::endpoint::{RemoteAddr, ConnectOpts};
+use crate::tcp::socket as tcp_socket;
+use crate::tcp::socket::{AcceptBackoff, is_out_of_resources};
+use crate::upgrade::{Active, or_drain};
+
+use realm_io::{CopyBuffer, CopyOpts, bidi_copy_buf, buf_size};
+
+pub type Datagram = Box<[u8]>;
+
+/// Sender side of a client association.
+pub type Tunnel = Sender<Datagram>;
+
+// packets queued before the stream is writable, drop the others
+const QUEUE_SIZE: usize = batched::MAX_PACKETS;
+
+mod frame {
+    use std::io::{Result, ErrorKind};
+    use tokio::io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt};
+
+    /// Read a datagram, return false if the stream is closed.
+    ///
+    /// The reader should be buffered, since some transports(e.g. ws)
+    /// could not decode a frame into a tiny buffer.
+    pub async fn read<R>(r: &mut R, buf: &mut Vec<u8>) -> Result<bool>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let len = match r.read_u16().await {
+            Ok(len) => len as usize,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        buf.resize(len, 0);
+        r.read_exact(buf).await?;
+        Ok(true)
+    }
+
+    /// Write a datagram, with the provided buffer.
+    pub async fn write<W>(w: &mut W, buf: &mut Vec<u8>, data: &[u8]) -> Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let len = u16::try_from(data.len()).map_err(|_| ErrorKind::InvalidInput)?;
+        buf.clear();
+        buf.extend_from_slice(&len.to_be_bytes());
+        buf.extend_from_slice(data);
+        w.write_all(buf).await?;
+        w.flush().await
+    }
+}
+
+/// Receive datagrams from clients, and send them through tunnels.
+pub async fn associate_and_tunnel(
+    lis: Ref<UdpSocket>,
+    rname: Ref<CachedAddr>,
+    conn_opts: Ref<ConnectOpts>,
+    tunnels: Ref<SockMap<Tunnel>>,
+    owner: &Owner,
+) -> Result<()> {
+    let mut registry = Registry::new(batched::MAX_PACKETS, conn_opts.udp_packet_size).with_gro(&lis);
+
+    loop {
+        or_drain(registry.batched_recv_on(&lis, tunnels.truncated_counter())).await?;
+        log::debug!("[udp]entry batched recvfrom[{}]", registry.count());
+
+        registry.group_by_addr();
+        for pkts in registry.group_iter() {
+            let laddr = pkts[0].addr.clone().into();
+            let (tunnel, stat) = tunnels.find_or_insert(&laddr, |stat| {
+                let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+                let stat = stat.clone();
+                let tunnel = connect_and_tunnel(lis, laddr, rx, stat, rname, conn_opts, tunnels);
+                tokio::spawn(KeepAlive::new(owner, tunnel));
+                log::info!("[udp]new tunnel {} => {}", laddr, rname.remote());
+                Result::Ok(tx)
+            })?;
+
+            for pkt in pkts {
+                let payload = pkt.payload();
+                match tunnel.try_send(payload.into()) {
+                    Ok(()) => stat.on_sent(payload.len()),
+                    Err(_) => log::debug!("[udp]tunnel for {} is busy, drop packet", laddr),
+                }
+            }
+        }
+    }
+}
+
+async fn connect_and_tunnel(
+    lis: Ref<UdpSocket>,
+    laddr: SocketAddr,
+    mut rx: Receiver<Datagram>,
+    stat: Arc<AssocStat>,
+    rname: Ref<CachedAddr>,
+    conn_opts: Ref<ConnectOpts>,
+    tunnels: Ref<SockMap<Tunnel>>,
+) {
+    let _active = Active::new();
+    let timeout = conn_opts.associate_timeout;
+
+    match connect(rname.remote(), &conn_opts).await {
+        Ok(stream) => {
+            if let Err(e) = relay_client(&lis, laddr, stream, &mut rx, &stat, timeout).await {
+                log::debug!("[udp]tunnel for {} closed: {}", laddr, e);
+            }
+        }
+        Err(e) => log::error!("[udp]failed to connect tunnel {} => {}: {}", laddr, rname.remote(), e),
+    }
+
+    tunnels.remove(&laddr);
+    log::debug!("[udp]remove tunnel for {}", laddr);
+}
+
+async fn relay_client<S>(
+    lis: &UdpSocket,
+    laddr: SocketAddr,
+    stream: S,
+    rx: &mut Receiver<Datagram>,
+    stat: &AssocStat,
+    timeout: usize,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (rd, mut wr) = tokio::io::split(stream);
+    let mut rd = BufReader::with_capacity(buf_size(), rd);
+
+    let uplink = async {
+        let mut buf = Vec::new();
+        while let Some(data) = rx.recv().await {
+            frame::write(&mut wr, &mut buf, &data).await?;
+        }
+        Ok(())
+    };
+
+    let downlink = async {
+        let mut buf = Vec::new();
+        while timeoutfut(frame::read(&mut rd, &mut buf), timeout).await?? {
+            lis.send_to(&buf, laddr).await?;
+            stat.on_recv(buf.len());
+        }
+        Ok(())
+    };
+
+    let (res, _) = select(pin!(uplink), pin!(downlink)).await.factor_first();
+    res
+}
+
+/// Accept tunnels, and relay datagrams to the remote peer.
+pub async fn accept_and_relay(
+    lis: Ref<TcpListener>,
+    rname: Ref<CachedAddr>,
+    conn_opts: Ref<ConnectOpts>,
+    truncated: Ref<AtomicU64>,
+    owner: &Owner,
+) -> Result<()> {
+    let mut backoff = AcceptBackoff::new();
+
+    loop {
+        let (stream, addr) = match or_drain(lis.accept()).await {
+            Ok(x) => {
+                backoff.reset();
+                x
+            }
+            Err(e) if is_out_of_resources(&e) => {
+                log::warn!("[udp]failed to accept tunnel: {}, retry in {:?}", e, backoff.delay());
+                backoff.wait().await;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        // ignore error
+        let _ = stream.set_nodelay(true);
+
+        let active = Active::new();
+        tokio::spawn(KeepAlive::new(owner, async move {
+            let _active = active;
+            match accept_and_forward(stream, addr, rname, conn_opts, truncated).await {
+                Ok(..) => log::debug!("[udp]tunnel {} => {}, finish", addr, rname.remote()),
+                Err(e) => log::error!("[udp]tunnel {} => {}, error: {}", addr, rname.remote(), e),
+            }
+        }));
+    }
+}
+
+async fn accept_and_forward(
+    stream: TcpStream,
+    addr: SocketAddr,
+    rname: Ref<CachedAddr>,
+    conn_opts: Ref<ConnectOpts>,
+    truncated: Ref<AtomicU64>,
+) -> Result<()> {
+    let mut local = accept(stream, &conn_opts).await?;
+
+    if !conn_opts.send_udp_over_tcp {
+        return associate_and_relay(local, addr, rname, conn_opts, truncated).await;
+    }
+
+    // tunnel => tunnel, frames are kept untouched
+    let mut remote = connect(rname.remote(), &conn_opts).await?;
+    log::info!("[udp]new tunnel {} => {}", addr, rname.remote());
+
+    let buf1 = CopyBuffer::new(vec![0; buf_size()]);
+    let buf2 = CopyBuffer::new(vec![0; buf_size()]);
+    let opts = CopyOpts {
+        shutdown: conn_opts.tcp_shutdown,
+        ..Default::default()
+    };
+    bidi_copy_buf(&mut local, &mut remote, buf1, buf2, opts)
+        .await
+        .map(|_| ())
+}
+
+async fn associate_and_relay<S>(
+    stream: S,
+    addr: SocketAddr,
+    rname: Ref<CachedAddr>,
+    conn_opts: Ref<ConnectOpts>,
+    truncated: Ref<AtomicU64>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let raddr = rname.resolve().await?;
+    let rsock = socket::associate(&raddr, &conn_opts)?;
+    log::info!(
+        "[udp]new tunnel association {} => {} as {}",
+        addr,
+        rname.remote(),
+        raddr
+    );
+
+    let timeout = conn_opts.associate_timeout;
+    let (rd, mut wr) = tokio::io::split(stream);
+    let mut rd = BufReader::with_capacity(buf_size(), rd);
+
+    let uplink = async {
+        let mut buf = Vec::new();
+        while frame::read(&mut rd, &mut buf).await? {
+            rsock.send_to(&buf, raddr).await?;
+        }
+        Ok(())
+    };
+
+    let downlink = async {
+        let mut registry = Registry::new(batched::MAX_PACKETS, conn_opts.udp_packet_size).with_gro(&rsock);
+        let mut out = Vec::new();
+        loop {
+            timeoutfut(registry.batched_recv_on(&rsock, &truncated), timeout).await??;
+            for pkt in registry.iter() {
+                frame::write(&mut wr, &mut out, pkt.payload()).await?;
+            }
+        }
+    };
+
+    let (res, _) = select(pin!(uplink), pin!(downlink)).await.factor_first();
+    res
+}
+
+/// Connect to the remote peer, then handshake if a transport is provided.
+async fn connect(raddr: &RemoteAddr, conn_opts: &ConnectOpts) -> Result<impl AsyncRead + AsyncWrite + Unpin> {
+    let stream = tcp_socket::connect(raddr, conn_opts).await?;
+
+    #[cfg(feature = "transport")]
+    {
+        use kaminari::AsyncConnect;
+        use kaminari::mix::{MixConnect, MixClientConf};
+
+        let plain;
+        let cc = match &conn_opts.transport {
+            Some((_, cc)) => cc,
+            None => {
+                plain = MixConnect::new(MixClientConf { ws: None, tls: None });
+                &plain
+            }
+        };
+
+        let mut buf = vec![0; buf_size()];
+        cc.connect(stream, &mut buf).await
+    }
+
+    #[cfg(not(feature = "transport"))]
+    Ok(stream)
+}
+
+/// Handshake with the client if a transport is provided.
+async fn accept(stream: TcpStream, conn_opts: &ConnectOpts) -> Result<impl AsyncRead + AsyncWrite + Unpin> {
+    #[cfg(feature = "transport")]
+    {
+        use kaminari::AsyncAccept;
+        use kaminari::mix::{MixAccept, MixServerConf};
+
+        let plain;
+        let ac = match &conn_opts.transport {
+            Some((ac, _)) => ac,
+            None => {
+                plain = MixAccept::new(MixServerConf { ws: None, tls: None });
+                &plain
+            }
+        };
+
+        let mut buf = vec![0; buf_size()];
+        ac.accept(stream, &mut buf).await
+    }
+
+    #[cfg(not(feature = "transport"))]
+    {
+        let _ = conn_opts;
+        Ok(stream)
+    }
+}
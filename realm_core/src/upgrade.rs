@@ -0,0 +1,250 @@
+//! Binary upgrade without closing listeners.
+//!
+//! The old process passes all its listeners to a new one,
+//! waits until it is ready, then stops accepting and drains
+//! existing relays before exiting.
+
+use std::future::{Future, pending};
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use futures::future::{select, Either};
+use once_cell::sync::Lazy;
+use tokio::sync::watch;
+
+static DRAIN: Lazy<watch::Sender<bool>> = Lazy::new(|| watch::channel(false).0);
+
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Stop accepting new connections or associations.
+pub fn drain() {
+    DRAIN.send_replace(true);
+}
+
+pub fn is_draining() -> bool {
+    *DRAIN.borrow()
+}
+
+/// Number of tcp relays and udp associations.
+pub fn active_relays() -> usize {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Wait until all relays are finished, or `timeout` seconds elapse.
+///
+/// Timeout = 0 means never timeout.
+/// Returns `false` if some relays are still active.
+pub async fn drained(timeout: usize) -> bool {
+    let wait = async {
+        while active_relays() != 0 {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    };
+    crate::time::timeoutfut(wait, timeout).await.is_ok()
+}
+
+/// Resolve `fut`, or never return once draining.
+///
+/// Listeners are not dropped when draining, since
+/// existing relays may still refer to them.
+pub(crate) async fn or_drain<F: Future>(fut: F) -> F::Output {
+    let mut rx = DRAIN.subscribe();
+    let draining = async move {
+        let _ = rx.wait_for(|x| *x).await;
+    };
+
+    match select(pin!(fut), pin!(draining)).await {
+        Either::Left((x, _)) => x,
+        Either::Right(_) => pending().await,
+    }
+}
+
+/// Counted as an active relay until dropped.
+pub(crate) struct Active(());
+
+impl Active {
+    pub(crate) fn new() -> Self {
+        ACTIVE.fetch_add(1, Ordering::Relaxed);
+        Active(())
+    }
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        ACTIVE.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(unix)]
+pub use handover::*;
+
+#[cfg(unix)]
+mod handover {
+    use std::env;
+    use std::fs::File;
+    use std::io::{Result, Error, ErrorKind};
+    use std::os::unix::fs::FileExt;
+    use std::os::unix::io::{AsFd, AsRawFd, OwnedFd, RawFd};
+    use std::process::{Child, Command};
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    use tokio::net::UnixDatagram;
+
+    /// Set to the passed descriptor where a new process should report its readiness.
+    const ENV_UPGRADE_NOTIFY: &str = "REALM_UPGRADE_NOTIFY";
+
+    /// Set to the passed descriptor of the pid file.
+    const ENV_UPGRADE_PIDFILE: &str = "REALM_UPGRADE_PIDFILE";
+
+    /// Set to the number and names of listeners passed to a new process.
+    const ENV_UPGRADE_FDS: &str = "REALM_UPGRADE_FDS";
+    const ENV_UPGRADE_FDNAMES: &str = "REALM_UPGRADE_FDNAMES";
+
+    static LISTENERS: Mutex<Vec<(OwnedFd, Option<String>)>> = Mutex::new(Vec::new());
+
+    static NOTIFY: Mutex<Option<OwnedFd>> = Mutex::new(None);
+
+    static PID_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+    /// Remember a listener, which is passed to the new process on upgrade.
+    pub(crate) fn register<T: AsFd>(lis: &T, name: Option<&str>) {
+        match lis.as_fd().try_clone_to_owned() {
+            Ok(fd) => LISTENERS.lock().unwrap().push((fd, name.map(String::from))),
+            Err(e) => log::warn!("[upgrade]failed to register listener: {}", e),
+        }
+    }
+
+    /// Keep the pid file open, which is rewritten with the pid
+    /// of the new process once it is ready, then passed to it.
+    ///
+    /// So the file need not be writable by an unprivileged user.
+    pub fn keep_pid_file(file: File) {
+        *PID_FILE.lock().unwrap() = Some(file);
+    }
+
+    /// Whether a pid file is kept, e.g. passed by the old process.
+    pub fn pid_file_kept() -> bool {
+        PID_FILE.lock().unwrap().is_some()
+    }
+
+    /// Start a new process with the same arguments and all listeners,
+    /// then wait until it is ready or `timeout` seconds elapse.
+    ///
+    /// `envs` are additionally passed to the new process.
+    /// Returns its pid.
+    pub async fn spawn_successor(envs: &[(&str, &str)], timeout: usize) -> Result<u32> {
+        let mut args = env::args_os();
+        let program = match args.next() {
+            Some(x) => x,
+            None => env::current_exe()?.into_os_string(),
+        };
+
+        // no one else could connect to an unnamed pair
+        let (notify, peer) = std::os::unix::net::UnixDatagram::pair()?;
+        notify.set_nonblocking(true)?;
+        let notify = UnixDatagram::from_std(notify)?;
+
+        let mut cmd = Command::new(program);
+        cmd.args(args)
+            .envs(envs.iter().copied())
+            // the watchdog is taken over once it becomes the main process
+            .env_remove("WATCHDOG_PID");
+
+        // the peer and the pid file are passed after all listeners,
+        // and closed here once the new process is spawned
+        let mut child = {
+            let mut fds = LISTENERS
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(fd, name)| Ok((fd.try_clone()?, name.clone())))
+                .collect::<Result<Vec<_>>>()?;
+            let mut pass = |var: &str, fd: OwnedFd| {
+                let n = realm_syscall::SD_LISTEN_FDS_START + fds.len() as RawFd;
+                cmd.env(var, n.to_string());
+                fds.push((fd, None));
+            };
+            pass(ENV_UPGRADE_NOTIFY, OwnedFd::from(peer));
+            if let Some(file) = PID_FILE.lock().unwrap().as_ref() {
+                pass(ENV_UPGRADE_PIDFILE, file.as_fd().try_clone_to_owned()?);
+            }
+
+            realm_syscall::pass_fds(&mut cmd, &fds, ENV_UPGRADE_FDS, ENV_UPGRADE_FDNAMES)
+                .and_then(|_dups| cmd.spawn())?
+        };
+        log::info!("[upgrade]spawned new process {}", child.id());
+
+        match wait_ready(&notify, &mut child, timeout).await {
+            Ok(()) => {
+                if let Some(file) = PID_FILE.lock().unwrap().as_ref() {
+                    let res = file
+                        .set_len(0)
+                        .and_then(|_| file.write_all_at(format!("{}\n", child.id()).as_bytes(), 0));
+                    if let Err(e) = res {
+                        log::warn!("[upgrade]failed to rewrite pid file: {}", e);
+                    }
+                }
+                Ok(child.id())
+            }
+            Err(e) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                Err(Error::new(e.kind(), format!("new process is not ready: {}", e)))
+            }
+        }
+    }
+
+    async fn wait_ready(notify: &UnixDatagram, child: &mut Child, timeout: usize) -> Result<()> {
+        let mut buf = [0u8; 64];
+        let deadline = Instant::now() + Duration::from_secs(timeout as u64);
+
+        while Instant::now() < deadline {
+            if let Some(status) = child.try_wait()? {
+                return Err(Error::other(format!("exited with {}", status)));
+            }
+
+            if let Ok(n) = tokio::time::timeout(Duration::from_millis(100), notify.recv(&mut buf)).await {
+                if buf[..n?].split(|x| *x == b'\n').any(|x| x == b"READY=1") {
+                    return Ok(());
+                }
+            }
+        }
+
+        Err(Error::new(ErrorKind::TimedOut, "timeout"))
+    }
+
+    /// Take listeners passed by the old process, if this one is started by an upgrade.
+    ///
+    /// The socket to report readiness and the pid file are kept aside.
+    pub(crate) fn take_fds() -> Result<Vec<(OwnedFd, Option<String>)>> {
+        let mut fds = realm_syscall::take_fds(ENV_UPGRADE_FDS, ENV_UPGRADE_FDNAMES)?;
+        if let Some(fd) = take_aside(&mut fds, ENV_UPGRADE_NOTIFY) {
+            *NOTIFY.lock().unwrap() = Some(fd);
+        }
+        if let Some(fd) = take_aside(&mut fds, ENV_UPGRADE_PIDFILE) {
+            keep_pid_file(File::from(fd));
+        }
+        Ok(fds)
+    }
+
+    /// Remove the descriptor whose number is set to `var`.
+    fn take_aside(fds: &mut Vec<(OwnedFd, Option<String>)>, var: &str) -> Option<OwnedFd> {
+        let n = env::var(var).ok().and_then(|x| x.parse::<RawFd>().ok());
+        env::remove_var(var);
+
+        let idx = fds.iter().position(|(fd, _)| Some(fd.as_raw_fd()) == n)?;
+        Some(fds.remove(idx).0)
+    }
+
+    /// Tell the old process this one is ready, if it is started by an upgrade.
+    pub(crate) fn notify_ready() {
+        if let Some(fd) = NOTIFY.lock().unwrap().take() {
+            let socket = std::os::unix::net::UnixDatagram::from(fd);
+            if let Err(e) = socket.send(b"READY=1") {
+                log::warn!("[upgrade]failed to notify old process: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,39 @@
+#![cfg(target_os = "linux")]
+
+use std::process::Command;
+use std::os::unix::process::CommandExt;
+
+use realm_core::realm_syscall::{Capability, drop_privileges, raise_ambient_capabilities};
+
+#[test]
+fn keep_caps() {
+    env_logger::init();
+
+    // requires root
+    if unsafe { libc::getuid() } != 0 {
+        log::warn!("skipped: not root");
+        return;
+    }
+
+    // switch to nobody in the child, which then executes another program
+    let mut cmd = Command::new("cat");
+    cmd.arg("/proc/self/status");
+    unsafe {
+        cmd.pre_exec(|| {
+            let caps = [Capability::NetBindService];
+            drop_privileges(65534, 65534, &caps)?;
+            raise_ambient_capabilities(&caps)
+        });
+    }
+
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    let status = String::from_utf8(output.stdout).unwrap();
+
+    // CAP_NET_BIND_SERVICE is kept across execve
+    for key in ["CapEff:", "CapAmb:"] {
+        let line = status.lines().find(|x| x.starts_with(key)).unwrap();
+        let caps = u64::from_str_radix(line[key.len()..].trim(), 16).unwrap();
+        assert_eq!(caps, 1 << 10, "{}", line);
+    }
+}
@@ -0,0 +1,80 @@
+#![cfg(unix)]
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::{TcpStream, TcpListener, UdpSocket};
+use tokio::time::sleep;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use realm_core::tcp::{self, run_tcp};
+use realm_core::udp::{self, run_udp};
+use realm_core::endpoint::{Endpoint, RemoteAddr};
+
+#[tokio::test]
+async fn prebind() {
+    env_logger::init();
+
+    let endpoint = || Endpoint {
+        laddr: "127.0.0.1:11800".parse().unwrap(),
+        raddr: "127.0.0.1:21800"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: Default::default(),
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    tcp::prebind(&endpoint()).unwrap();
+    udp::prebind(&endpoint()).unwrap();
+
+    // already bound
+    assert!(std::net::TcpListener::bind("127.0.0.1:11800").is_err());
+    assert!(std::net::UdpSocket::bind("127.0.0.1:11800").is_err());
+
+    // taken instead of binding again
+    tokio::spawn(run_tcp(endpoint()));
+    tokio::spawn(run_udp(endpoint()));
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+        let mut stream = TcpStream::connect("127.0.0.1:11800").await.unwrap();
+
+        let mut buf = vec![0; 32];
+        stream.write_all(b"Ping Ping Ping").await.unwrap();
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(b"Pong Pong Pong", &buf[..n]);
+    };
+
+    let task2 = async {
+        let lis = TcpListener::bind("127.0.0.1:21800").await.unwrap();
+        let (mut stream, _) = lis.accept().await.unwrap();
+
+        let mut buf = vec![0; 32];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(b"Ping Ping Ping", &buf[..n]);
+        stream.write_all(b"Pong Pong Pong").await.unwrap();
+    };
+
+    let task3 = async {
+        sleep(Duration::from_millis(500)).await;
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        let mut buf = vec![0; 32];
+        socket.send_to(b"Ping Ping Ping", "127.0.0.1:11800").await.unwrap();
+        let (n, _) = socket.recv_from(&mut buf).await.unwrap();
+        assert_eq!(b"Pong Pong Pong", &buf[..n]);
+    };
+
+    let task4 = async {
+        let socket = UdpSocket::bind("127.0.0.1:21800").await.unwrap();
+
+        let mut buf = vec![0; 32];
+        let (n, addr) = socket.recv_from(&mut buf).await.unwrap();
+        assert_eq!(b"Ping Ping Ping", &buf[..n]);
+        socket.send_to(b"Pong Pong Pong", addr).await.unwrap();
+    };
+
+    tokio::join!(task1, task2, task3, task4);
+}
@@ -0,0 +1,92 @@
+#![cfg(feature = "proxy")]
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::{TcpStream, TcpListener};
+use tokio::time::sleep;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use realm_core::tcp::run_tcp;
+use realm_core::endpoint::{Endpoint, RemoteAddr, ConnectOpts, ProxyOpts};
+use realm_core::proxy::{self, MAX_HEADER_LEN};
+
+#[tokio::test]
+async fn proxy_dst() {
+    env_logger::init();
+
+    let make_endpoint = |laddr: &str, version| Endpoint {
+        laddr: laddr.parse().unwrap(),
+        raddr: "127.0.0.1:22300"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: ConnectOpts {
+            proxy_opts: ProxyOpts {
+                send_proxy: true,
+                send_proxy_version: version,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    // the second one is a dual-stack listener,
+    // which sees ipv4-mapped addresses
+    tokio::spawn(run_tcp(make_endpoint("127.0.0.1:12300", 1)));
+    tokio::spawn(run_tcp(make_endpoint("[::]:12301", 2)));
+
+    let relays = ["127.0.0.1:12300", "127.0.0.1:12301"];
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+
+        let mut clients = Vec::new();
+        for relay in relays {
+            let mut stream = TcpStream::connect(relay).await.unwrap();
+            stream.write_all(b"Ping Ping Ping").await.unwrap();
+
+            let mut buf = vec![0; 32];
+            let n = stream.read(&mut buf).await.unwrap();
+            assert_eq!(b"Pong Pong Pong", &buf[..n]);
+            clients.push(stream.local_addr().unwrap());
+        }
+        clients
+    };
+
+    let task2 = async {
+        let lis = TcpListener::bind("127.0.0.1:22300").await.unwrap();
+
+        let mut received = Vec::new();
+        for _ in relays {
+            let (mut stream, _) = lis.accept().await.unwrap();
+
+            let mut buf = [0u8; MAX_HEADER_LEN];
+            let mut n = 0;
+            let (header, len) = loop {
+                n += stream.read(&mut buf[n..]).await.unwrap();
+                if let Some(x) = proxy::parse(&buf[..n]).unwrap() {
+                    break x;
+                }
+            };
+            received.push(header.inet_addrs().unwrap());
+
+            let mut data = buf[len..n].to_vec();
+            while data.len() < 14 {
+                let m = stream.read(&mut buf).await.unwrap();
+                data.extend_from_slice(&buf[..m]);
+            }
+            assert_eq!(b"Ping Ping Ping", &data[..]);
+            stream.write_all(b"Pong Pong Pong").await.unwrap();
+        }
+        received
+    };
+
+    let (clients, received) = tokio::join!(task1, task2);
+
+    for ((client, relay), addrs) in clients.into_iter().zip(relays).zip(received) {
+        assert_eq!(addrs, (client, relay.parse().unwrap()));
+    }
+}
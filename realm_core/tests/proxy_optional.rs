@@ -0,0 +1,154 @@
+#![cfg(feature = "proxy")]
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::{TcpStream, TcpListener};
+use tokio::time::{sleep, timeout};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use realm_core::tcp::run_tcp;
+use realm_core::endpoint::{Endpoint, RemoteAddr, ConnectOpts, ProxyOpts};
+use realm_core::proxy::{self, v2, Command, Protocol, Addrs, MAX_HEADER_LEN};
+
+#[tokio::test]
+async fn proxy_optional() {
+    env_logger::init();
+
+    let make_endpoint = |laddr: &str, accept_proxy_from: &str, optional: bool, send_proxy: bool| Endpoint {
+        laddr: laddr.parse().unwrap(),
+        raddr: "127.0.0.1:22200"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: ConnectOpts {
+            proxy_opts: ProxyOpts {
+                send_proxy,
+                send_proxy_version: 2,
+                accept_proxy: true,
+                accept_proxy_timeout: 5,
+                accept_proxy_optional: optional,
+                accept_proxy_from: vec![accept_proxy_from.parse().unwrap()],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    // local peers are not trusted by 12201, 12202 and 12204,
+    // and are closed by the latter two which require a header
+    tokio::spawn(run_tcp(make_endpoint("127.0.0.1:12200", "127.0.0.0/8", true, true)));
+    tokio::spawn(run_tcp(make_endpoint("127.0.0.1:12201", "192.0.2.0/24", true, true)));
+    tokio::spawn(run_tcp(make_endpoint("127.0.0.1:12202", "192.0.2.0/24", false, true)));
+    tokio::spawn(run_tcp(make_endpoint("127.0.0.1:12203", "127.0.0.0/8", true, false)));
+    tokio::spawn(run_tcp(make_endpoint("127.0.0.1:12204", "192.0.2.0/24", false, false)));
+
+    let src: SocketAddr = "192.0.2.1:1000".parse().unwrap();
+    let dst: SocketAddr = "192.0.2.2:2000".parse().unwrap();
+
+    let mut header = [0u8; MAX_HEADER_LEN];
+    let header_len = v2::encode(
+        &mut header,
+        Command::Proxy,
+        Protocol::Stream,
+        &Addrs::Inet { src, dst },
+        &[],
+    )
+    .unwrap();
+    let header = &header[..header_len];
+
+    // (relay, send a header, header is consumed, relay sends a header)
+    let cases = [
+        ("127.0.0.1:12200", false, false, true),
+        ("127.0.0.1:12200", true, true, true),
+        ("127.0.0.1:12201", true, false, true),
+        ("127.0.0.1:12203", true, true, false),
+        ("127.0.0.1:12203", false, false, false),
+    ];
+
+    // (relay, send a header)
+    let rejected = [("127.0.0.1:12202", true), ("127.0.0.1:12204", false)];
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+
+        let mut clients = Vec::new();
+        for (relay, send_header, ..) in cases {
+            let mut stream = TcpStream::connect(relay).await.unwrap();
+            if send_header {
+                stream.write_all(header).await.unwrap();
+            }
+            stream.write_all(b"Ping Ping Ping").await.unwrap();
+
+            let mut buf = vec![0; 32];
+            let n = stream.read(&mut buf).await.unwrap();
+            assert_eq!(b"Pong Pong Pong", &buf[..n]);
+            clients.push(stream.local_addr().unwrap());
+        }
+
+        for (relay, send_header) in rejected {
+            let mut stream = TcpStream::connect(relay).await.unwrap();
+            if send_header {
+                let _ = stream.write_all(header).await;
+            }
+            let _ = stream.write_all(b"Ping Ping Ping").await;
+
+            let mut buf = vec![0; 32];
+            assert!(matches!(stream.read(&mut buf).await, Ok(0) | Err(_)));
+        }
+        clients
+    };
+
+    let task2 = async {
+        let lis = TcpListener::bind("127.0.0.1:22200").await.unwrap();
+
+        let mut received = Vec::new();
+        for (_, send_header, consumed, sent) in cases {
+            let (mut stream, _) = lis.accept().await.unwrap();
+
+            let mut buf = [0u8; MAX_HEADER_LEN];
+            let mut n = 0;
+            let (addrs, len) = if sent {
+                let (parsed, len) = loop {
+                    n += stream.read(&mut buf[n..]).await.unwrap();
+                    if let Some(x) = proxy::parse(&buf[..n]).unwrap() {
+                        break x;
+                    }
+                };
+                (parsed.inet_addrs(), len)
+            } else {
+                (None, 0)
+            };
+
+            let mut data = buf[len..n].to_vec();
+            let expect = if send_header && !consumed { header.len() } else { 0 } + 14;
+            while data.len() < expect {
+                let m = stream.read(&mut buf).await.unwrap();
+                data.extend_from_slice(&buf[..m]);
+            }
+            if send_header && !consumed {
+                assert_eq!(header, &data[..header.len()]);
+            }
+            assert_eq!(b"Ping Ping Ping", &data[expect - 14..]);
+            stream.write_all(b"Pong Pong Pong").await.unwrap();
+
+            received.push(addrs);
+        }
+
+        // rejected ones are never relayed
+        assert!(timeout(Duration::from_secs(1), lis.accept()).await.is_err());
+        received
+    };
+
+    let (clients, received) = tokio::join!(task1, task2);
+
+    // fall back to the peer address
+    assert_eq!(received[0], Some((clients[0], "127.0.0.1:12200".parse().unwrap())));
+    assert_eq!(received[1], Some((src, dst)));
+    // not trusted, the same as a peer which does not speak proxy-protocol
+    assert_eq!(received[2], Some((clients[2], "127.0.0.1:12201".parse().unwrap())));
+    // never forward a header without send_proxy
+    assert_eq!(received[3..], [None, None]);
+}
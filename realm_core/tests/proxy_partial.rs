@@ -0,0 +1,88 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::{TcpStream, TcpListener};
+use tokio::time::sleep;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use realm_core::tcp::run_tcp;
+use realm_core::endpoint::{Endpoint, RemoteAddr, ConnectOpts};
+
+#[cfg(feature = "proxy")]
+use realm_core::endpoint::ProxyOpts;
+#[cfg(feature = "proxy")]
+use realm_core::proxy::{v2, Command, Protocol, Addrs};
+
+#[tokio::test]
+#[cfg(feature = "proxy")]
+async fn proxy_partial() {
+    env_logger::init();
+
+    let endpoint = Endpoint {
+        laddr: "127.0.0.1:11900".parse().unwrap(),
+        raddr: "127.0.0.1:21900"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: ConnectOpts {
+            proxy_opts: ProxyOpts {
+                send_proxy: true,
+                send_proxy_version: 1,
+                accept_proxy: true,
+                accept_proxy_timeout: 5,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    tokio::spawn(run_tcp(endpoint));
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+        let mut stream = TcpStream::connect("127.0.0.1:11900").await.unwrap();
+        stream.set_nodelay(true).unwrap();
+
+        // the header arrives in pieces
+        let mut buf = [0u8; 64];
+        let n = v2::encode(
+            &mut buf,
+            Command::Proxy,
+            Protocol::Stream,
+            &Addrs::Inet {
+                src: "192.0.2.1:1000".parse().unwrap(),
+                dst: "192.0.2.2:2000".parse().unwrap(),
+            },
+            &[],
+        )
+        .unwrap();
+        for chunk in [&buf[..5], &buf[5..14], &buf[14..20], &buf[20..n]] {
+            stream.write_all(chunk).await.unwrap();
+            sleep(Duration::from_millis(100)).await;
+        }
+        stream.write_all(b"Ping Ping Ping").await.unwrap();
+
+        let mut buf = vec![0; 32];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(b"Pong Pong Pong", &buf[..n]);
+    };
+
+    let task2 = async {
+        let lis = TcpListener::bind("127.0.0.1:21900").await.unwrap();
+        let (mut stream, _) = lis.accept().await.unwrap();
+
+        let header = b"PROXY TCP4 192.0.2.1 192.0.2.2 1000 2000\r\n";
+        let mut buf = vec![0; header.len()];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, header);
+
+        let mut buf = vec![0; 32];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(b"Ping Ping Ping", &buf[..n]);
+        stream.write_all(b"Pong Pong Pong").await.unwrap();
+    };
+
+    tokio::join!(task1, task2);
+}
@@ -0,0 +1,162 @@
+#![cfg(all(
+    feature = "proxy",
+    any(feature = "transport-tls-ring", feature = "transport-tls-awslc")
+))]
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::{TcpStream, TcpListener};
+use tokio::time::sleep;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use realm_core::tcp::run_tcp;
+use realm_core::endpoint::{Endpoint, RemoteAddr, ConnectOpts, ProxyOpts};
+use realm_core::proxy::{self, v2, Command, Protocol, Addrs, MAX_HEADER_LEN};
+use realm_core::proxy::v2::{tlv, Tlvs, TlvWriter};
+
+#[tokio::test]
+async fn proxy_tlvs() {
+    use realm_core::kaminari::opt::{get_tls_client_conf, get_tls_server_conf};
+    use realm_core::kaminari::mix::{MixAccept, MixConnect, MixClientConf, MixServerConf};
+
+    env_logger::init();
+    realm_core::kaminari::install_tls_provider();
+
+    let client = (
+        MixAccept::new(MixServerConf { ws: None, tls: None }),
+        MixConnect::new(MixClientConf {
+            ws: None,
+            tls: get_tls_client_conf("tls;sni=example.com;insecure"),
+        }),
+    );
+    let server = (
+        MixAccept::new(MixServerConf {
+            ws: None,
+            tls: get_tls_server_conf("tls;servername=example.com"),
+        }),
+        MixConnect::new(MixClientConf { ws: None, tls: None }),
+    );
+
+    // client -> realm1 -[tls]-> realm2 -> server
+    let endpoint1 = Endpoint {
+        laddr: "127.0.0.1:12000".parse().unwrap(),
+        raddr: "127.0.0.1:12001"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: ConnectOpts {
+            proxy_opts: ProxyOpts {
+                send_proxy: true,
+                send_proxy_version: 2,
+                accept_proxy: true,
+                accept_proxy_timeout: 5,
+                send_proxy_tlvs: "forward".parse().unwrap(),
+                ..Default::default()
+            },
+            transport: Some(client),
+            ..Default::default()
+        },
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    let endpoint2 = Endpoint {
+        laddr: "127.0.0.1:12001".parse().unwrap(),
+        raddr: "127.0.0.1:22000"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: ConnectOpts {
+            proxy_opts: ProxyOpts {
+                send_proxy: true,
+                send_proxy_version: 2,
+                accept_proxy: true,
+                accept_proxy_timeout: 5,
+                send_proxy_tlvs: "forward,authority,alpn,unique-id,ssl".parse().unwrap(),
+                ..Default::default()
+            },
+            transport: Some(server),
+            ..Default::default()
+        },
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    tokio::spawn(run_tcp(endpoint1));
+    tokio::spawn(run_tcp(endpoint2));
+
+    let src: SocketAddr = "192.0.2.1:1000".parse().unwrap();
+    let dst: SocketAddr = "192.0.2.2:2000".parse().unwrap();
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+        let mut stream = TcpStream::connect("127.0.0.1:12000").await.unwrap();
+
+        let mut tlvs = [0u8; 64];
+        let mut writer = TlvWriter::new(&mut tlvs);
+        writer.push(tlv::AUTHORITY, b"client.example").unwrap();
+        writer.push(tlv::CRC32C, &[0, 0, 0, 0]).unwrap();
+        writer.push(0xe0, b"custom").unwrap();
+
+        let mut buf = [0u8; MAX_HEADER_LEN];
+        let addrs = Addrs::Inet { src, dst };
+        let n = v2::encode(&mut buf, Command::Proxy, Protocol::Stream, &addrs, writer.as_bytes()).unwrap();
+        stream.write_all(&buf[..n]).await.unwrap();
+        stream.write_all(b"Ping Ping Ping").await.unwrap();
+
+        let mut buf = vec![0; 32];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(b"Pong Pong Pong", &buf[..n]);
+    };
+
+    let task2 = async {
+        let lis = TcpListener::bind("127.0.0.1:22000").await.unwrap();
+        let (mut stream, _) = lis.accept().await.unwrap();
+
+        let mut buf = [0u8; MAX_HEADER_LEN];
+        let mut n = 0;
+        let (header, len) = loop {
+            n += stream.read(&mut buf[n..]).await.unwrap();
+            if let Some(x) = proxy::parse(&buf[..n]).unwrap() {
+                break x;
+            }
+        };
+        assert_eq!(header.inet_addrs(), Some((src, dst)));
+
+        let tlvs: Vec<_> = header.tlvs().collect();
+        log::debug!("tlvs: {:x?}", tlvs);
+        let find = |ty| {
+            tlvs.iter()
+                .filter(|(x, _)| *x == ty)
+                .map(|(_, v)| *v)
+                .collect::<Vec<_>>()
+        };
+
+        // ours replace the forwarded
+        assert_eq!(find(tlv::AUTHORITY), vec![&b"example.com"[..]]);
+        assert_eq!(find(0xe0), vec![&b"custom"[..]]);
+        assert!(find(tlv::CRC32C).is_empty());
+        // not negotiated
+        assert!(find(tlv::ALPN).is_empty());
+        assert_eq!(find(tlv::UNIQUE_ID).len(), 1);
+        assert_eq!(find(tlv::UNIQUE_ID)[0].len(), 16);
+
+        let ssl = find(tlv::SSL);
+        assert_eq!(ssl.len(), 1);
+        assert_eq!(&ssl[0][..5], &[tlv::CLIENT_SSL, 0, 0, 0, 0]);
+        let subs: Vec<_> = Tlvs::new(&ssl[0][5..]).collect();
+        assert_eq!(subs[0], (tlv::SUBTYPE_SSL_VERSION, &b"TLSv1.3"[..]));
+        assert_eq!(subs[1].0, tlv::SUBTYPE_SSL_CIPHER);
+
+        let mut data = buf[len..n].to_vec();
+        while data.len() < 14 {
+            let m = stream.read(&mut buf).await.unwrap();
+            data.extend_from_slice(&buf[..m]);
+        }
+        assert_eq!(b"Ping Ping Ping", &data[..]);
+        stream.write_all(b"Pong Pong Pong").await.unwrap();
+    };
+
+    tokio::join!(task1, task2);
+}
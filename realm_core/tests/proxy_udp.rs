@@ -0,0 +1,93 @@
+#![cfg(feature = "proxy")]
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+use tokio::time::sleep;
+
+use realm_core::udp::run_udp;
+use realm_core::endpoint::{Endpoint, RemoteAddr, ConnectOpts, ProxyOpts, UdpProxy};
+use realm_core::proxy::{self, Protocol};
+
+#[tokio::test]
+async fn proxy_udp() {
+    env_logger::init();
+
+    // client -> realm1 -> realm2 -> server
+    let endpoint1 = Endpoint {
+        laddr: "127.0.0.1:12100".parse().unwrap(),
+        raddr: "127.0.0.1:12101"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: ConnectOpts {
+            proxy_opts: ProxyOpts {
+                send_proxy_udp: UdpProxy::Every,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    let endpoint2 = Endpoint {
+        laddr: "127.0.0.1:12101".parse().unwrap(),
+        raddr: "127.0.0.1:22100"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: ConnectOpts {
+            proxy_opts: ProxyOpts {
+                send_proxy_udp: UdpProxy::First,
+                accept_proxy_udp: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    tokio::spawn(run_udp(endpoint1));
+    tokio::spawn(run_udp(endpoint2));
+
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let client_addr = client.local_addr().unwrap();
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+
+        let mut buf = vec![0; 64];
+        for _ in 0..3 {
+            client.send_to(b"Ping Ping Ping", "127.0.0.1:12100").await.unwrap();
+            let (n, _) = client.recv_from(&mut buf).await.unwrap();
+            assert_eq!(b"Pong Pong Pong", &buf[..n]);
+        }
+    };
+
+    let task2 = async {
+        let socket = UdpSocket::bind("127.0.0.1:22100").await.unwrap();
+        let mut buf = vec![0; 256];
+
+        // the first one carries the original addresses
+        let (n, peer) = socket.recv_from(&mut buf).await.unwrap();
+        let (header, len) = proxy::parse(&buf[..n]).unwrap().unwrap();
+        assert_eq!(header.protocol, Protocol::Dgram);
+        assert_eq!(
+            header.inet_addrs(),
+            Some((client_addr, "127.0.0.1:12100".parse().unwrap()))
+        );
+        assert_eq!(b"Ping Ping Ping", &buf[len..n]);
+        socket.send_to(b"Pong Pong Pong", peer).await.unwrap();
+
+        for _ in 0..2 {
+            let (n, peer) = socket.recv_from(&mut buf).await.unwrap();
+            assert_eq!(b"Ping Ping Ping", &buf[..n]);
+            socket.send_to(b"Pong Pong Pong", peer).await.unwrap();
+        }
+    };
+
+    tokio::join!(task1, task2);
+}
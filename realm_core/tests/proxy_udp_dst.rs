@@ -0,0 +1,78 @@
+#![cfg(all(target_os = "linux", feature = "proxy"))]
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+use tokio::time::sleep;
+
+use realm_core::udp::run_udp;
+use realm_core::endpoint::{Endpoint, RemoteAddr, ConnectOpts, ProxyOpts, UdpProxy};
+use realm_core::proxy;
+
+#[tokio::test]
+async fn proxy_udp_dst() {
+    env_logger::init();
+
+    let make_endpoint = |laddr: &str| Endpoint {
+        laddr: laddr.parse().unwrap(),
+        raddr: "127.0.0.1:22302"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: ConnectOpts {
+            proxy_opts: ProxyOpts {
+                send_proxy_udp: UdpProxy::Every,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    // wildcard listeners, the second one is dual-stack
+    tokio::spawn(run_udp(make_endpoint("0.0.0.0:12302")));
+    tokio::spawn(run_udp(make_endpoint("[::]:12303")));
+
+    // destinations sent to by each client
+    let dsts = ["127.0.0.1:12302", "127.0.0.2:12302", "127.0.0.3:12303", "[::1]:12303"];
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+
+        let mut clients = Vec::new();
+        let mut buf = vec![0; 64];
+        for dst in dsts {
+            let bind = if dst.starts_with('[') { "[::1]:0" } else { "127.0.0.1:0" };
+            let client = UdpSocket::bind(bind).await.unwrap();
+            client.send_to(b"Ping Ping Ping", dst).await.unwrap();
+            let (n, _) = client.recv_from(&mut buf).await.unwrap();
+            assert_eq!(b"Pong Pong Pong", &buf[..n]);
+            clients.push(client.local_addr().unwrap());
+        }
+        clients
+    };
+
+    let task2 = async {
+        let socket = UdpSocket::bind("127.0.0.1:22302").await.unwrap();
+        let mut buf = vec![0; 256];
+
+        let mut received = Vec::new();
+        for _ in dsts {
+            let (n, peer) = socket.recv_from(&mut buf).await.unwrap();
+            let (header, len) = proxy::parse(&buf[..n]).unwrap().unwrap();
+            assert_eq!(b"Ping Ping Ping", &buf[len..n]);
+            socket.send_to(b"Pong Pong Pong", peer).await.unwrap();
+            received.push(header.inet_addrs().unwrap());
+        }
+        received
+    };
+
+    let (clients, received) = tokio::join!(task1, task2);
+
+    // not the wildcard address of the listener
+    for ((client, dst), addrs) in clients.into_iter().zip(dsts).zip(received) {
+        assert_eq!(addrs, (client, dst.parse().unwrap()));
+    }
+}
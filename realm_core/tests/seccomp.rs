@@ -0,0 +1,62 @@
+#![cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+
+use realm_core::realm_syscall::{apply_seccomp, SeccompMode};
+
+// run in a forked child, which is killed by SIGSYS if an unlisted syscall is made
+fn run_in_child(allow_upgrade: bool, f: fn() -> bool) -> Option<libc::c_int> {
+    match unsafe { libc::fork() } {
+        -1 => panic!("failed to fork: {}", std::io::Error::last_os_error()),
+        0 => {
+            let ok = apply_seccomp(SeccompMode::Enforce, allow_upgrade).is_ok() && f();
+            unsafe { libc::_exit(if ok { 0 } else { 1 }) }
+        }
+        pid => {
+            let mut status = 0;
+            assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+            if libc::WIFSIGNALED(status) {
+                Some(libc::WTERMSIG(status))
+            } else {
+                assert_eq!(libc::WEXITSTATUS(status), 0);
+                None
+            }
+        }
+    }
+}
+
+fn spawn_thread() -> bool {
+    std::thread::spawn(|| unsafe { libc::getpid() } > 0).join().unwrap()
+}
+
+fn fork() -> bool {
+    match unsafe { libc::fork() } {
+        0 => unsafe { libc::_exit(0) },
+        pid => pid > 0 && unsafe { libc::waitpid(pid, std::ptr::null_mut(), 0) } == pid,
+    }
+}
+
+fn chdir() -> bool {
+    unsafe { libc::chdir(c"/".as_ptr()) == 0 }
+}
+
+fn set_dumpable() -> bool {
+    unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) == 0 }
+}
+
+fn set_name() -> bool {
+    unsafe { libc::prctl(libc::PR_SET_NAME, c"realm".as_ptr(), 0, 0, 0) == 0 }
+}
+
+#[test]
+fn seccomp() {
+    env_logger::init();
+
+    // threads are allowed, processes are not
+    assert_eq!(run_in_child(false, spawn_thread), None);
+    assert_eq!(run_in_child(false, fork), Some(libc::SIGSYS));
+    assert_eq!(run_in_child(true, fork), None);
+
+    // unlisted syscalls or prctl options
+    assert_eq!(run_in_child(false, chdir), Some(libc::SIGSYS));
+    assert_eq!(run_in_child(false, set_dumpable), Some(libc::SIGSYS));
+    assert_eq!(run_in_child(false, set_name), None);
+}
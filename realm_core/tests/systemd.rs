@@ -0,0 +1,138 @@
+#![cfg(unix)]
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::{TcpStream, TcpListener, UdpSocket, UnixDatagram};
+use tokio::time::timeout;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use realm_core::tcp::run_tcp;
+use realm_core::udp::run_udp;
+use realm_core::systemd;
+use realm_core::endpoint::{Endpoint, RemoteAddr, BindOpts};
+
+#[tokio::test]
+async fn systemd() {
+    env_logger::init();
+
+    // fds passed to another process are ignored
+    std::env::set_var("LISTEN_PID", "1");
+    std::env::set_var("LISTEN_FDS", "2");
+    assert_eq!(systemd::inherit_listen_fds().unwrap(), 0);
+    assert!(std::env::var("LISTEN_FDS").is_err());
+
+    // so are fds without a pid
+    std::env::set_var("LISTEN_FDS", "2");
+    assert_eq!(systemd::inherit_listen_fds().unwrap(), 0);
+    assert!(std::env::var("LISTEN_FDS").is_err());
+
+    // fake notify socket
+    let path = std::env::temp_dir().join(format!("realm-notify-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let notify = UnixDatagram::bind(&path).unwrap();
+    std::env::set_var("NOTIFY_SOCKET", &path);
+
+    async fn recv(notify: &UnixDatagram) -> String {
+        let mut buf = vec![0; 64];
+        let n = timeout(Duration::from_secs(2), notify.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        String::from_utf8(buf[..n].to_vec()).unwrap()
+    }
+
+    // matched by address, binding again would fail
+    let lis1 = std::net::TcpListener::bind("127.0.0.1:11500").unwrap();
+    systemd::inherit(lis1.into(), None);
+
+    // matched by name
+    let lis2 = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let laddr2 = lis2.local_addr().unwrap();
+    systemd::inherit(lis2.into(), Some(String::from("named")));
+
+    let lis3 = std::net::UdpSocket::bind("127.0.0.1:11502").unwrap();
+    systemd::inherit(lis3.into(), None);
+
+    let endpoint = |laddr: &str, fd_name: Option<&str>| Endpoint {
+        laddr: laddr.parse().unwrap(),
+        raddr: "127.0.0.1:21500"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: Default::default(),
+        bind_opts: BindOpts {
+            fd_name: fd_name.map(String::from),
+            ..Default::default()
+        },
+        extra_raddrs: Vec::new(),
+    };
+
+    systemd::expect_listening(3);
+    tokio::spawn(run_tcp(endpoint("127.0.0.1:11500", None)));
+    tokio::spawn(run_tcp(endpoint("127.0.0.1:11501", Some("named"))));
+    tokio::spawn(run_udp(endpoint("127.0.0.1:11502", None)));
+
+    assert_eq!(recv(&notify).await, "READY=1");
+
+    let task1 = async {
+        for addr in ["127.0.0.1:11500".parse().unwrap(), laddr2] {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let mut buf = vec![0; 32];
+            stream.write_all(b"Ping Ping Ping").await.unwrap();
+            let n = stream.read(&mut buf).await.unwrap();
+            assert_eq!(b"Pong Pong Pong", &buf[..n]);
+        }
+    };
+
+    let task2 = async {
+        let lis = TcpListener::bind("127.0.0.1:21500").await.unwrap();
+
+        for _ in 0..2 {
+            let (mut stream, _) = lis.accept().await.unwrap();
+            let mut buf = vec![0; 32];
+            let n = stream.read(&mut buf).await.unwrap();
+            assert_eq!(b"Ping Ping Ping", &buf[..n]);
+            stream.write_all(b"Pong Pong Pong").await.unwrap();
+        }
+    };
+
+    let task3 = async {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut buf = vec![0; 32];
+        socket.send_to(b"Ping Ping Ping", "127.0.0.1:11502").await.unwrap();
+        let (n, _) = socket.recv_from(&mut buf).await.unwrap();
+        assert_eq!(b"Pong Pong Pong", &buf[..n]);
+    };
+
+    let task4 = async {
+        let socket = UdpSocket::bind("127.0.0.1:21500").await.unwrap();
+        let mut buf = vec![0; 32];
+        let (n, addr) = socket.recv_from(&mut buf).await.unwrap();
+        assert_eq!(b"Ping Ping Ping", &buf[..n]);
+        socket.send_to(b"Pong Pong Pong", addr).await.unwrap();
+    };
+
+    tokio::join!(task1, task2, task3, task4);
+
+    // ping at half of the interval
+    std::env::set_var("WATCHDOG_USEC", "400000");
+    tokio::spawn(systemd::watchdog());
+    assert_eq!(recv(&notify).await, "WATCHDOG=1");
+    assert_eq!(recv(&notify).await, "WATCHDOG=1");
+
+    systemd::notify_stopping();
+    let mut buf = vec![0; 64];
+    loop {
+        let n = timeout(Duration::from_secs(1), notify.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        if &buf[..n] != b"WATCHDOG=1" {
+            assert_eq!(&buf[..n], b"STOPPING=1");
+            break;
+        }
+    }
+
+    let _ = std::fs::remove_file(&path);
+}
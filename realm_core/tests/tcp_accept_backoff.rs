@@ -0,0 +1,70 @@
+#![cfg(target_os = "linux")]
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::{TcpStream, TcpListener};
+use tokio::time::{sleep, timeout};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use realm_core::tcp::run_tcp;
+use realm_core::endpoint::{Endpoint, RemoteAddr};
+use realm_core::realm_syscall::set_nofile_limit;
+
+#[tokio::test]
+async fn tcp_accept_backoff() {
+    env_logger::init();
+    let endpoint = Endpoint {
+        laddr: "127.0.0.1:10700".parse().unwrap(),
+        raddr: "127.0.0.1:20700"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: Default::default(),
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    tokio::spawn(run_tcp(endpoint));
+
+    // echo server
+    tokio::spawn(async {
+        let lis = TcpListener::bind("127.0.0.1:20700").await.unwrap();
+        loop {
+            let Ok((mut stream, _)) = lis.accept().await else {
+                continue;
+            };
+            tokio::spawn(async move {
+                let (mut rd, mut wr) = stream.split();
+                let _ = tokio::io::copy(&mut rd, &mut wr).await;
+            });
+        }
+    });
+
+    sleep(Duration::from_millis(500)).await;
+
+    // run out of fds, so that the relay fails to accept
+    let opened = std::fs::read_dir("/proc/self/fd").unwrap().count() as u64;
+    set_nofile_limit(opened + 16).unwrap();
+
+    let mut clients = Vec::new();
+    while let Ok(stream) = TcpStream::connect("127.0.0.1:10700").await {
+        clients.push(stream);
+    }
+    sleep(Duration::from_millis(200)).await;
+
+    // the listener should survive, once fds are released
+    drop(clients);
+    sleep(Duration::from_millis(200)).await;
+
+    let echo = async {
+        let mut stream = TcpStream::connect("127.0.0.1:10700").await.unwrap();
+        let mut buf = [0; 32];
+        for _ in 0..10 {
+            stream.write_all(b"Ping Ping Ping").await.unwrap();
+            stream.read_exact(&mut buf[..14]).await.unwrap();
+            assert_eq!(b"Ping Ping Ping", &buf[..14]);
+        }
+    };
+    timeout(Duration::from_secs(5), echo).await.unwrap();
+}
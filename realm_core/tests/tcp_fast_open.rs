@@ -0,0 +1,113 @@
+#![cfg(target_os = "linux")]
+
+use std::net::SocketAddr;
+use std::os::fd::AsRawFd;
+use std::time::Duration;
+
+use tokio::net::{TcpStream, TcpListener};
+use tokio::time::sleep;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use realm_core::tcp::run_tcp;
+use realm_core::endpoint::{Endpoint, RemoteAddr, BindOpts, ConnectOpts};
+
+const CONNECTIONS: usize = 3;
+
+// from linux/tcp.h
+const TCPI_OPT_SYN_DATA: u8 = 32;
+
+/// Whether data was carried in the SYN packet of an accepted connection.
+fn syn_data(stream: &TcpStream) -> bool {
+    let mut info: libc::tcp_info = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            &mut info as *mut _ as *mut _,
+            &mut len,
+        )
+    };
+    assert_eq!(ret, 0);
+    info.tcpi_options & TCPI_OPT_SYN_DATA != 0
+}
+
+#[tokio::test]
+async fn tcp_fast_open() {
+    env_logger::init();
+
+    // enable both sides of fast open in a new network namespace,
+    // which requires CAP_SYS_ADMIN, the runtime runs on this thread only
+    let enabled = unsafe { libc::unshare(libc::CLONE_NEWNET) } == 0
+        && std::process::Command::new("ip")
+            .args(["link", "set", "lo", "up"])
+            .status()
+            .is_ok_and(|x| x.success())
+        && std::fs::write("/proc/sys/net/ipv4/tcp_fastopen", "3").is_ok();
+    if !enabled {
+        log::warn!("fast open is not verified: {}", std::io::Error::last_os_error());
+    }
+
+    let endpoint = Endpoint {
+        laddr: "127.0.0.1:11000".parse().unwrap(),
+        raddr: "127.0.0.1:21000"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: ConnectOpts {
+            send_fast_open: true,
+            ..Default::default()
+        },
+        bind_opts: BindOpts {
+            accept_fast_open: true,
+            ..Default::default()
+        },
+        extra_raddrs: Vec::new(),
+    };
+
+    tokio::spawn(run_tcp(endpoint));
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+
+        for _ in 0..CONNECTIONS {
+            let mut stream = TcpStream::connect("127.0.0.1:11000").await.unwrap();
+            let mut buf = vec![0; 32];
+
+            for _ in 0..20 {
+                stream.write_all(b"Ping Ping Ping").await.unwrap();
+                let n = stream.read(&mut buf).await.unwrap();
+                log::debug!("a got: {:?}", std::str::from_utf8(&buf[..n]).unwrap());
+                assert_eq!(b"Pong Pong Pong", &buf[..n]);
+            }
+        }
+    };
+
+    let task2 = async {
+        let lis = TcpListener::bind("127.0.0.1:21000").await.unwrap();
+        realm_core::realm_syscall::set_tcp_fastopen(&lis, 16).unwrap();
+
+        let mut accepted = Vec::new();
+        for _ in 0..CONNECTIONS {
+            let (mut stream, _) = lis.accept().await.unwrap();
+            let mut buf = vec![0; 32];
+
+            for _ in 0..20 {
+                let n = stream.read(&mut buf).await.unwrap();
+                log::debug!("b got: {:?}", std::str::from_utf8(&buf[..n]).unwrap());
+                assert_eq!(b"Ping Ping Ping", &buf[..n]);
+                stream.write_all(b"Pong Pong Pong").await.unwrap();
+            }
+            accepted.push(syn_data(&stream));
+        }
+        accepted
+    };
+
+    let (_, accepted) = tokio::join!(task1, task2);
+
+    // the first one gets a cookie, then the relay sends data in SYN
+    if enabled {
+        assert_eq!(accepted, [false, true, true]);
+    }
+}
@@ -0,0 +1,119 @@
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use tokio::net::{TcpStream, TcpListener};
+use tokio::time::{sleep, timeout};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use realm_core::tcp::run_tcp;
+use realm_core::endpoint::{Endpoint, RemoteAddr, ConnectOpts};
+
+#[tokio::test]
+async fn tcp_idle_timeout() {
+    env_logger::init();
+
+    // close after idle for 1s
+    let endpoint1 = Endpoint {
+        laddr: "127.0.0.1:11100".parse().unwrap(),
+        raddr: "127.0.0.1:21100"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: ConnectOpts {
+            tcp_idle_timeout: 1,
+            ..Default::default()
+        },
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    // close after 2s, even if it is busy
+    let endpoint2 = Endpoint {
+        laddr: "127.0.0.1:11101".parse().unwrap(),
+        raddr: "127.0.0.1:21100"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: ConnectOpts {
+            tcp_idle_timeout: 1,
+            tcp_max_lifetime: 2,
+            ..Default::default()
+        },
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    tokio::spawn(run_tcp(endpoint1));
+    tokio::spawn(run_tcp(endpoint2));
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+        let mut stream = TcpStream::connect("127.0.0.1:11100").await.unwrap();
+
+        let mut buf = vec![0; 32];
+
+        // keep it busy for a while
+        for _ in 0..6 {
+            stream.write_all(b"Ping Ping Ping").await.unwrap();
+            let n = stream.read(&mut buf).await.unwrap();
+            assert_eq!(b"Pong Pong Pong", &buf[..n]);
+            sleep(Duration::from_millis(300)).await;
+        }
+
+        // then idle
+        let start = Instant::now();
+        let n = timeout(Duration::from_secs(3), stream.read(&mut buf))
+            .await
+            .unwrap()
+            .unwrap_or(0);
+        assert_eq!(n, 0);
+        assert!(start.elapsed() >= Duration::from_millis(500));
+    };
+
+    let task2 = async {
+        sleep(Duration::from_millis(500)).await;
+        let mut stream = TcpStream::connect("127.0.0.1:11101").await.unwrap();
+
+        let mut buf = vec![0; 32];
+
+        let start = Instant::now();
+        let mut closed = false;
+        for _ in 0..20 {
+            if stream.write_all(b"Ping Ping Ping").await.is_err() {
+                closed = true;
+                break;
+            }
+            match stream.read(&mut buf).await {
+                Ok(n) if n > 0 => assert_eq!(b"Pong Pong Pong", &buf[..n]),
+                _ => {
+                    closed = true;
+                    break;
+                }
+            }
+            sleep(Duration::from_millis(300)).await;
+        }
+        assert!(closed);
+        assert!(start.elapsed() >= Duration::from_secs(2));
+        assert!(start.elapsed() < Duration::from_secs(4));
+    };
+
+    let task3 = async {
+        let lis = TcpListener::bind("127.0.0.1:21100").await.unwrap();
+
+        for _ in 0..2 {
+            let (mut stream, _) = lis.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut buf = vec![0; 32];
+                while let Ok(n) = stream.read(&mut buf).await {
+                    if n == 0 {
+                        break;
+                    }
+                    assert_eq!(b"Ping Ping Ping", &buf[..n]);
+                    let _ = stream.write_all(b"Pong Pong Pong").await;
+                }
+            });
+        }
+    };
+
+    tokio::join!(task1, task2, task3);
+}
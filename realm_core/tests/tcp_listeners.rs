@@ -0,0 +1,61 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::future::join_all;
+use tokio::net::{TcpStream, TcpListener};
+use tokio::time::sleep;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use realm_core::tcp::run_tcp;
+use realm_core::endpoint::{Endpoint, RemoteAddr, BindOpts, Steering};
+
+const CLIENTS: usize = 16;
+
+#[tokio::test]
+async fn tcp_listeners() {
+    env_logger::init();
+    let endpoint = Endpoint {
+        laddr: "127.0.0.1:10600".parse().unwrap(),
+        raddr: "127.0.0.1:20600"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: Default::default(),
+        bind_opts: BindOpts {
+            tcp_listeners: 4,
+            tcp_steering: Steering::Bpf,
+            ..Default::default()
+        },
+        extra_raddrs: Vec::new(),
+    };
+
+    tokio::spawn(run_tcp(endpoint));
+
+    // echo server
+    tokio::spawn(async {
+        let lis = TcpListener::bind("127.0.0.1:20600").await.unwrap();
+        loop {
+            let (mut stream, _) = lis.accept().await.unwrap();
+            tokio::spawn(async move {
+                let (mut rd, mut wr) = stream.split();
+                tokio::io::copy(&mut rd, &mut wr).await.unwrap();
+            });
+        }
+    });
+
+    let client = |id: usize| async move {
+        sleep(Duration::from_millis(500)).await;
+        let mut stream = TcpStream::connect("127.0.0.1:10600").await.unwrap();
+
+        let mut buf = [0; 64];
+
+        for i in 0..10 {
+            let msg = format!("client {} ping {}", id, i);
+            stream.write_all(msg.as_bytes()).await.unwrap();
+            stream.read_exact(&mut buf[..msg.len()]).await.unwrap();
+            assert_eq!(msg.as_bytes(), &buf[..msg.len()]);
+        }
+    };
+
+    join_all((0..CLIENTS).map(client)).await;
+}
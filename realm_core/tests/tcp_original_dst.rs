@@ -0,0 +1,39 @@
+#![cfg(target_os = "linux")]
+
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+use tokio::io::AsyncReadExt;
+
+use realm_core::tcp::run_tcp;
+use realm_core::endpoint::{Endpoint, RemoteAddr, ConnectOpts};
+
+#[tokio::test]
+async fn tcp_original_dst() {
+    env_logger::init();
+    let endpoint = Endpoint {
+        laddr: "127.0.0.1:10900".parse().unwrap(),
+        raddr: RemoteAddr::OriginalDst,
+        conn_opts: ConnectOpts {
+            original_dst_allow: vec!["127.0.0.0/8".parse().unwrap()],
+            ..Default::default()
+        },
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    tokio::spawn(run_tcp(endpoint));
+    sleep(Duration::from_millis(500)).await;
+
+    // not redirected, there is no original destination
+    for _ in 0..5 {
+        let mut stream = TcpStream::connect("127.0.0.1:10900").await.unwrap();
+        let mut buf = vec![0; 32];
+        let n = timeout(Duration::from_secs(1), stream.read(&mut buf))
+            .await
+            .unwrap()
+            .unwrap_or(0);
+        assert_eq!(n, 0);
+    }
+}
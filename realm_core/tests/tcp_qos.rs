@@ -0,0 +1,178 @@
+#![cfg(target_os = "linux")]
+
+use std::net::SocketAddr;
+use std::os::fd::BorrowedFd;
+use std::time::Duration;
+
+use tokio::net::{TcpStream, TcpListener};
+use tokio::time::{sleep, timeout};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use realm_core::tcp::run_tcp;
+use realm_core::endpoint::{Endpoint, RemoteAddr, BindOpts, ConnectOpts};
+use realm_core::realm_syscall::{set_mark, set_tos, set_tcp_congestion};
+use realm_core::realm_syscall::socket2::SockRef;
+
+/// Check options of a socket of this process, found by its addresses.
+fn check_socket(filter: impl Fn(SocketAddr, SocketAddr) -> bool, mark: u32, tos: u32) {
+    let fds = std::fs::read_dir("/proc/self/fd").unwrap();
+    let fd = fds
+        .filter_map(|x| x.ok()?.file_name().to_str()?.parse().ok())
+        .find(|fd| {
+            let fd = unsafe { BorrowedFd::borrow_raw(*fd) };
+            let sock = SockRef::from(&fd);
+            let addrs = (sock.local_addr(), sock.peer_addr());
+            let (Ok(local), Ok(peer)) = addrs else {
+                return false;
+            };
+            matches!((local.as_socket(), peer.as_socket()), (Some(x), Some(y)) if filter(x, y))
+        })
+        .expect("socket not found");
+
+    let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+    let sock = SockRef::from(&fd);
+    assert_eq!(sock.tos_v4().unwrap(), tos);
+    assert!(sock.tcp_congestion().unwrap().starts_with(b"reno\0"));
+    if mark != 0 {
+        assert_eq!(sock.mark().unwrap(), mark);
+    }
+}
+
+/// TOS of the SYN packet, requires `IP_RECVTOS` on the listener.
+fn received_tos(stream: &TcpStream) -> Option<u8> {
+    use std::os::fd::AsRawFd;
+    let mut buf = [0u8; 64];
+    let mut len = buf.len() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::IPPROTO_IP,
+            libc::IP_PKTOPTIONS,
+            buf.as_mut_ptr() as *mut _,
+            &mut len,
+        )
+    };
+    assert_eq!(ret, 0);
+
+    let msg = libc::msghdr {
+        msg_control: buf.as_mut_ptr() as *mut _,
+        msg_controllen: len as _,
+        ..unsafe { std::mem::zeroed() }
+    };
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while let Some(x) = unsafe { cmsg.as_ref() } {
+        if x.cmsg_level == libc::IPPROTO_IP && x.cmsg_type == libc::IP_TOS {
+            return Some(unsafe { *libc::CMSG_DATA(x) });
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, x) };
+    }
+    None
+}
+
+#[tokio::test]
+async fn tcp_qos() {
+    env_logger::init();
+
+    // options are set as is
+    let probe = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    set_tos(&probe, false, 0x20).unwrap();
+    set_tcp_congestion(&probe, "reno").unwrap();
+    assert_eq!(SockRef::from(&probe).tos_v4().unwrap(), 0x20);
+    let cc = SockRef::from(&probe).tcp_congestion().unwrap();
+    assert!(cc.starts_with(b"reno\0"));
+    assert!(set_tcp_congestion(&probe, "").is_err());
+
+    // requires CAP_NET_ADMIN
+    let send_mark = match set_mark(&probe, 100) {
+        Ok(()) => {
+            assert_eq!(SockRef::from(&probe).mark().unwrap(), 100);
+            100
+        }
+        Err(e) => {
+            log::warn!("skipped mark: {}", e);
+            0
+        }
+    };
+
+    let endpoint1 = Endpoint {
+        laddr: "127.0.0.1:11300".parse().unwrap(),
+        raddr: "127.0.0.1:21300"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: ConnectOpts {
+            send_mark,
+            send_tos: 0x10,
+            send_congestion: Some(String::from("reno")),
+            ..Default::default()
+        },
+        bind_opts: BindOpts {
+            accept_mark: send_mark,
+            accept_tos: 0x20,
+            accept_congestion: Some(String::from("reno")),
+            ..Default::default()
+        },
+        extra_raddrs: Vec::new(),
+    };
+
+    // an unknown algorithm fails each connection
+    let endpoint2 = Endpoint {
+        laddr: "127.0.0.1:11301".parse().unwrap(),
+        raddr: "127.0.0.1:21300"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: ConnectOpts {
+            send_congestion: Some(String::from("realm-unknown")),
+            ..Default::default()
+        },
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    tokio::spawn(run_tcp(endpoint1));
+    tokio::spawn(run_tcp(endpoint2));
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+        let mut stream = TcpStream::connect("127.0.0.1:11300").await.unwrap();
+
+        let mut buf = vec![0; 32];
+        stream.write_all(b"Ping Ping Ping").await.unwrap();
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(b"Pong Pong Pong", &buf[..n]);
+    };
+
+    let task2 = async {
+        sleep(Duration::from_millis(500)).await;
+        let mut stream = TcpStream::connect("127.0.0.1:11301").await.unwrap();
+
+        let mut buf = vec![0; 32];
+        let n = timeout(Duration::from_secs(1), stream.read(&mut buf))
+            .await
+            .unwrap()
+            .unwrap_or(0);
+        assert_eq!(n, 0);
+    };
+
+    let task3 = async {
+        let lis = TcpListener::bind("127.0.0.1:21300").await.unwrap();
+        SockRef::from(&lis).set_recv_tos_v4(true).unwrap();
+        let (mut stream, _) = lis.accept().await.unwrap();
+        assert_eq!(received_tos(&stream), Some(0x10));
+
+        let mut buf = vec![0; 32];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(b"Ping Ping Ping", &buf[..n]);
+
+        // both sockets of the relay are alive
+        let relay: SocketAddr = "127.0.0.1:11300".parse().unwrap();
+        let remote: SocketAddr = "127.0.0.1:21300".parse().unwrap();
+        check_socket(|local, _| local == relay, send_mark, 0x20);
+        check_socket(|_, peer| peer == remote, send_mark, 0x10);
+
+        stream.write_all(b"Pong Pong Pong").await.unwrap();
+    };
+
+    tokio::join!(task1, task2, task3);
+}
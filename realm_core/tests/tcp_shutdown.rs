@@ -0,0 +1,89 @@
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use tokio::net::{TcpStream, TcpListener};
+use tokio::time::sleep;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use realm_core::tcp::run_tcp;
+use realm_core::endpoint::{Endpoint, RemoteAddr, ConnectOpts};
+use realm_core::realm_io::Shutdown;
+
+#[tokio::test]
+async fn tcp_shutdown() {
+    env_logger::init();
+
+    let endpoint = |laddr: &str, shutdown: Shutdown| Endpoint {
+        laddr: laddr.parse().unwrap(),
+        raddr: "127.0.0.1:21200"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: ConnectOpts {
+            tcp_shutdown: shutdown,
+            ..Default::default()
+        },
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    tokio::spawn(run_tcp(endpoint("127.0.0.1:11200", Shutdown::Graceful)));
+    tokio::spawn(run_tcp(endpoint("127.0.0.1:11201", Shutdown::Brutal)));
+    tokio::spawn(run_tcp(endpoint(
+        "127.0.0.1:11202",
+        Shutdown::HalfClose(Duration::from_secs(1)),
+    )));
+
+    // send then half-close, return what is received and when
+    async fn half_close(laddr: &str) -> (Vec<u8>, Duration) {
+        let mut stream = TcpStream::connect(laddr).await.unwrap();
+        stream.write_all(b"Ping Ping Ping").await.unwrap();
+        stream.shutdown().await.unwrap();
+
+        let start = Instant::now();
+        let mut buf = Vec::new();
+        let _ = stream.read_to_end(&mut buf).await;
+        (buf, start.elapsed())
+    }
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+        let (buf, elapsed) = half_close("127.0.0.1:11200").await;
+        assert_eq!(b"Pong Pong Pong", buf.as_slice());
+        assert!(elapsed >= Duration::from_secs(2));
+    };
+
+    let task2 = async {
+        sleep(Duration::from_millis(500)).await;
+        let (buf, elapsed) = half_close("127.0.0.1:11201").await;
+        assert!(buf.is_empty());
+        assert!(elapsed < Duration::from_secs(1));
+    };
+
+    let task3 = async {
+        sleep(Duration::from_millis(500)).await;
+        let (buf, elapsed) = half_close("127.0.0.1:11202").await;
+        assert!(buf.is_empty());
+        assert!(elapsed >= Duration::from_millis(500));
+        assert!(elapsed < Duration::from_secs(2));
+    };
+
+    // reply 2s after the client half-closes
+    let task4 = async {
+        let lis = TcpListener::bind("127.0.0.1:21200").await.unwrap();
+
+        for _ in 0..3 {
+            let (mut stream, _) = lis.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut buf = Vec::new();
+                stream.read_to_end(&mut buf).await.unwrap();
+                assert_eq!(b"Ping Ping Ping", buf.as_slice());
+
+                sleep(Duration::from_secs(2)).await;
+                let _ = stream.write_all(b"Pong Pong Pong").await;
+            });
+        }
+    };
+
+    tokio::join!(task1, task2, task3, task4);
+}
@@ -0,0 +1,120 @@
+#![cfg(target_os = "linux")]
+
+use std::process::Command;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use tokio::net::{TcpSocket, TcpListener};
+use tokio::time::{sleep, timeout};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use realm_core::tcp::run_tcp;
+use realm_core::endpoint::{Endpoint, RemoteAddr, BindOpts};
+
+const RULES: &str = "
+table ip realm {
+    chain prerouting {
+        type filter hook prerouting priority mangle;
+        tcp dport 12400 tproxy to 127.0.0.1:12400 meta mark set 1
+        ip saddr 127.0.0.2 tcp dport 22400 tproxy to 127.0.0.1:12400 meta mark set 1
+    }
+}
+";
+
+fn sh(cmd: &str, stdin: Option<&str>) -> bool {
+    use std::io::Write;
+    use std::process::Stdio;
+    let mut args = cmd.split_whitespace();
+    let child = Command::new(args.next().unwrap())
+        .args(args)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let Ok(mut child) = child else {
+        return false;
+    };
+    if let Some(x) = stdin {
+        let _ = child.stdin.take().unwrap().write_all(x.as_bytes());
+    }
+    child.wait().is_ok_and(|x| x.success())
+}
+
+async fn connect(src: &str, dst: &str) -> tokio::net::TcpStream {
+    let socket = TcpSocket::new_v4().unwrap();
+    socket.bind(SocketAddr::new(src.parse::<IpAddr>().unwrap(), 0)).unwrap();
+    socket.connect(dst.parse().unwrap()).await.unwrap()
+}
+
+async fn expect_closed(mut stream: tokio::net::TcpStream) {
+    let mut buf = vec![0; 32];
+    let n = timeout(Duration::from_secs(1), stream.read(&mut buf))
+        .await
+        .unwrap()
+        .unwrap_or(0);
+    assert_eq!(n, 0);
+}
+
+#[tokio::test]
+async fn tcp_tproxy() {
+    env_logger::init();
+
+    // requires CAP_SYS_ADMIN, the runtime runs on this thread only
+    if unsafe { libc::unshare(libc::CLONE_NEWNET) } != 0 {
+        log::warn!("skipped: {}", std::io::Error::last_os_error());
+        return;
+    }
+    assert!(sh("ip link set lo up", None));
+    assert!(sh("ip addr add 192.0.2.1/32 dev lo", None));
+
+    let endpoint = |laddr: &str| Endpoint {
+        laddr: laddr.parse().unwrap(),
+        raddr: RemoteAddr::OriginalDst,
+        conn_opts: Default::default(),
+        bind_opts: BindOpts {
+            transparent: true,
+            ..Default::default()
+        },
+        extra_raddrs: Vec::new(),
+    };
+
+    tokio::spawn(run_tcp(endpoint("127.0.0.1:12400")));
+    tokio::spawn(run_tcp(endpoint("0.0.0.0:12401")));
+    sleep(Duration::from_millis(500)).await;
+
+    // a wildcard listener must not connect to itself
+    expect_closed(connect("127.0.0.1", "127.0.0.1:12401").await).await;
+    expect_closed(connect("127.0.0.1", "192.0.2.1:12401").await).await;
+
+    // requires nftables with TPROXY support
+    let tproxy = sh("nft -f -", Some(RULES))
+        && sh("ip rule add fwmark 1 lookup 100", None)
+        && sh("ip route add local 0.0.0.0/0 dev lo table 100", None);
+    if !tproxy {
+        log::warn!("skipped tproxy cases");
+        return;
+    }
+
+    // redirected to a local address on the listening port
+    expect_closed(connect("127.0.0.2", "192.0.2.1:12400").await).await;
+
+    // redirected to a local address on another port
+    let lis = TcpListener::bind("192.0.2.1:22400").await.unwrap();
+    let task1 = async {
+        let mut stream = connect("127.0.0.2", "192.0.2.1:22400").await;
+        let mut buf = vec![0; 32];
+        stream.write_all(b"Ping Ping Ping").await.unwrap();
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(b"Pong Pong Pong", &buf[..n]);
+    };
+    let task2 = async {
+        let (mut stream, peer) = lis.accept().await.unwrap();
+        // connected by the relay
+        assert_ne!(peer.ip(), "127.0.0.2".parse::<IpAddr>().unwrap());
+
+        let mut buf = vec![0; 32];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(b"Ping Ping Ping", &buf[..n]);
+        stream.write_all(b"Pong Pong Pong").await.unwrap();
+    };
+    tokio::join!(task1, task2);
+}
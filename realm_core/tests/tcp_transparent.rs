@@ -0,0 +1,93 @@
+#![cfg(target_os = "linux")]
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use tokio::net::{TcpSocket, TcpListener};
+use tokio::time::sleep;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use realm_core::tcp::run_tcp;
+use realm_core::endpoint::{Endpoint, RemoteAddr, BindOpts, ConnectOpts};
+use realm_core::realm_syscall::set_ip_transparent;
+
+#[tokio::test]
+async fn tcp_transparent() {
+    env_logger::init();
+
+    // requires CAP_NET_ADMIN
+    let probe = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    if let Err(e) = set_ip_transparent(&probe, false, true) {
+        log::warn!("skipped: {}", e);
+        return;
+    }
+
+    // connect with the client's address
+    let endpoint1 = Endpoint {
+        laddr: "127.0.0.1:10800".parse().unwrap(),
+        raddr: "127.0.0.1:20800"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: ConnectOpts {
+            spoof_source: true,
+            ..Default::default()
+        },
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    // without tproxy rules, only connections to itself are accepted
+    let endpoint2 = Endpoint {
+        laddr: "127.0.0.1:10801".parse().unwrap(),
+        raddr: RemoteAddr::OriginalDst,
+        conn_opts: Default::default(),
+        bind_opts: BindOpts {
+            transparent: true,
+            ..Default::default()
+        },
+        extra_raddrs: Vec::new(),
+    };
+
+    tokio::spawn(run_tcp(endpoint1));
+    tokio::spawn(run_tcp(endpoint2));
+
+    let client_ip: IpAddr = "127.0.0.2".parse().unwrap();
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+
+        let socket = TcpSocket::new_v4().unwrap();
+        socket.bind(SocketAddr::new(client_ip, 0)).unwrap();
+        let mut stream = socket.connect("127.0.0.1:10800".parse().unwrap()).await.unwrap();
+
+        let mut buf = vec![0; 32];
+        stream.write_all(b"Ping Ping Ping").await.unwrap();
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(b"Pong Pong Pong", &buf[..n]);
+    };
+
+    let task2 = async {
+        let lis = TcpListener::bind("127.0.0.1:20800").await.unwrap();
+        let (mut stream, peer) = lis.accept().await.unwrap();
+        assert_eq!(peer.ip(), client_ip);
+
+        let mut buf = vec![0; 32];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(b"Ping Ping Ping", &buf[..n]);
+        stream.write_all(b"Pong Pong Pong").await.unwrap();
+    };
+
+    let task3 = async {
+        sleep(Duration::from_millis(500)).await;
+
+        let socket = TcpSocket::new_v4().unwrap();
+        let mut stream = socket.connect("127.0.0.1:10801".parse().unwrap()).await.unwrap();
+
+        let mut buf = vec![0; 32];
+        let n = stream.read(&mut buf).await.unwrap_or(0);
+        assert_eq!(n, 0);
+    };
+
+    tokio::join!(task1, task2, task3);
+}
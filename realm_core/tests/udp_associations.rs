@@ -0,0 +1,63 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+use tokio::time::sleep;
+
+use realm_core::udp::{run_udp, associations};
+use realm_core::endpoint::{Endpoint, RemoteAddr};
+
+#[tokio::test]
+async fn udp_associations() {
+    env_logger::init();
+    let endpoint = Endpoint {
+        laddr: "127.0.0.1:12500".parse().unwrap(),
+        raddr: "127.0.0.1:22500"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: Default::default(),
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    let laddr: SocketAddr = "127.0.0.1:12500".parse().unwrap();
+    assert!(associations(&laddr).is_none());
+
+    tokio::spawn(run_udp(endpoint));
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut buf = vec![0; 32];
+
+        for _ in 0..3 {
+            socket.send_to(b"Ping Ping Ping", &laddr).await.unwrap();
+            let (n, _) = socket.recv_from(&mut buf).await.unwrap();
+            assert_eq!(b"Pong Pong Pong", &buf[..n]);
+        }
+        socket.local_addr().unwrap()
+    };
+
+    let task2 = async {
+        let socket = UdpSocket::bind("127.0.0.1:22500").await.unwrap();
+        let mut buf = vec![0; 32];
+
+        for _ in 0..3 {
+            let (n, peer) = socket.recv_from(&mut buf).await.unwrap();
+            assert_eq!(b"Ping Ping Ping", &buf[..n]);
+            socket.send_to(b"Pong Pong Pong", peer).await.unwrap();
+        }
+    };
+
+    let (client, _) = tokio::join!(task1, task2);
+
+    // visible to others while the relay is running
+    let assocs = associations(&laddr).unwrap();
+    assert_eq!(assocs.len(), 1);
+
+    let stats = assocs.stats();
+    assert_eq!(stats[0].0, client);
+    assert_eq!(stats[0].1.sent_bytes(), 3 * 14);
+}
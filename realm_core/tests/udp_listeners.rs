@@ -0,0 +1,60 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::future::join_all;
+use tokio::net::UdpSocket;
+use tokio::time::sleep;
+
+use realm_core::udp::run_udp;
+use realm_core::endpoint::{Endpoint, RemoteAddr, BindOpts};
+
+const CLIENTS: usize = 16;
+
+#[tokio::test]
+async fn udp_listeners() {
+    env_logger::init();
+    let endpoint = Endpoint {
+        laddr: "127.0.0.1:10500".parse().unwrap(),
+        raddr: "127.0.0.1:20500"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: Default::default(),
+        bind_opts: BindOpts {
+            udp_listeners: 4,
+            ..Default::default()
+        },
+        extra_raddrs: Vec::new(),
+    };
+
+    tokio::spawn(run_udp(endpoint));
+
+    // echo server
+    tokio::spawn(async {
+        let socket = UdpSocket::bind("127.0.0.1:20500").await.unwrap();
+        let mut buf = vec![0; 2048];
+        loop {
+            let (n, addr) = socket.recv_from(&mut buf).await.unwrap();
+            socket.send_to(&buf[..n], addr).await.unwrap();
+        }
+    });
+
+    // clients are spread across listeners by their source port
+    let client = |id: usize| async move {
+        sleep(Duration::from_millis(500)).await;
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let peer: SocketAddr = "127.0.0.1:10500".parse().unwrap();
+        let mut buf = vec![0; 2048];
+
+        for i in 0..10 {
+            let msg = format!("client {} packet {}", id, i);
+            socket.send_to(msg.as_bytes(), &peer).await.unwrap();
+            let (n, peer2) = socket.recv_from(&mut buf).await.unwrap();
+            assert_eq!(peer, peer2);
+            assert_eq!(msg.as_bytes(), &buf[..n]);
+        }
+    };
+
+    join_all((0..CLIENTS).map(client)).await;
+}
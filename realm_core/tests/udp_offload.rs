@@ -0,0 +1,165 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+use tokio::time::sleep;
+
+use realm_core::udp::run_udp;
+use realm_core::endpoint::{Endpoint, RemoteAddr, ConnectOpts};
+
+const COUNT: u8 = 64;
+
+fn packet(i: u8) -> Vec<u8> {
+    // the last one is shorter
+    let size = if i == COUNT - 1 { 600 } else { 1200 };
+    vec![i; size]
+}
+
+#[tokio::test]
+async fn udp_offload() {
+    env_logger::init();
+    // chained, so that segments sent by one are coalesced by the other
+    let endpoint = |laddr: &str, raddr: &str| Endpoint {
+        laddr: laddr.parse().unwrap(),
+        raddr: raddr.parse::<SocketAddr>().map(RemoteAddr::SocketAddr).unwrap(),
+        conn_opts: ConnectOpts {
+            udp_offload: true,
+            ..Default::default()
+        },
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    tokio::spawn(run_udp(endpoint("127.0.0.1:10400", "127.0.0.1:15400")));
+    tokio::spawn(run_udp(endpoint("127.0.0.1:15400", "127.0.0.1:20400")));
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        let mut buf = vec![0; 2000];
+        let peer: SocketAddr = "127.0.0.1:10400".parse().unwrap();
+
+        for _ in 0..5 {
+            send(&socket, &peer).await;
+            for i in 0..COUNT {
+                let (n, peer2) = socket.recv_from(&mut buf).await.unwrap();
+                assert_eq!(peer, peer2);
+                assert_eq!(&packet(i), &buf[..n]);
+            }
+        }
+    };
+
+    let task2 = async {
+        let socket = UdpSocket::bind("127.0.0.1:20400").await.unwrap();
+        let mut coalesced = 0;
+
+        for _ in 0..5 {
+            let (peer, n) = recv(&socket).await;
+            coalesced += n;
+            for i in 0..COUNT {
+                socket.send_to(&packet(i), peer).await.unwrap();
+            }
+        }
+        coalesced
+    };
+
+    let (_, coalesced) = tokio::join!(task1, task2);
+
+    // segments sent by realm with gso are delivered as is
+    #[cfg(all(target_os = "linux", feature = "batched-udp"))]
+    assert!(coalesced > 0);
+    #[cfg(not(all(target_os = "linux", feature = "batched-udp")))]
+    let _ = coalesced;
+}
+
+#[cfg(all(target_os = "linux", feature = "batched-udp"))]
+use gso::{send, recv};
+
+#[cfg(not(all(target_os = "linux", feature = "batched-udp")))]
+use plain::{send, recv};
+
+// send and receive packets of a round
+#[cfg(all(target_os = "linux", feature = "batched-udp"))]
+mod gso {
+    use super::*;
+    use std::io::{IoSlice, IoSliceMut};
+    use realm_core::realm_io::mmsg::{MmsgHdr, MmsgHdrMut, SockAddrStore, send_mul_pkts, recv_mul_pkts};
+    use realm_core::realm_io::offload::{self, SegmentCmsg};
+
+    // within the max payload of a message
+    const SEGMENTS: usize = 32;
+
+    pub async fn send(socket: &UdpSocket, peer: &SocketAddr) {
+        let addr: SockAddrStore = (*peer).into();
+        let pkts: Vec<_> = (0..COUNT).map(packet).collect();
+        for chunk in pkts.chunks(SEGMENTS) {
+            let iovs: Vec<_> = chunk.iter().map(|x| IoSlice::new(x)).collect();
+            let cmsg = SegmentCmsg::with_segment(chunk[0].len() as u16);
+            let mut msgs = [MmsgHdr::new()
+                .with_addr(&addr)
+                .with_iovec(&iovs)
+                .with_control(cmsg.as_slice())];
+            assert_eq!(send_mul_pkts(socket, &mut msgs).await.unwrap(), 1);
+        }
+    }
+
+    /// Returns the peer, and how many messages carry multiple segments.
+    pub async fn recv(socket: &UdpSocket) -> (SocketAddr, usize) {
+        offload::set_gro(socket, true).unwrap();
+
+        let mut buf = vec![0; offload::MAX_PAYLOAD + 1];
+        let mut i = 0;
+        let mut coalesced = 0;
+        loop {
+            let mut addr = SockAddrStore::new();
+            let mut cmsg = SegmentCmsg::new();
+            let (n, segment) = {
+                let mut iov = [IoSliceMut::new(&mut buf)];
+                let mut msgs = [MmsgHdrMut::new()
+                    .with_addr(&mut addr)
+                    .with_iovec(&mut iov)
+                    .with_control(cmsg.as_mut_slice())];
+                recv_mul_pkts(socket, &mut msgs).await.unwrap();
+
+                let msg = msgs[0].get_ref();
+                let n = msg.nbytes() as usize;
+                (n, offload::gro_segment(msg.control()).unwrap_or(n))
+            };
+            if n > segment {
+                coalesced += 1;
+            }
+
+            for seg in buf[..n].chunks(segment) {
+                assert_eq!(&packet(i), seg);
+                i += 1;
+            }
+            if i == COUNT {
+                return (addr.into(), coalesced);
+            }
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "batched-udp")))]
+mod plain {
+    use super::*;
+
+    pub async fn send(socket: &UdpSocket, peer: &SocketAddr) {
+        for i in 0..COUNT {
+            socket.send_to(&packet(i), peer).await.unwrap();
+        }
+    }
+
+    pub async fn recv(socket: &UdpSocket) -> (SocketAddr, usize) {
+        let mut buf = vec![0; 2000];
+        let mut peer = None;
+        for i in 0..COUNT {
+            let (n, addr) = socket.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&packet(i), &buf[..n]);
+            peer = Some(addr);
+        }
+        (peer.unwrap(), 0)
+    }
+}
@@ -0,0 +1,66 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+use tokio::time::sleep;
+
+use realm_core::udp::{run_udp, associations};
+use realm_core::endpoint::{Endpoint, RemoteAddr, ConnectOpts};
+
+#[tokio::test]
+async fn udp_packet_size() {
+    env_logger::init();
+    let endpoint = Endpoint {
+        laddr: "127.0.0.1:10300".parse().unwrap(),
+        raddr: "127.0.0.1:20300"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: ConnectOpts {
+            udp_packet_size: 9000,
+            ..Default::default()
+        },
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    tokio::spawn(run_udp(endpoint));
+
+    let large = vec![b'a'; 8000];
+    let jumbo = vec![b'b'; 9001];
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        let mut buf = vec![0; 10000];
+        let peer: SocketAddr = "127.0.0.1:10300".parse().unwrap();
+
+        for _ in 0..20 {
+            // dropped by realm
+            socket.send_to(&jumbo, &peer).await.unwrap();
+
+            socket.send_to(&large, &peer).await.unwrap();
+            let (n, peer2) = socket.recv_from(&mut buf).await.unwrap();
+            assert_eq!(peer, peer2);
+            assert_eq!(&large, &buf[..n]);
+        }
+    };
+
+    let task2 = async {
+        let socket = UdpSocket::bind("127.0.0.1:20300").await.unwrap();
+
+        let mut buf = vec![0; 10000];
+
+        for _ in 0..20 {
+            let (n, peer) = socket.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&large, &buf[..n]);
+            socket.send_to(&buf[..n], peer).await.unwrap();
+        }
+    };
+
+    tokio::join!(task1, task2);
+    let sockmap = associations(&"127.0.0.1:10300".parse().unwrap()).unwrap();
+    assert_eq!(sockmap.truncated(), 20);
+}
@@ -0,0 +1,87 @@
+#![cfg(target_os = "linux")]
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::{TcpStream, TcpListener};
+use tokio::time::sleep;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use realm_core::tcp::run_tcp;
+use realm_core::endpoint::{Endpoint, LocalAddr, RemoteAddr, UnixAddr};
+
+#[tokio::test]
+async fn unix() {
+    env_logger::init();
+
+    let path = std::env::temp_dir().join(format!("realm-{}.sock", std::process::id()));
+    let path_addr = UnixAddr::Path(path.clone());
+    let abstract_addr: UnixAddr = "unix:@realm-11400".parse().unwrap();
+    assert_eq!(abstract_addr.to_string(), "unix:@realm-11400");
+
+    // tcp => unix path
+    let endpoint1 = Endpoint {
+        laddr: "127.0.0.1:11400".parse().unwrap(),
+        raddr: RemoteAddr::Unix(path_addr.clone()),
+        conn_opts: Default::default(),
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    // unix path => unix abstract
+    let endpoint2 = Endpoint {
+        laddr: LocalAddr::Unix(path_addr),
+        raddr: RemoteAddr::Unix(abstract_addr.clone()),
+        conn_opts: Default::default(),
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    // unix abstract => tcp
+    let endpoint3 = Endpoint {
+        laddr: LocalAddr::Unix(abstract_addr),
+        raddr: "127.0.0.1:21400"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: Default::default(),
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    // a stale socket file is replaced
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+    tokio::spawn(run_tcp(endpoint1));
+    tokio::spawn(run_tcp(endpoint2));
+    tokio::spawn(run_tcp(endpoint3));
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+        let mut stream = TcpStream::connect("127.0.0.1:11400").await.unwrap();
+
+        let mut buf = vec![0; 32];
+
+        for _ in 0..20 {
+            stream.write_all(b"Ping Ping Ping").await.unwrap();
+            let n = stream.read(&mut buf).await.unwrap();
+            assert_eq!(b"Pong Pong Pong", &buf[..n]);
+        }
+    };
+
+    let task2 = async {
+        let lis = TcpListener::bind("127.0.0.1:21400").await.unwrap();
+        let (mut stream, _) = lis.accept().await.unwrap();
+
+        let mut buf = vec![0; 32];
+
+        for _ in 0..20 {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert_eq!(b"Ping Ping Ping", &buf[..n]);
+            stream.write_all(b"Pong Pong Pong").await.unwrap();
+        }
+    };
+
+    tokio::join!(task1, task2);
+    let _ = std::fs::remove_file(&path);
+}
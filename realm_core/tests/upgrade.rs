@@ -0,0 +1,74 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::{TcpStream, TcpListener};
+use tokio::time::{sleep, timeout};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use realm_core::tcp::run_tcp;
+use realm_core::upgrade;
+use realm_core::endpoint::{Endpoint, RemoteAddr};
+
+#[tokio::test]
+async fn upgrade() {
+    env_logger::init();
+
+    let endpoint = Endpoint {
+        laddr: "127.0.0.1:11600".parse().unwrap(),
+        raddr: "127.0.0.1:21600"
+            .parse::<SocketAddr>()
+            .map(RemoteAddr::SocketAddr)
+            .unwrap(),
+        conn_opts: Default::default(),
+        bind_opts: Default::default(),
+        extra_raddrs: Vec::new(),
+    };
+
+    tokio::spawn(run_tcp(endpoint));
+
+    let task1 = async {
+        sleep(Duration::from_millis(500)).await;
+        let mut stream = TcpStream::connect("127.0.0.1:11600").await.unwrap();
+
+        let mut buf = vec![0; 32];
+        stream.write_all(b"Ping Ping Ping").await.unwrap();
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(b"Pong Pong Pong", &buf[..n]);
+        assert_eq!(upgrade::active_relays(), 1);
+
+        upgrade::drain();
+        assert!(upgrade::is_draining());
+        sleep(Duration::from_millis(100)).await;
+
+        // not accepted any more, while the listener is kept
+        let mut stream2 = TcpStream::connect("127.0.0.1:11600").await.unwrap();
+        stream2.write_all(b"Ping Ping Ping").await.unwrap();
+        assert!(timeout(Duration::from_secs(1), stream2.read(&mut buf)).await.is_err());
+
+        // existing relays are not affected
+        stream.write_all(b"Ping Ping Ping").await.unwrap();
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(b"Pong Pong Pong", &buf[..n]);
+        assert_eq!(upgrade::active_relays(), 1);
+
+        drop(stream);
+        assert!(upgrade::drained(3).await);
+        assert_eq!(upgrade::active_relays(), 0);
+    };
+
+    let task2 = async {
+        let lis = TcpListener::bind("127.0.0.1:21600").await.unwrap();
+        let (mut stream, _) = lis.accept().await.unwrap();
+
+        let mut buf = vec![0; 32];
+        while let Ok(n) = stream.read(&mut buf).await {
+            if n == 0 {
+                break;
+            }
+            assert_eq!(b"Ping Ping Ping", &buf[..n]);
+            stream.write_all(b"Pong Pong Pong").await.unwrap();
+        }
+    };
+
+    tokio::join!(task1, task2);
+}
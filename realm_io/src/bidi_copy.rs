@@ -1,9 +1,12 @@
-use std::io::Result;
+use std::io::{Error, ErrorKind, Result};
 use std::pin::Pin;
 use std::task::{Context, Poll, ready};
 use std::future::Future;
+use std::time::Duration;
+use std::fmt::{Display, Formatter};
 
 use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::{Instant, Sleep, sleep};
 
 use super::{AsyncIOBuf, CopyBuffer};
 
@@ -13,11 +16,127 @@ enum TransferState<B, SR, SW> {
     Done(u64),
 }
 
+/// How to close two streams once either direction reaches `EOF`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Shutdown {
+    /// Forward the `FIN` packet, and wait for the other direction to finish.
+    #[default]
+    Graceful,
+
+    /// Close two streams on both sides without waiting for the other direction.
+    Brutal,
+
+    /// Same as [`Shutdown::Graceful`], but wait for the other direction
+    /// at most this duration.
+    HalfClose(Duration),
+}
+
+impl Display for Shutdown {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Shutdown::Graceful => write!(f, "graceful"),
+            Shutdown::Brutal => write!(f, "brutal"),
+            Shutdown::HalfClose(timeout) => write!(f, "graceful[{}s]", timeout.as_secs()),
+        }
+    }
+}
+
+/// Options of [`bidi_copy_buf`] and other IO functions.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CopyOpts {
+    /// Shutdown behavior once either direction reaches `EOF`.
+    pub shutdown: Shutdown,
+
+    /// Close both streams if no data is transferred in either direction for this duration.
+    pub idle_timeout: Option<Duration>,
+
+    /// Close both streams once they have been relayed for this duration.
+    pub max_lifetime: Option<Duration>,
+}
+
+/// Reason of a relay closed by [`CopyOpts::idle_timeout`] or [`CopyOpts::max_lifetime`],
+/// carried by an error of [`ErrorKind::TimedOut`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expired {
+    IdleTimeout,
+    MaxLifetime,
+}
+
+impl Expired {
+    /// Get the reason if an error is caused by expiry.
+    pub fn of(e: &Error) -> Option<Self> {
+        e.get_ref().and_then(|x| x.downcast_ref::<Self>()).copied()
+    }
+}
+
+impl Display for Expired {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expired::IdleTimeout => write!(f, "idle timeout"),
+            Expired::MaxLifetime => write!(f, "max lifetime exceeded"),
+        }
+    }
+}
+
+impl std::error::Error for Expired {}
+
+struct Deadline {
+    idle: Option<(Duration, Instant, Pin<Box<Sleep>>)>,
+    lifetime: Option<Pin<Box<Sleep>>>,
+    half_close: Option<Pin<Box<Sleep>>>,
+}
+
+impl Deadline {
+    fn new(opts: &CopyOpts) -> Self {
+        let now = Instant::now();
+        Self {
+            idle: opts.idle_timeout.map(|d| (d, now, Box::pin(sleep(d)))),
+            lifetime: opts.max_lifetime.map(|d| Box::pin(sleep(d))),
+            half_close: None,
+        }
+    }
+
+    // the timer starts once this is first called
+    fn poll_half_closed(&mut self, cx: &mut Context<'_>, timeout: Duration) -> Poll<()> {
+        self.half_close
+            .get_or_insert_with(|| Box::pin(sleep(timeout)))
+            .as_mut()
+            .poll(cx)
+    }
+
+    fn poll_expired(&mut self, cx: &mut Context<'_>, active: bool) -> Poll<Error> {
+        if let Some(lifetime) = self.lifetime.as_mut() {
+            if lifetime.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Error::new(ErrorKind::TimedOut, Expired::MaxLifetime));
+            }
+        }
+
+        if let Some((timeout, last_active, idle)) = self.idle.as_mut() {
+            if active {
+                *last_active = Instant::now();
+            }
+
+            // the timer is not reset on each transfer,
+            // instead it is pushed back once it fires.
+            while idle.as_mut().poll(cx).is_ready() {
+                let deadline = *last_active + *timeout;
+                if deadline <= Instant::now() {
+                    return Poll::Ready(Error::new(ErrorKind::TimedOut, Expired::IdleTimeout));
+                }
+                idle.as_mut().reset(deadline);
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
 fn transfer<B, SL, SR>(
     cx: &mut Context<'_>,
     state: &mut TransferState<B, SL, SR>,
     r: &mut <CopyBuffer<B, SL, SR> as AsyncIOBuf>::StreamR,
     w: &mut <CopyBuffer<B, SL, SR> as AsyncIOBuf>::StreamW,
+    active: &mut bool,
 ) -> Poll<Result<u64>>
 where
     B: Unpin,
@@ -29,7 +148,9 @@ where
     loop {
         match state {
             TransferState::Running(buf) => {
-                let count = ready!(buf.poll_copy(cx, r, w))?;
+                let res = buf.poll_copy(cx, r, w);
+                *active |= std::mem::take(&mut buf.active);
+                let count = ready!(res)?;
 
                 *state = TransferState::ShuttingDown(count);
             }
@@ -48,6 +169,7 @@ fn transfer2<B, SL, SR>(
     state: &mut TransferState<B, SR, SL>, // reverse
     r: &mut <CopyBuffer<B, SL, SR> as AsyncIOBuf>::StreamW,
     w: &mut <CopyBuffer<B, SL, SR> as AsyncIOBuf>::StreamR,
+    active: &mut bool,
 ) -> Poll<Result<u64>>
 where
     B: Unpin,
@@ -62,7 +184,9 @@ where
     loop {
         match state {
             TransferState::Running(buf) => {
-                let count = ready!(buf.poll_copy(cx, r, w))?;
+                let res = buf.poll_copy(cx, r, w);
+                *active |= std::mem::take(&mut buf.active);
+                let count = ready!(res)?;
 
                 *state = TransferState::ShuttingDown(count);
             }
@@ -88,6 +212,8 @@ where
     b: &'a mut <CopyBuffer<B, SL, SR> as AsyncIOBuf>::StreamW,
     a_to_b: TransferState<B, SL, SR>,
     b_to_a: TransferState<B, SR, SL>,
+    shutdown: Shutdown,
+    deadline: Deadline,
 }
 
 impl<'a, B, SL, SR> Future for BidiCopy<'a, B, SL, SR>
@@ -102,38 +228,52 @@ where
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         // Unpack self into mut refs to each field to avoid borrow check issues.
-        let BidiCopy { a, b, a_to_b, b_to_a } = self.get_mut();
+        let BidiCopy {
+            a,
+            b,
+            a_to_b,
+            b_to_a,
+            shutdown,
+            deadline,
+        } = self.get_mut();
 
-        let a_to_b = transfer(cx, a_to_b, a, b)?;
-        let b_to_a = transfer2::<B, SL, SR>(cx, b_to_a, b, a)?;
+        let mut active = false;
+        let a_to_b = transfer(cx, a_to_b, a, b, &mut active)?;
+        let b_to_a = transfer2::<B, SL, SR>(cx, b_to_a, b, a, &mut active)?;
 
-        // graceful shutdown
-        #[cfg(not(feature = "brutal-shutdown"))]
-        {
-            let a_to_b = ready!(a_to_b);
-            let b_to_a = ready!(b_to_a);
-            Poll::Ready(Ok((a_to_b, b_to_a)))
-        }
+        let amt = |x: Poll<u64>| match x {
+            Poll::Ready(n) => n,
+            Poll::Pending => 0,
+        };
 
-        // brutal shutdown
-        #[cfg(feature = "brutal-shutdown")]
-        {
-            match (a_to_b, b_to_a) {
-                (Poll::Ready(a), Poll::Ready(b)) => Poll::Ready(Ok((a, b))),
-                (Poll::Pending, Poll::Ready(b)) => Poll::Ready(Ok((0, b))),
-                (Poll::Ready(a), Poll::Pending) => Poll::Ready(Ok((a, 0))),
-                _ => Poll::Pending,
+        match (a_to_b, b_to_a, *shutdown) {
+            (Poll::Ready(a), Poll::Ready(b), _) => return Poll::Ready(Ok((a, b))),
+            (Poll::Pending, Poll::Pending, _) | (_, _, Shutdown::Graceful) => {}
+            // brutal shutdown
+            (a, b, Shutdown::Brutal) => return Poll::Ready(Ok((amt(a), amt(b)))),
+            // graceful shutdown, but do not wait forever
+            (_, _, Shutdown::HalfClose(timeout)) => {
+                if deadline.poll_half_closed(cx, timeout).is_ready() {
+                    return Poll::Ready(Err(Error::new(ErrorKind::TimedOut, "half-close timeout")));
+                }
             }
         }
+
+        // idle timeout or max lifetime
+        deadline.poll_expired(cx, active).map(Err)
     }
 }
 
 /// Copy data bidirectionally between two streams with provided buffer.
+///
+/// Fails with [`ErrorKind::TimedOut`] once the idle timeout, max lifetime
+/// or half-close timeout in [`CopyOpts`] is reached, see [`Expired`].
 pub async fn bidi_copy_buf<B, SR, SW>(
     a: &mut <CopyBuffer<B, SR, SW> as AsyncIOBuf>::StreamR,
     b: &mut <CopyBuffer<B, SR, SW> as AsyncIOBuf>::StreamW,
     a_to_b_buf: CopyBuffer<B, SR, SW>,
     b_to_a_buf: CopyBuffer<B, SW, SR>,
+    opts: CopyOpts,
 ) -> Result<(u64, u64)>
 where
     B: Unpin,
@@ -145,5 +285,15 @@ where
     let a_to_b = TransferState::Running(a_to_b_buf);
     let b_to_a = TransferState::Running(b_to_a_buf);
 
-    BidiCopy { a, b, a_to_b, b_to_a }.await
+    let deadline = Deadline::new(&opts);
+
+    BidiCopy {
+        a,
+        b,
+        a_to_b,
+        b_to_a,
+        shutdown: opts.shutdown,
+        deadline,
+    }
+    .await
 }
@@ -8,6 +8,7 @@ use tokio::io::{AsyncRead, AsyncWrite};
 pub struct CopyBuffer<B, SR, SW> {
     pub(crate) read_done: bool,
     pub(crate) need_flush: bool,
+    pub(crate) active: bool,
     pub(crate) pos: usize,
     pub(crate) cap: usize,
     pub(crate) amt: u64,
@@ -22,6 +23,7 @@ impl<B, SR, SW> CopyBuffer<B, SR, SW> {
         Self {
             read_done: false,
             need_flush: false,
+            active: false,
             pos: 0,
             cap: 0,
             amt: 0,
@@ -82,6 +84,7 @@ where
                 } else {
                     self.pos = 0;
                     self.cap = n;
+                    self.active = true;
                 }
             }
 
@@ -98,6 +101,7 @@ where
                     self.pos += i;
                     self.amt += i as u64;
                     self.need_flush = true;
+                    self.active = true;
                 }
             }
 
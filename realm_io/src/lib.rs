@@ -8,47 +8,60 @@
 //! async {
 //!     use tokio::net::TcpStream;
 //!     use realm_io::{bidi_copy, bidi_zero_copy, bidi_copy_buf};
-//!     use realm_io::{Pipe, CopyBuffer};
+//!     use realm_io::{Pipe, CopyBuffer, CopyOpts};
+//!     use std::time::Duration;
 //!
 //!     let mut left = TcpStream::connect("abc").await.unwrap();
 //!     let mut right = TcpStream::connect("def").await.unwrap();
 //!
 //!     // direct copy     
-//!     bidi_copy(&mut left, &mut right).await;
+//!     bidi_copy(&mut left, &mut right, CopyOpts::default()).await;
 //!
 //!     // zero copy
-//!     bidi_zero_copy(&mut left, &mut right).await;
+//!     bidi_zero_copy(&mut left, &mut right, CopyOpts::default()).await;
 //!
 //!     // use custom buffer(AsMut<[u8]>)
 //!     let buf1 = CopyBuffer::new(vec![0; 0x2000]);
 //!     let buf2 = CopyBuffer::new(vec![0; 0x2000]);
-//!     bidi_copy_buf(&mut left, &mut right, buf1, buf2).await;
+//!     bidi_copy_buf(&mut left, &mut right, buf1, buf2, CopyOpts::default()).await;
 //!
 //!     // use custom buffer(Pipe or &mut Pipe)
 //!     let buf1 = CopyBuffer::new(Pipe::new().unwrap());
 //!     let buf2 = CopyBuffer::new(Pipe::new().unwrap());
-//!     bidi_copy_buf(&mut left, &mut right, buf1, buf2).await;
+//!     bidi_copy_buf(&mut left, &mut right, buf1, buf2, CopyOpts::default()).await;
+//!
+//!     // close on idle or after a while
+//!     let opts = CopyOpts {
+//!         idle_timeout: Some(Duration::from_secs(60)),
+//!         max_lifetime: Some(Duration::from_secs(3600)),
+//!         ..Default::default()
+//!     };
+//!     bidi_zero_copy(&mut left, &mut right, opts).await;
 //! };
 //! ```
 //!
-//! ## About Brutal Shutdown
+//! ## About Shutdown
 //!
-//! By default, [`bidi_copy_buf`] and other IO functions perform a **graceful shutdown**.
+//! By default, [`bidi_copy_buf`] and other IO functions perform a **graceful shutdown**,
+//! which forwards the `FIN` packet and waits for the other direction to finish.
 //!
-//! With the feature `brutal-shutdown` enabled, these IO functions will decide to
+//! With [`Shutdown::Brutal`], these IO functions will decide to
 //! perform a **brutal shutdown** once a `FIN` packet reaches, which will forcefully
 //! close two connections on both sides without waiting for a reply packet.
 //!
 //! This is helpful when handling connections from a poorly implemented client or server,
 //! which may never shutdown its write side nor close the underlying socket.
 //!
+//! [`Shutdown::HalfClose`] stays in between, it waits for the other direction
+//! for a while, then fails with a timeout error.
+//!
 
 mod buf;
 mod mem_copy;
 mod bidi_copy;
 
 pub use buf::{AsyncIOBuf, CopyBuffer};
-pub use bidi_copy::bidi_copy_buf;
+pub use bidi_copy::{bidi_copy_buf, CopyOpts, Expired, Shutdown};
 pub use mem_copy::{bidi_copy, buf_size, set_buf_size};
 
 #[cfg(target_os = "linux")]
@@ -57,7 +70,7 @@ mod linux;
 #[cfg(any(target_os = "linux", doc))]
 #[cfg_attr(doc, doc(cfg(target_os = "linux")))]
 pub use linux::{
-    AsyncRawIO, mmsg,
+    AsyncRawIO, mmsg, offload,
     zero_copy::{Pipe, bidi_zero_copy, pipe_size, set_pipe_size},
 };
 
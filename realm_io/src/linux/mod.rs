@@ -5,6 +5,7 @@ use std::os::unix::io::AsRawFd;
 use tokio::io::Interest;
 
 pub mod mmsg;
+pub mod offload;
 pub mod zero_copy;
 
 /// Type traits of Linux objects.
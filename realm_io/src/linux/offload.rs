@@ -0,0 +1,127 @@
+//! UDP segmentation offload.
+//!
+//! With GSO(`UDP_SEGMENT`), a large buffer is sent with a single syscall,
+//! and split into datagrams of the given size by the kernel or the NIC.
+//!
+//! With GRO(`UDP_GRO`), datagrams from the same peer could be coalesced
+//! by the kernel, whose segment size is delivered in a control message.
+
+use std::io::{Result, Error};
+use std::mem::{self, MaybeUninit};
+use std::os::unix::io::AsRawFd;
+
+use libc::{c_int, c_uint, socklen_t};
+use libc::{SOL_UDP, UDP_GRO, UDP_SEGMENT};
+
+/// Max segments in a GSO buffer.
+pub const MAX_SEGMENTS: usize = 64;
+
+/// Max payload of a GSO buffer, or a coalesced GRO buffer.
+pub const MAX_PAYLOAD: usize = 65507;
+
+/// Enable or disable `UDP_GRO` on a socket.
+pub fn set_gro<S: AsRawFd>(sock: &S, gro: bool) -> Result<()> {
+    let val = gro as c_int;
+    let ret = unsafe {
+        libc::setsockopt(
+            sock.as_raw_fd(),
+            SOL_UDP,
+            UDP_GRO,
+            &val as *const _ as *const _,
+            mem::size_of::<c_int>() as socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Check if `UDP_GRO` is enabled on a socket.
+pub fn gro_enabled<S: AsRawFd>(sock: &S) -> bool {
+    let mut val: c_int = 0;
+    let mut len = mem::size_of::<c_int>() as socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            sock.as_raw_fd(),
+            SOL_UDP,
+            UDP_GRO,
+            &mut val as *mut _ as *mut _,
+            &mut len,
+        )
+    };
+    ret == 0 && val != 0
+}
+
+/// Check if an error is caused by the lack of GSO support.
+///
+/// Devices without checksum offload yield `EIO`.
+///
+/// `EINVAL` is not included, which is also returned for
+/// a single message, e.g. when the segment size exceeds the mtu.
+pub fn is_gso_unsupported(err: &Error) -> bool {
+    use libc::{EIO, EOPNOTSUPP, ENOPROTOOPT};
+    matches!(err.raw_os_error(), Some(EIO | EOPNOTSUPP | ENOPROTOOPT))
+}
+
+const CMSG_SIZE: usize = unsafe { libc::CMSG_SPACE(mem::size_of::<c_int>() as c_uint) } as usize;
+
+/// Control message buffer, which carries a segment size.
+#[derive(Clone, Copy)]
+#[repr(C, align(8))]
+pub struct SegmentCmsg([u8; CMSG_SIZE]);
+
+impl SegmentCmsg {
+    /// New zeroed storage, to receive `UDP_GRO`.
+    pub const fn new() -> Self {
+        Self([0u8; CMSG_SIZE])
+    }
+
+    /// New `UDP_SEGMENT` message, to send with GSO.
+    pub fn with_segment(size: u16) -> Self {
+        let mut cmsg = Self::new();
+        unsafe {
+            let hdr = cmsg.0.as_mut_ptr() as *mut libc::cmsghdr;
+            (*hdr).cmsg_level = SOL_UDP;
+            (*hdr).cmsg_type = UDP_SEGMENT;
+            (*hdr).cmsg_len = libc::CMSG_LEN(mem::size_of::<u16>() as c_uint) as _;
+            std::ptr::write_unaligned(libc::CMSG_DATA(hdr) as *mut u16, size);
+        }
+        cmsg
+    }
+
+    #[inline]
+    pub const fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl Default for SegmentCmsg {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Find the `UDP_GRO` segment size in received control messages.
+pub fn gro_segment(control: &[u8]) -> Option<usize> {
+    let mut mhdr: libc::msghdr = unsafe { MaybeUninit::zeroed().assume_init() };
+    mhdr.msg_control = control.as_ptr() as *mut _;
+    mhdr.msg_controllen = control.len() as _;
+
+    unsafe {
+        let mut hdr = libc::CMSG_FIRSTHDR(&mhdr);
+        while !hdr.is_null() {
+            if (*hdr).cmsg_level == SOL_UDP && (*hdr).cmsg_type == UDP_GRO {
+                let size = std::ptr::read_unaligned(libc::CMSG_DATA(hdr) as *const c_int);
+                return Some(size as usize);
+            }
+            hdr = libc::CMSG_NXTHDR(&mhdr, hdr);
+        }
+    }
+    None
+}
@@ -6,7 +6,7 @@ use std::os::unix::io::RawFd;
 use tokio::io::{AsyncRead, AsyncWrite};
 
 use crate::{CopyBuffer, AsyncIOBuf, AsyncRawIO};
-use crate::bidi_copy_buf;
+use crate::{bidi_copy_buf, CopyOpts};
 
 /// Unix pipe.
 pub struct Pipe(RawFd, RawFd);
@@ -84,7 +84,7 @@ where
     }
 }
 
-impl<'a, SR, SW> AsyncIOBuf for CopyBuffer<&'a mut Pipe, SR, SW>
+impl<SR, SW> AsyncIOBuf for CopyBuffer<&mut Pipe, SR, SW>
 where
     SR: AsyncRead + AsyncWrite + AsyncRawIO + Unpin,
     SW: AsyncRead + AsyncWrite + AsyncRawIO + Unpin,
@@ -106,14 +106,14 @@ where
 }
 
 /// Copy data bidirectionally between two streams with pipe.
-pub async fn bidi_zero_copy<A, B>(a: &mut A, b: &mut B) -> Result<(u64, u64)>
+pub async fn bidi_zero_copy<A, B>(a: &mut A, b: &mut B, opts: CopyOpts) -> Result<(u64, u64)>
 where
     A: AsyncRead + AsyncWrite + AsyncRawIO + Unpin,
     B: AsyncRead + AsyncWrite + AsyncRawIO + Unpin,
 {
     let a_to_b_buf = CopyBuffer::new(Pipe::new()?);
     let b_to_a_buf = CopyBuffer::new(Pipe::new()?);
-    bidi_copy_buf(a, b, a_to_b_buf, b_to_a_buf).await
+    bidi_copy_buf(a, b, a_to_b_buf, b_to_a_buf, opts).await
 }
 
 mod pipe_ctl {
@@ -6,7 +6,7 @@ use tokio::io::ReadBuf;
 use tokio::io::{AsyncRead, AsyncWrite};
 
 use super::{CopyBuffer, AsyncIOBuf};
-use super::bidi_copy_buf;
+use super::{bidi_copy_buf, CopyOpts};
 
 impl<B, SR, SW> AsyncIOBuf for CopyBuffer<B, SR, SW>
 where
@@ -35,14 +35,14 @@ where
 }
 
 /// Copy data bidirectionally between two streams with userspace buffer.
-pub async fn bidi_copy<A, B>(a: &mut A, b: &mut B) -> Result<(u64, u64)>
+pub async fn bidi_copy<A, B>(a: &mut A, b: &mut B, opts: CopyOpts) -> Result<(u64, u64)>
 where
     A: AsyncRead + AsyncWrite + Unpin,
     B: AsyncRead + AsyncWrite + Unpin,
 {
     let a_to_b_buf = CopyBuffer::new(vec![0u8; buf_size()].into_boxed_slice());
     let b_to_a_buf = CopyBuffer::new(vec![0u8; buf_size()].into_boxed_slice());
-    bidi_copy_buf(a, b, a_to_b_buf, b_to_a_buf).await
+    bidi_copy_buf(a, b, a_to_b_buf, b_to_a_buf, opts).await
 }
 
 mod buf_ctl {
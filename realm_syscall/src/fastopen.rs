@@ -0,0 +1,27 @@
+use std::io::Result;
+use std::os::unix::io::AsRawFd;
+
+use libc::c_int;
+use libc::{IPPROTO_TCP, TCP_FASTOPEN, TCP_FASTOPEN_CONNECT};
+
+use crate::socket::set_int;
+
+/// Enable TCP Fast Open on a listener, with `TCP_FASTOPEN`.
+///
+/// `queue` is the max length of pending connections which
+/// have not completed the handshake, 0 to disable.
+///
+/// The server side of `net.ipv4.tcp_fastopen` sysctl should be enabled.
+pub fn set_tcp_fastopen<T: AsRawFd>(socket: &T, queue: u32) -> Result<()> {
+    set_int(socket, IPPROTO_TCP, TCP_FASTOPEN, queue.min(c_int::MAX as u32) as c_int)
+}
+
+/// Enable TCP Fast Open on an outbound socket, with `TCP_FASTOPEN_CONNECT`.
+///
+/// `connect` returns at once, and the SYN is delayed until the first write,
+/// carrying the written data if there is a cookie of the peer.
+///
+/// The client side of `net.ipv4.tcp_fastopen` sysctl should be enabled.
+pub fn set_tcp_fastopen_connect<T: AsRawFd>(socket: &T, fastopen: bool) -> Result<()> {
+    set_int(socket, IPPROTO_TCP, TCP_FASTOPEN_CONNECT, fastopen as c_int)
+}
@@ -5,6 +5,16 @@ mod daemon;
 #[cfg(unix)]
 pub use daemon::*;
 
+#[cfg(unix)]
+mod privilege;
+#[cfg(unix)]
+pub use privilege::*;
+
+#[cfg(unix)]
+mod systemd;
+#[cfg(unix)]
+pub use systemd::*;
+
 #[cfg(all(unix, not(target_os = "android")))]
 mod nofile;
 #[cfg(all(unix, not(target_os = "android")))]
@@ -12,4 +22,39 @@ pub use nofile::*;
 
 mod socket;
 pub use socket::*;
+
+#[cfg(target_os = "linux")]
+mod reuseport;
+#[cfg(target_os = "linux")]
+pub use reuseport::*;
+
+#[cfg(target_os = "linux")]
+mod transparent;
+#[cfg(target_os = "linux")]
+pub use transparent::*;
+
+#[cfg(target_os = "linux")]
+mod fastopen;
+#[cfg(target_os = "linux")]
+pub use fastopen::*;
+
+#[cfg(target_os = "linux")]
+mod redirect;
+#[cfg(target_os = "linux")]
+pub use redirect::*;
 pub use socket2;
+
+#[cfg(target_os = "linux")]
+mod pktinfo;
+#[cfg(target_os = "linux")]
+pub use pktinfo::*;
+
+#[cfg(target_os = "linux")]
+mod seccomp;
+#[cfg(target_os = "linux")]
+pub use seccomp::*;
+
+#[cfg(target_os = "linux")]
+mod qos;
+#[cfg(target_os = "linux")]
+pub use qos::*;
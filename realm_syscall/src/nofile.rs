@@ -35,6 +35,8 @@ pub fn get_nofile_limit() -> Result<(u64, u64)> {
     if unsafe { libc::getrlimit(RLIMIT_NOFILE, &mut lim as *mut _) } < 0 {
         Err(Error::last_os_error())
     } else {
+        // rlim_t is not u64 on every platform
+        #[allow(clippy::unnecessary_cast)]
         Ok((lim.rlim_cur as u64, lim.rlim_max as u64))
     }
 }
@@ -0,0 +1,103 @@
+use std::io::{Result, Error, ErrorKind};
+use std::mem::{self, MaybeUninit};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::os::unix::io::AsRawFd;
+
+use libc::{c_int, c_uint, socklen_t, in_pktinfo, in6_pktinfo};
+use libc::{IPPROTO_IP, IP_PKTINFO, IPPROTO_IPV6, IPV6_RECVPKTINFO, IPV6_PKTINFO};
+use socket2::SockAddr;
+
+use crate::socket::set_int;
+
+const CMSG_SIZE: usize = unsafe { libc::CMSG_SPACE(mem::size_of::<in6_pktinfo>() as c_uint) } as usize;
+
+/// Receive the destination address of each datagram, with
+/// `IP_PKTINFO`(or `IPV6_RECVPKTINFO`), see [`recv_from_to`].
+///
+/// A socket bound to a wildcard address does not know which of
+/// the local addresses a datagram is sent to without it.
+///
+/// On an ipv6 socket, ipv4 datagrams carry ipv4-mapped addresses.
+pub fn set_recv_pktinfo<T: AsRawFd>(socket: &T, ipv6: bool, enable: bool) -> Result<()> {
+    let val = enable as c_int;
+    if ipv6 {
+        set_int(socket, IPPROTO_IPV6, IPV6_RECVPKTINFO, val)
+    } else {
+        set_int(socket, IPPROTO_IP, IP_PKTINFO, val)
+    }
+}
+
+/// Check if [`set_recv_pktinfo`] is enabled on a socket.
+pub fn recv_pktinfo_enabled<T: AsRawFd>(socket: &T, ipv6: bool) -> bool {
+    let (level, name) = if ipv6 {
+        (IPPROTO_IPV6, IPV6_RECVPKTINFO)
+    } else {
+        (IPPROTO_IP, IP_PKTINFO)
+    };
+
+    let mut val: c_int = 0;
+    let mut len = mem::size_of::<c_int>() as socklen_t;
+    let ret = unsafe { libc::getsockopt(socket.as_raw_fd(), level, name, &mut val as *mut _ as *mut _, &mut len) };
+    ret == 0 && val != 0
+}
+
+/// Receive a datagram from a non-blocking socket, returns the size,
+/// the source address, and the destination address if provided.
+///
+/// The destination is only available with [`set_recv_pktinfo`].
+pub fn recv_from_to<T: AsRawFd>(socket: &T, buf: &mut [u8]) -> Result<(usize, SocketAddr, Option<IpAddr>)> {
+    let mut control = [0u8; CMSG_SIZE];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut _,
+        iov_len: buf.len(),
+    };
+
+    let mut mhdr: libc::msghdr = unsafe { MaybeUninit::zeroed().assume_init() };
+    let mut n = 0;
+
+    let (_, addr) = unsafe {
+        SockAddr::try_init(|storage, len| {
+            mhdr.msg_name = storage as *mut _;
+            mhdr.msg_namelen = *len;
+            mhdr.msg_iov = &mut iov;
+            mhdr.msg_iovlen = 1;
+            mhdr.msg_control = control.as_mut_ptr() as *mut _;
+            mhdr.msg_controllen = control.len() as _;
+
+            let ret = libc::recvmsg(socket.as_raw_fd(), &mut mhdr, 0);
+            if ret < 0 {
+                return Err(Error::last_os_error());
+            }
+            n = ret as usize;
+            *len = mhdr.msg_namelen;
+            Ok(())
+        })
+    }?;
+
+    let src = addr
+        .as_socket()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid source address"))?;
+
+    Ok((n, src, pktinfo_dst(&mhdr)))
+}
+
+fn pktinfo_dst(mhdr: &libc::msghdr) -> Option<IpAddr> {
+    unsafe {
+        let mut hdr = libc::CMSG_FIRSTHDR(mhdr);
+        while !hdr.is_null() {
+            match ((*hdr).cmsg_level, (*hdr).cmsg_type) {
+                (IPPROTO_IP, IP_PKTINFO) => {
+                    let info = std::ptr::read_unaligned(libc::CMSG_DATA(hdr) as *const in_pktinfo);
+                    return Some(Ipv4Addr::from(u32::from_be(info.ipi_addr.s_addr)).into());
+                }
+                (IPPROTO_IPV6, IPV6_PKTINFO) => {
+                    let info = std::ptr::read_unaligned(libc::CMSG_DATA(hdr) as *const in6_pktinfo);
+                    return Some(Ipv6Addr::from(info.ipi6_addr.s6_addr).into());
+                }
+                _ => {}
+            }
+            hdr = libc::CMSG_NXTHDR(mhdr, hdr);
+        }
+    }
+    None
+}
@@ -0,0 +1,224 @@
+use std::ffi::CString;
+use std::fmt::{Display, Formatter};
+use std::io::{Result, Error, ErrorKind};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::str::FromStr;
+
+use libc::{uid_t, gid_t, c_char};
+
+/// Capabilities which could be retained after dropping privileges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// Bind to ports below 1024.
+    NetBindService,
+    /// Bind to a device, set `SO_MARK`, `IP_TRANSPARENT`, etc.
+    NetAdmin,
+    /// Use raw sockets, or `IP_TRANSPARENT`.
+    NetRaw,
+}
+
+impl Capability {
+    #[cfg(target_os = "linux")]
+    fn bit(self) -> u32 {
+        match self {
+            Capability::NetBindService => 10,
+            Capability::NetAdmin => 12,
+            Capability::NetRaw => 13,
+        }
+    }
+}
+
+impl FromStr for Capability {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim().to_ascii_lowercase();
+        match s.strip_prefix("cap_").unwrap_or(&s) {
+            "net_bind_service" => Ok(Capability::NetBindService),
+            "net_admin" => Ok(Capability::NetAdmin),
+            "net_raw" => Ok(Capability::NetRaw),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown capability: {}", s),
+            )),
+        }
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Capability::NetBindService => "net_bind_service",
+            Capability::NetAdmin => "net_admin",
+            Capability::NetRaw => "net_raw",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+fn cstring(s: &[u8]) -> Result<CString> {
+    CString::new(s).map_err(|_| Error::new(ErrorKind::InvalidInput, "unexpected nul byte"))
+}
+
+/// Real uid and gid of the current process.
+pub fn current_user() -> (uid_t, gid_t) {
+    unsafe { (libc::getuid(), libc::getgid()) }
+}
+
+/// Look up the uid and primary gid of a user, by name or id.
+pub fn lookup_user(user: &str) -> Result<(uid_t, gid_t)> {
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as c_char; 4096];
+    let mut res = std::ptr::null_mut();
+
+    let name = cstring(user.as_bytes())?;
+    let ret = match user.parse::<uid_t>() {
+        Ok(uid) => unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut res) },
+        Err(_) => unsafe { libc::getpwnam_r(name.as_ptr(), &mut pwd, buf.as_mut_ptr(), buf.len(), &mut res) },
+    };
+
+    if ret != 0 {
+        return Err(Error::from_raw_os_error(ret));
+    }
+    if res.is_null() {
+        return Err(Error::new(ErrorKind::NotFound, format!("unknown user: {}", user)));
+    }
+    Ok((pwd.pw_uid, pwd.pw_gid))
+}
+
+/// Look up the gid of a group, by name or id.
+pub fn lookup_group(group: &str) -> Result<gid_t> {
+    if let Ok(gid) = group.parse::<gid_t>() {
+        return Ok(gid);
+    }
+
+    let mut grp: libc::group = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as c_char; 4096];
+    let mut res = std::ptr::null_mut();
+
+    let name = cstring(group.as_bytes())?;
+    let ret = unsafe { libc::getgrnam_r(name.as_ptr(), &mut grp, buf.as_mut_ptr(), buf.len(), &mut res) };
+
+    if ret != 0 {
+        return Err(Error::from_raw_os_error(ret));
+    }
+    if res.is_null() {
+        return Err(Error::new(ErrorKind::NotFound, format!("unknown group: {}", group)));
+    }
+    Ok(grp.gr_gid)
+}
+
+/// Change the root directory, then enter it.
+///
+/// `CAP_SYS_CHROOT` privilege is required.
+pub fn chroot<P: AsRef<Path>>(dir: P) -> Result<()> {
+    let dir = cstring(dir.as_ref().as_os_str().as_bytes())?;
+
+    if unsafe { libc::chroot(dir.as_ptr()) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    std::env::set_current_dir("/")
+}
+
+/// Switch to another user and group, with `setgroups`, `setgid` and `setuid`.
+///
+/// Capabilities in `keep_caps` are retained, others are dropped.
+/// This is only supported on Linux.
+///
+/// It should be called before any other threads are spawned,
+/// as capabilities are set per thread.
+pub fn drop_privileges(uid: uid_t, gid: gid_t, keep_caps: &[Capability]) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    if !keep_caps.is_empty() && unsafe { libc::prctl(libc::PR_SET_KEEPCAPS, 1, 0, 0, 0) } < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    if !keep_caps.is_empty() {
+        return Err(Error::new(ErrorKind::Unsupported, "capabilities"));
+    }
+
+    unsafe {
+        if libc::setgroups(1, &gid) < 0 || libc::setgid(gid) < 0 || libc::setuid(uid) < 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    if !keep_caps.is_empty() {
+        set_capabilities(keep_caps)?;
+        if unsafe { libc::prctl(libc::PR_SET_KEEPCAPS, 0, 0, 0, 0) } < 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+
+    // make sure there is no way back
+    if uid != 0 && unsafe { libc::setuid(0) } == 0 {
+        return Err(Error::new(ErrorKind::PermissionDenied, "privileges are not dropped"));
+    }
+
+    Ok(())
+}
+
+/// Set permitted, effective and inheritable capabilities of the current thread, with `capset`.
+#[cfg(target_os = "linux")]
+pub fn set_capabilities(caps: &[Capability]) -> Result<()> {
+    #[repr(C)]
+    struct Header {
+        version: u32,
+        pid: libc::c_int,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct Data {
+        effective: u32,
+        permitted: u32,
+        inheritable: u32,
+    }
+
+    const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+
+    let mut header = Header {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut data = [Data::default(); 2];
+    for cap in caps {
+        let bit = cap.bit();
+        let x = &mut data[(bit / 32) as usize];
+        x.effective |= 1 << (bit % 32);
+        x.permitted |= 1 << (bit % 32);
+        x.inheritable |= 1 << (bit % 32);
+    }
+
+    if unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } < 0 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Add capabilities to the ambient set, so that they are kept across `execve`
+/// by a process which is not root, e.g. a new process started by an upgrade.
+///
+/// They must be permitted and inheritable, see [`set_capabilities`].
+#[cfg(target_os = "linux")]
+pub fn raise_ambient_capabilities(caps: &[Capability]) -> Result<()> {
+    for cap in caps {
+        let ret = unsafe {
+            libc::prctl(
+                libc::PR_CAP_AMBIENT,
+                libc::PR_CAP_AMBIENT_RAISE,
+                cap.bit() as libc::c_ulong,
+                0,
+                0,
+            )
+        };
+        if ret < 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+    Ok(())
+}
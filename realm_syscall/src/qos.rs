@@ -0,0 +1,62 @@
+use std::io::{Result, Error, ErrorKind};
+use std::os::unix::io::AsRawFd;
+
+use libc::c_int;
+use libc::{SOL_SOCKET, SO_MARK};
+use libc::{IPPROTO_IP, IP_TOS, IPPROTO_IPV6, IPV6_TCLASS};
+use libc::{IPPROTO_TCP, TCP_CONGESTION};
+
+use crate::socket::set_int;
+
+/// Set the firewall mark of a socket, with `SO_MARK`.
+///
+/// Packets sent through the socket carry the mark,
+/// which could be matched by policy routing rules or netfilter.
+///
+/// `CAP_NET_ADMIN` privilege is required.
+pub fn set_mark<T: AsRawFd>(socket: &T, mark: u32) -> Result<()> {
+    set_int(socket, SOL_SOCKET, SO_MARK, mark as c_int)
+}
+
+/// Set the type of service field of outgoing packets,
+/// with `IP_TOS`(or `IPV6_TCLASS`).
+///
+/// The higher 6 bits are DSCP, and the lower 2 bits are ECN,
+/// which are usually overridden by the kernel.
+///
+/// On an ipv6 socket, `IP_TOS` is also set for ipv4-mapped peers.
+pub fn set_tos<T: AsRawFd>(socket: &T, ipv6: bool, tos: u8) -> Result<()> {
+    if ipv6 {
+        set_int(socket, IPPROTO_IPV6, IPV6_TCLASS, tos as c_int)?;
+        // ignore error, it fails on an ipv6-only socket
+        let _ = set_int(socket, IPPROTO_IP, IP_TOS, tos as c_int);
+        Ok(())
+    } else {
+        set_int(socket, IPPROTO_IP, IP_TOS, tos as c_int)
+    }
+}
+
+/// Set the congestion control algorithm of a tcp socket, with `TCP_CONGESTION`.
+///
+/// The algorithm should be listed in `net.ipv4.tcp_available_congestion_control`,
+/// or `CAP_NET_ADMIN` privilege is required to load its module.
+pub fn set_tcp_congestion<T: AsRawFd>(socket: &T, name: &str) -> Result<()> {
+    if name.is_empty() || name.len() >= 16 || name.contains('\0') {
+        return Err(Error::new(ErrorKind::InvalidInput, "invalid congestion control name"));
+    }
+
+    if unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            IPPROTO_TCP,
+            TCP_CONGESTION,
+            name.as_ptr() as *const libc::c_void,
+            name.len() as libc::socklen_t,
+        )
+    } < 0
+    {
+        Err(Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+use std::io::{Result, Error, ErrorKind};
+use std::net::SocketAddr;
+use std::os::unix::io::AsRawFd;
+
+use libc::{SOL_IP, SOL_IPV6, SO_ORIGINAL_DST, IP6T_SO_ORIGINAL_DST};
+use socket2::SockAddr;
+
+/// Get the original destination of a connection redirected by
+/// iptables or nftables `REDIRECT`/`DNAT` rules,
+/// with `SO_ORIGINAL_DST`(or `IP6T_SO_ORIGINAL_DST`).
+///
+/// It is looked up from conntrack, and fails with `ENOENT`
+/// if the connection is not tracked.
+pub fn original_dst<T: AsRawFd>(socket: &T, ipv6: bool) -> Result<SocketAddr> {
+    let (level, name) = if ipv6 {
+        (SOL_IPV6, IP6T_SO_ORIGINAL_DST)
+    } else {
+        (SOL_IP, SO_ORIGINAL_DST)
+    };
+
+    let (_, addr) = unsafe {
+        SockAddr::try_init(|storage, len| {
+            if libc::getsockopt(socket.as_raw_fd(), level, name, storage as *mut _, len) < 0 {
+                Err(Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        })
+    }?;
+
+    addr.as_socket()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid original destination"))
+}
@@ -0,0 +1,55 @@
+use std::io::{Result, Error};
+use std::os::unix::io::AsRawFd;
+
+use libc::{sock_filter, sock_fprog, socklen_t};
+use libc::{BPF_LD, BPF_W, BPF_ABS, BPF_ALU, BPF_MOD, BPF_K, BPF_RET, BPF_A};
+use libc::{SKF_AD_OFF, SKF_AD_CPU, SOL_SOCKET, SO_ATTACH_REUSEPORT_CBPF};
+
+const fn stmt(code: u32, k: u32) -> sock_filter {
+    sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+/// Steer connections or packets of a `SO_REUSEPORT` group by cpu.
+///
+/// A classic BPF program is attached to the group, which selects
+/// the `cpu % n`th socket, in the order they were bound.
+/// It could be attached to any socket in the group.
+pub fn attach_reuseport_cpu_bpf<T: AsRawFd>(socket: &T, n: u32) -> Result<()> {
+    if n == 0 {
+        return Err(Error::other("empty reuseport group"));
+    }
+
+    let mut filter = [
+        // A = current cpu
+        stmt(BPF_LD | BPF_W | BPF_ABS, (SKF_AD_OFF + SKF_AD_CPU) as u32),
+        // A = A % n
+        stmt(BPF_ALU | BPF_MOD | BPF_K, n),
+        // return A
+        stmt(BPF_RET | BPF_A, 0),
+    ];
+
+    let prog = sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+
+    if unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            SOL_SOCKET,
+            SO_ATTACH_REUSEPORT_CBPF,
+            &prog as *const _ as *const libc::c_void,
+            std::mem::size_of::<sock_fprog>() as socklen_t,
+        )
+    } < 0
+    {
+        Err(Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
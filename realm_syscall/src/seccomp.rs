@@ -0,0 +1,316 @@
+#![cfg_attr(not(any(target_arch = "x86_64", target_arch = "aarch64")), allow(unused))]
+
+use std::fmt::{Display, Formatter};
+use std::io::{Result, Error, ErrorKind};
+use std::str::FromStr;
+
+use libc::{sock_filter, sock_fprog, c_int, c_long};
+use libc::{BPF_LD, BPF_W, BPF_ABS, BPF_JMP, BPF_JEQ, BPF_JGE, BPF_JSET, BPF_K, BPF_RET};
+use libc::{SECCOMP_RET_ALLOW, SECCOMP_RET_ERRNO, SECCOMP_RET_KILL_PROCESS, SECCOMP_RET_LOG};
+use libc::{SECCOMP_SET_MODE_FILTER, SECCOMP_FILTER_FLAG_TSYNC};
+
+/// What happens when a syscall out of [`SECCOMP_ALLOWED`] is made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeccompMode {
+    /// Kill the process.
+    Enforce,
+    /// Allow the syscall, and record it in the audit log.
+    Log,
+}
+
+impl FromStr for SeccompMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "enforce" => Ok(SeccompMode::Enforce),
+            "log" => Ok(SeccompMode::Log),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown seccomp mode: {}", s),
+            )),
+        }
+    }
+}
+
+impl Display for SeccompMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            SeccompMode::Enforce => "enforce",
+            SeccompMode::Log => "log",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+// x32 syscalls share the same arch with x86_64
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+// offsets of struct seccomp_data
+const OFFSET_NR: u32 = 0;
+const OFFSET_ARCH: u32 = 4;
+// lower half of the first argument, both arches are little endian
+const OFFSET_ARG0: u32 = 16;
+
+/// Syscalls required by a relay, including the runtime and resolver.
+///
+/// `clone` is only allowed to start threads, and `prctl` is limited to [`PRCTL_ALLOWED`].
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub const SECCOMP_ALLOWED: &[c_long] = &[
+    // io
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_readv,
+    libc::SYS_writev,
+    libc::SYS_pread64,
+    libc::SYS_pwrite64,
+    libc::SYS_lseek,
+    libc::SYS_close,
+    libc::SYS_openat,
+    libc::SYS_newfstatat,
+    libc::SYS_fstat,
+    libc::SYS_statx,
+    libc::SYS_readlinkat,
+    libc::SYS_faccessat,
+    libc::SYS_unlinkat,
+    libc::SYS_getcwd,
+    libc::SYS_ioctl,
+    libc::SYS_fcntl,
+    libc::SYS_dup,
+    libc::SYS_dup3,
+    libc::SYS_pipe2,
+    libc::SYS_splice,
+    // network
+    libc::SYS_socket,
+    libc::SYS_socketpair,
+    libc::SYS_bind,
+    libc::SYS_listen,
+    libc::SYS_accept4,
+    libc::SYS_connect,
+    libc::SYS_shutdown,
+    libc::SYS_getsockname,
+    libc::SYS_getpeername,
+    libc::SYS_setsockopt,
+    libc::SYS_getsockopt,
+    libc::SYS_sendto,
+    libc::SYS_recvfrom,
+    libc::SYS_sendmsg,
+    libc::SYS_recvmsg,
+    libc::SYS_sendmmsg,
+    libc::SYS_recvmmsg,
+    // poll
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_ctl,
+    libc::SYS_epoll_pwait,
+    libc::SYS_epoll_pwait2,
+    libc::SYS_eventfd2,
+    libc::SYS_ppoll,
+    // memory
+    libc::SYS_brk,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    // thread
+    libc::SYS_futex,
+    libc::SYS_set_robust_list,
+    libc::SYS_set_tid_address,
+    libc::SYS_rseq,
+    libc::SYS_sched_yield,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_sched_setaffinity,
+    libc::SYS_gettid,
+    // signal
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack,
+    libc::SYS_tgkill,
+    libc::SYS_restart_syscall,
+    // process
+    libc::SYS_getpid,
+    libc::SYS_getppid,
+    libc::SYS_getuid,
+    libc::SYS_geteuid,
+    libc::SYS_getgid,
+    libc::SYS_getegid,
+    libc::SYS_prlimit64,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    // time
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_nanosleep,
+    libc::SYS_gettimeofday,
+    // misc
+    libc::SYS_getrandom,
+    libc::SYS_uname,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_arch_prctl,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_open,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_stat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_lstat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_access,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_readlink,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_unlink,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_dup2,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_pipe,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_poll,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_epoll_wait,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_epoll_create,
+];
+
+/// Options of `prctl` used by the runtime, or by a new process on upgrade.
+pub const PRCTL_ALLOWED: &[c_int] = &[
+    libc::PR_SET_NAME,
+    libc::PR_GET_NAME,
+    libc::PR_SET_VMA,
+    libc::PR_SET_NO_NEW_PRIVS,
+];
+
+/// Syscalls required by an upgrade, which starts a new process,
+/// then stops it if it is not ready in time.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub const SECCOMP_UPGRADE: &[c_long] = &[
+    libc::SYS_clone,
+    libc::SYS_clone3,
+    libc::SYS_execve,
+    libc::SYS_kill,
+    libc::SYS_wait4,
+    libc::SYS_waitid,
+    libc::SYS_pidfd_open,
+    libc::SYS_pidfd_send_signal,
+    // the pid file is rewritten with the new process
+    libc::SYS_ftruncate,
+    // installed again by the new process
+    libc::SYS_seccomp,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_vfork,
+];
+
+const fn stmt(code: u32, k: u32) -> sock_filter {
+    sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+const fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+/// Restrict the process to syscalls in [`SECCOMP_ALLOWED`] with a seccomp-bpf filter,
+/// which applies to all threads, and threads or processes started later.
+///
+/// Syscalls in [`SECCOMP_UPGRADE`] are also allowed if `allow_upgrade` is set,
+/// otherwise `clone3` fails with `ENOSYS` so that threads are started with `clone`.
+///
+/// It could not be removed once installed.
+/// Only `x86_64` and `aarch64` are supported.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub fn apply_seccomp(mode: SeccompMode, allow_upgrade: bool) -> Result<()> {
+    let deny = match mode {
+        SeccompMode::Enforce => SECCOMP_RET_KILL_PROCESS,
+        SeccompMode::Log => SECCOMP_RET_LOG,
+    };
+
+    let mut filter = Vec::with_capacity(2 * (SECCOMP_ALLOWED.len() + SECCOMP_UPGRADE.len() + PRCTL_ALLOWED.len()) + 16);
+    // A = arch, kill if another abi is used
+    filter.push(stmt(BPF_LD | BPF_W | BPF_ABS, OFFSET_ARCH));
+    filter.push(jump(BPF_JMP | BPF_JEQ | BPF_K, AUDIT_ARCH, 1, 0));
+    filter.push(stmt(BPF_RET | BPF_K, SECCOMP_RET_KILL_PROCESS));
+    // A = syscall number
+    filter.push(stmt(BPF_LD | BPF_W | BPF_ABS, OFFSET_NR));
+    #[cfg(target_arch = "x86_64")]
+    {
+        filter.push(jump(BPF_JMP | BPF_JGE | BPF_K, X32_SYSCALL_BIT, 0, 1));
+        filter.push(stmt(BPF_RET | BPF_K, SECCOMP_RET_KILL_PROCESS));
+    }
+    // prctl: check the option, A is not restored since all branches return
+    filter.push(jump(
+        BPF_JMP | BPF_JEQ | BPF_K,
+        libc::SYS_prctl as u32,
+        0,
+        2 * PRCTL_ALLOWED.len() as u8 + 2,
+    ));
+    filter.push(stmt(BPF_LD | BPF_W | BPF_ABS, OFFSET_ARG0));
+    for opt in PRCTL_ALLOWED {
+        filter.push(jump(BPF_JMP | BPF_JEQ | BPF_K, *opt as u32, 0, 1));
+        filter.push(stmt(BPF_RET | BPF_K, SECCOMP_RET_ALLOW));
+    }
+    filter.push(stmt(BPF_RET | BPF_K, deny));
+    if !allow_upgrade {
+        // flags of clone3 are behind a pointer, let libc fall back to clone
+        filter.push(jump(BPF_JMP | BPF_JEQ | BPF_K, libc::SYS_clone3 as u32, 0, 1));
+        filter.push(stmt(BPF_RET | BPF_K, SECCOMP_RET_ERRNO | libc::ENOSYS as u32));
+        // clone: start a thread, not a process
+        filter.push(jump(BPF_JMP | BPF_JEQ | BPF_K, libc::SYS_clone as u32, 0, 4));
+        filter.push(stmt(BPF_LD | BPF_W | BPF_ABS, OFFSET_ARG0));
+        filter.push(jump(BPF_JMP | BPF_JSET | BPF_K, libc::CLONE_THREAD as u32, 0, 1));
+        filter.push(stmt(BPF_RET | BPF_K, SECCOMP_RET_ALLOW));
+        filter.push(stmt(BPF_RET | BPF_K, deny));
+    }
+    // return ALLOW if A == nr
+    let upgrade = if allow_upgrade { SECCOMP_UPGRADE } else { &[] };
+    for nr in SECCOMP_ALLOWED.iter().chain(upgrade) {
+        filter.push(jump(BPF_JMP | BPF_JEQ | BPF_K, *nr as u32, 0, 1));
+        filter.push(stmt(BPF_RET | BPF_K, SECCOMP_RET_ALLOW));
+    }
+    filter.push(stmt(BPF_RET | BPF_K, deny));
+
+    let prog = sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+
+    // required without CAP_SYS_ADMIN
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            SECCOMP_SET_MODE_FILTER,
+            SECCOMP_FILTER_FLAG_TSYNC,
+            &prog as *const sock_fprog,
+        )
+    };
+    match ret {
+        0 => Ok(()),
+        x if x < 0 => Err(Error::last_os_error()),
+        // tid of a thread which could not be synchronized
+        x => Err(Error::other(format!("failed to synchronize thread {}", x))),
+    }
+}
+
+/// Seccomp is not supported on this architecture.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn apply_seccomp(_mode: SeccompMode, _allow_upgrade: bool) -> Result<()> {
+    Err(Error::new(ErrorKind::Unsupported, "seccomp on this architecture"))
+}
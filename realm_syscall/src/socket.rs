@@ -101,6 +101,30 @@ pub fn new_udp_socket(addr: &SocketAddr) -> Result<Socket> {
     new_socket(domain, Type::DGRAM, Protocol::UDP)
 }
 
+/// Set an integer socket option.
+#[cfg(target_os = "linux")]
+pub(crate) fn set_int<T: std::os::unix::io::AsRawFd>(
+    socket: &T,
+    level: libc::c_int,
+    name: libc::c_int,
+    val: libc::c_int,
+) -> std::io::Result<()> {
+    if unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &val as *const _ as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    } < 0
+    {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
 /// Bind a socket to a specific network interface.
 ///
 /// It seems `SO_BINDTODEVICE` is not supported on BSDs, we should use `IP_SENDIF` instead.
@@ -0,0 +1,177 @@
+use std::env;
+use std::ffi::OsStr;
+use std::io::{Result, Error, ErrorKind};
+use std::os::unix::io::{OwnedFd, RawFd, AsRawFd, FromRawFd};
+use std::os::unix::net::UnixDatagram;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::time::Duration;
+
+/// The first file descriptor passed by systemd.
+pub const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// Take the file descriptors passed by systemd, with their names,
+/// see `sd_listen_fds_with_names(3)`.
+///
+/// `LISTEN_PID`, `LISTEN_FDS` and `LISTEN_FDNAMES` are unset,
+/// so that they are not taken twice, or inherited by child processes.
+///
+/// Returns an empty list if they are not passed to this process,
+/// i.e. `LISTEN_PID` is absent or not the pid of this process.
+pub fn listen_fds() -> Result<Vec<(OwnedFd, Option<String>)>> {
+    let pid = env::var("LISTEN_PID");
+    env::remove_var("LISTEN_PID");
+
+    if pid.ok().and_then(|x| x.parse::<u32>().ok()) != Some(std::process::id()) {
+        env::remove_var("LISTEN_FDS");
+        env::remove_var("LISTEN_FDNAMES");
+        return Ok(Vec::new());
+    }
+
+    take_fds("LISTEN_FDS", "LISTEN_FDNAMES")
+}
+
+/// Take the file descriptors passed by [`pass_fds`], with their names.
+///
+/// The number of them is read from `fds_var`, and their names
+/// are read from `names_var`, both are unset.
+///
+/// Returns an empty list if `fds_var` is absent.
+pub fn take_fds(fds_var: &str, names_var: &str) -> Result<Vec<(OwnedFd, Option<String>)>> {
+    let fds = env::var(fds_var);
+    let names = env::var(names_var);
+
+    env::remove_var(fds_var);
+    env::remove_var(names_var);
+
+    let n = match fds {
+        Ok(x) => x
+            .parse::<RawFd>()
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("invalid {}", fds_var)))?,
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    let mut names = names
+        .unwrap_or_default()
+        .split(':')
+        .map(String::from)
+        .collect::<Vec<_>>();
+    names.resize(n.max(0) as usize, String::new());
+
+    (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + n)
+        .zip(names)
+        .map(|(fd, name)| {
+            if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+                return Err(Error::last_os_error());
+            }
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            Ok((fd, (!name.is_empty()).then_some(name)))
+        })
+        .collect()
+}
+
+/// Pass file descriptors to a child process, which could be taken by [`take_fds`].
+///
+/// They are placed from [`SD_LISTEN_FDS_START`] in the child process,
+/// the number of them is set to `fds_var`, and their names are set to `names_var`.
+/// Use private names rather than `LISTEN_FDS`, which requires `LISTEN_PID`.
+///
+/// Returns duplicated descriptors, which should be kept open until the child is spawned.
+pub fn pass_fds(
+    cmd: &mut Command,
+    fds: &[(OwnedFd, Option<String>)],
+    fds_var: &str,
+    names_var: &str,
+) -> Result<Vec<OwnedFd>> {
+    let n = fds.len() as RawFd;
+    let names = fds.iter().map(|(_, x)| x.as_deref().unwrap_or("")).collect::<Vec<_>>();
+
+    // move above the target range, so that none is overwritten by dup2
+    let dups = fds
+        .iter()
+        .map(|(fd, _)| {
+            let x = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, SD_LISTEN_FDS_START + n) };
+            if x < 0 {
+                return Err(Error::last_os_error());
+            }
+            Ok(unsafe { OwnedFd::from_raw_fd(x) })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let raw = dups.iter().map(|x| x.as_raw_fd()).collect::<Vec<_>>();
+
+    cmd.env(fds_var, n.to_string()).env(names_var, names.join(":"));
+
+    // dup2 clears close-on-exec, and is async-signal-safe
+    unsafe {
+        cmd.pre_exec(move || {
+            for (idx, fd) in raw.iter().enumerate() {
+                if libc::dup2(*fd, SD_LISTEN_FDS_START + idx as RawFd) < 0 {
+                    return Err(Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+
+    Ok(dups)
+}
+
+/// Notify systemd of a state change, e.g. `READY=1`, see `sd_notify(3)`.
+///
+/// The message is sent to `NOTIFY_SOCKET`, which could be
+/// a path or an abstract name(prefixed by `@`).
+///
+/// Returns `false` if `NOTIFY_SOCKET` is not set.
+pub fn sd_notify(state: &str) -> Result<bool> {
+    match env::var_os("NOTIFY_SOCKET") {
+        Some(x) if !x.is_empty() => sd_notify_to(&x, state).map(|_| true),
+        _ => Ok(false),
+    }
+}
+
+/// Send a notification to a specific socket, see [`sd_notify`].
+pub fn sd_notify_to(path: &OsStr, state: &str) -> Result<()> {
+    let socket = UnixDatagram::unbound()?;
+
+    match path.as_bytes() {
+        [b'@', name @ ..] => {
+            #[cfg(target_os = "linux")]
+            {
+                use std::os::linux::net::SocketAddrExt;
+                use std::os::unix::net::SocketAddr;
+
+                let addr = SocketAddr::from_abstract_name(name)?;
+                socket.send_to_addr(state.as_bytes(), &addr)?;
+            }
+
+            #[cfg(not(target_os = "linux"))]
+            {
+                let _ = name;
+                return Err(Error::new(ErrorKind::Unsupported, "abstract notify socket"));
+            }
+        }
+        _ => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Interval of watchdog keep-alive pings expected by systemd,
+/// from `WATCHDOG_USEC`, see `sd_watchdog_enabled(3)`.
+///
+/// Returns `None` if the watchdog is disabled or not for this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+
+    match env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()? {
+        0 => None,
+        x => Some(Duration::from_micros(x)),
+    }
+}
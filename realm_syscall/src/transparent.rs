@@ -0,0 +1,25 @@
+use std::io::Result;
+use std::os::unix::io::AsRawFd;
+
+use libc::c_int;
+use libc::{IPPROTO_IP, IPPROTO_IPV6, IP_TRANSPARENT, IPV6_TRANSPARENT};
+
+use crate::socket::set_int;
+
+/// Enable or disable `IP_TRANSPARENT`(or `IPV6_TRANSPARENT`) on a socket.
+///
+/// A transparent socket could bind to a non-local address,
+/// which is required to accept connections or packets redirected by TPROXY,
+/// or to connect with a spoofed source address.
+///
+/// `CAP_NET_ADMIN` privilege is required.
+///
+/// Reference: [tproxy](https://docs.kernel.org/networking/tproxy.html).
+pub fn set_ip_transparent<T: AsRawFd>(socket: &T, ipv6: bool, transparent: bool) -> Result<()> {
+    let val = transparent as c_int;
+    if ipv6 {
+        set_int(socket, IPPROTO_IPV6, IPV6_TRANSPARENT, val)
+    } else {
+        set_int(socket, IPPROTO_IP, IP_TRANSPARENT, val)
+    }
+}
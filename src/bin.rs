@@ -1,8 +1,9 @@
 use std::env;
+use std::path::{Path, PathBuf};
 use cfg_if::cfg_if;
 
 use realm::cmd;
-use realm::conf::{Config, FullConf, LogConf, DnsConf, EndpointInfo};
+use realm::conf::{Config, FullConf, LogConf, DnsConf, SysConf, SysInfo, EndpointInfo};
 use realm::ENV_CONFIG;
 
 cfg_if! {
@@ -49,9 +50,13 @@ fn main() {
 }
 
 fn start_from_conf(full: FullConf) {
+    // handed over to the new process on upgrade
+    let conf_str = serde_json::to_string(&full).unwrap();
+
     let FullConf {
         log: log_conf,
         dns: dns_conf,
+        sys: sys_conf,
         endpoints: endpoints_conf,
         ..
     } = full;
@@ -59,6 +64,7 @@ fn start_from_conf(full: FullConf) {
     setup_log(log_conf);
     setup_dns(dns_conf);
     setup_transport();
+    setup_systemd();
 
     let endpoints: Vec<EndpointInfo> = endpoints_conf
         .into_iter()
@@ -66,7 +72,13 @@ fn start_from_conf(full: FullConf) {
         .inspect(|x| println!("inited: {}", x.endpoint))
         .collect();
 
-    execute(endpoints);
+    let (pid_file, upgradable) = setup_sys(sys_conf, &endpoints);
+
+    execute(endpoints, conf_str, upgradable);
+
+    if let Some(path) = pid_file {
+        remove_pid_file(&path);
+    }
 }
 
 fn setup_log(log: LogConf) {
@@ -103,14 +115,166 @@ fn setup_transport() {
     }
 }
 
-fn execute(eps: Vec<EndpointInfo>) {
+fn setup_systemd() {
+    #[cfg(unix)]
+    match realm::core::systemd::inherit_listen_fds() {
+        Ok(0) => {}
+        Ok(n) => println!("inherited {} listeners", n),
+        Err(e) => eprintln!("failed to inherit listeners: {}", e),
+    }
+}
+
+/// Bind listeners, write the pid file, then chroot and switch user,
+/// finally restrict syscalls.
+///
+/// This runs before the runtime starts, when there is only one thread.
+/// Returns the pid file, and whether a new process could be started on upgrade.
+fn setup_sys(sys: SysConf, endpoints: &[EndpointInfo]) -> (Option<PathBuf>, bool) {
+    println!("sys: {}", &sys);
+
+    let info = sys.build();
+    let need_prebind = info.need_prebind();
+    let SysInfo {
+        user,
+        group,
+        #[cfg(unix)]
+        keep_caps,
+        pid_file,
+        chroot,
+        #[cfg(target_os = "linux")]
+        seccomp,
+        seccomp_upgrade,
+    } = info;
+
+    // the binary and the notify socket are out of reach after chroot,
+    // and a new process could not be started under seccomp unless allowed
+    #[cfg(target_os = "linux")]
+    let upgradable = chroot.is_none() && (seccomp.is_none() || seccomp_upgrade);
+    #[cfg(not(target_os = "linux"))]
+    let upgradable = {
+        let _ = seccomp_upgrade;
+        chroot.is_none()
+    };
+
+    #[cfg(unix)]
+    if need_prebind {
+        use realm::core::{tcp, udp};
+
+        for EndpointInfo {
+            endpoint,
+            no_tcp,
+            use_udp,
+        } in endpoints
+        {
+            if !no_tcp {
+                tcp::prebind(endpoint).unwrap_or_else(|e| panic!("[tcp]failed to bind {}: {}", endpoint.laddr, e));
+            }
+            if *use_udp {
+                udp::prebind(endpoint).unwrap_or_else(|e| panic!("[udp]failed to bind {}: {}", endpoint.laddr, e));
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    if need_prebind {
+        let _ = (endpoints, user, group, chroot);
+        panic!("user, group and chroot are not supported on this platform");
+    }
+
+    #[cfg(unix)]
+    {
+        use realm::core::realm_syscall::{current_user, lookup_user, lookup_group, chroot as change_root, drop_privileges};
+
+        // look up before chroot, /etc/passwd may be unreachable then
+        let user = user.map(|x| lookup_user(&x).unwrap_or_else(|e| panic!("failed to find user {}: {}", x, e)));
+        let group = match group {
+            Some(x) => Some(lookup_group(&x).unwrap_or_else(|e| panic!("failed to find group {}: {}", x, e))),
+            None => user.map(|(_, gid)| gid),
+        };
+
+        let current = current_user();
+        let (uid, gid) = (user.map_or(current.0, |(x, _)| x), group.unwrap_or(current.1));
+
+        // written by the old process on upgrade
+        if let Some(path) = pid_file.as_ref().filter(|_| !realm::core::upgrade::pid_file_kept()) {
+            write_pid_file(path);
+        }
+
+        if let Some(dir) = chroot {
+            change_root(&dir).unwrap_or_else(|e| panic!("failed to chroot {}: {}", dir.display(), e));
+            println!("chroot: {}", dir.display());
+        }
+
+        // already switched, e.g. started by an upgrade
+        if (user.is_some() || group.is_some()) && current != (uid, gid) {
+            drop_privileges(uid, gid, &keep_caps)
+                .unwrap_or_else(|e| panic!("failed to switch to uid={}, gid={}: {}", uid, gid, e));
+            println!("switched to uid={}, gid={}", uid, gid);
+
+            // otherwise they are lost when a new process is started on upgrade
+            #[cfg(target_os = "linux")]
+            if uid != 0 && !keep_caps.is_empty() {
+                if let Err(e) = realm::core::realm_syscall::raise_ambient_capabilities(&keep_caps) {
+                    eprintln!("failed to keep capabilities for upgrade: {}", e);
+                }
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    if let Some(path) = &pid_file {
+        write_pid_file(path);
+    }
+
+    // applied last, chroot and setuid are not allowed then
+    #[cfg(target_os = "linux")]
+    if let Some(mode) = seccomp {
+        realm::core::realm_syscall::apply_seccomp(mode, upgradable)
+            .unwrap_or_else(|e| panic!("failed to apply seccomp filter: {}", e));
+        println!("seccomp: {}", mode);
+    }
+
+    (pid_file, upgradable)
+}
+
+#[cfg(unix)]
+fn write_pid_file(path: &Path) {
+    use std::io::Write;
+
+    let res = std::fs::File::create(path).and_then(|mut f| {
+        f.write_all(format!("{}\n", std::process::id()).as_bytes())?;
+        Ok(f)
+    });
+    match res {
+        // still writable after switching user
+        Ok(f) => realm::core::upgrade::keep_pid_file(f),
+        Err(e) => eprintln!("failed to write pid file {}: {}", path.display(), e),
+    }
+}
+
+#[cfg(not(unix))]
+fn write_pid_file(path: &Path) {
+    if let Err(e) = std::fs::write(path, format!("{}\n", std::process::id())) {
+        eprintln!("failed to write pid file {}: {}", path.display(), e);
+    }
+}
+
+/// Remove the pid file, unless it is taken by another process.
+fn remove_pid_file(path: &Path) {
+    let pid = std::process::id().to_string();
+    if std::fs::read_to_string(path).is_ok_and(|x| x.trim() == pid) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+fn execute(eps: Vec<EndpointInfo>, conf_str: String, upgradable: bool) {
     #[cfg(feature = "multi-thread")]
     {
         tokio::runtime::Builder::new_multi_thread()
             .enable_all()
             .build()
             .unwrap()
-            .block_on(run(eps))
+            .block_on(run(eps, conf_str, upgradable))
     }
 
     #[cfg(not(feature = "multi-thread"))]
@@ -119,15 +283,19 @@ fn execute(eps: Vec<EndpointInfo>) {
             .enable_all()
             .build()
             .unwrap()
-            .block_on(run(eps))
+            .block_on(run(eps, conf_str, upgradable))
     }
 }
 
-async fn run(endpoints: Vec<EndpointInfo>) {
+async fn run(endpoints: Vec<EndpointInfo>, conf_str: String, upgradable: bool) {
     use realm::core::tcp::run_tcp;
     use realm::core::udp::run_udp;
     use futures::future::join_all;
 
+    // notify systemd once all of them are listening
+    #[cfg(unix)]
+    realm::core::systemd::expect_listening(endpoints.iter().map(|x| x.use_udp as usize + !x.no_tcp as usize).sum());
+
     let mut workers = Vec::with_capacity(2 * endpoints.len());
 
     for EndpointInfo {
@@ -147,5 +315,80 @@ async fn run(endpoints: Vec<EndpointInfo>) {
 
     workers.shrink_to_fit();
 
-    join_all(workers).await;
+    #[cfg(unix)]
+    {
+        use realm::core::systemd;
+
+        tokio::spawn(systemd::watchdog());
+
+        tokio::select! {
+            _ = join_all(workers) => {},
+            _ = shutdown_signal() => {
+                log::info!("shutting down");
+                systemd::notify_stopping();
+            }
+            _ = upgrade(conf_str, upgradable) => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (conf_str, upgradable);
+        join_all(workers).await;
+    }
+}
+
+/// Hand over listeners to a new process on SIGUSR2,
+/// then drain existing relays.
+#[cfg(unix)]
+async fn upgrade(conf_str: String, upgradable: bool) {
+    use tokio::signal::unix::{signal, SignalKind};
+    use realm::core::{systemd, upgrade};
+    use realm::consts::{UPGRADE_TIMEOUT, DRAIN_TIMEOUT};
+
+    let mut usr2 = signal(SignalKind::user_defined2()).unwrap();
+    loop {
+        usr2.recv().await;
+        if !upgradable {
+            log::error!("failed to upgrade: not allowed with chroot or seccomp");
+            continue;
+        }
+        log::info!("upgrading");
+
+        match upgrade::spawn_successor(&[(ENV_CONFIG, &conf_str)], UPGRADE_TIMEOUT).await {
+            Ok(pid) => {
+                log::info!(
+                    "new process {} is ready, draining {} relays",
+                    pid,
+                    upgrade::active_relays()
+                );
+                systemd::notify(&format!("MAINPID={}", pid));
+                break;
+            }
+            Err(e) => log::error!("failed to upgrade: {}", e),
+        }
+    }
+
+    upgrade::drain();
+    if upgrade::drained(DRAIN_TIMEOUT).await {
+        log::info!("drained, exit");
+    } else {
+        log::warn!(
+            "{} relays are not drained in {}s, exit",
+            upgrade::active_relays(),
+            DRAIN_TIMEOUT
+        );
+    }
+}
+
+#[cfg(unix)]
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut term = signal(SignalKind::terminate()).unwrap();
+    let mut int = signal(SignalKind::interrupt()).unwrap();
+    tokio::select! {
+        _ = term.recv() => {},
+        _ = int.recv() => {},
+    }
 }
@@ -55,7 +55,7 @@ pub fn add_flags(app: Command) -> Command {
         Arg::new("fast_open")
             .short('f')
             .long("tfo")
-            .help("force enable tcp fast open -- deprecated")
+            .help("force enable tcp fast open on both sides")
             .action(ArgAction::SetTrue)
             .display_order(7),
         Arg::new("zero_copy")
@@ -117,6 +117,11 @@ pub fn add_options(app: Command) -> Command {
             .help("remote transport")
             .value_name("options")
             .display_order(7),
+        Arg::new("listen_fd_name")
+            .long("listen-fd-name")
+            .help("take the listener passed by systemd")
+            .value_name("name")
+            .display_order(8),
     ])
 }
 
@@ -141,6 +146,41 @@ pub fn add_global_options(app: Command) -> Command {
             .help("set pre-connect hook")
             .value_name("path")
             .display_order(2),
+        Arg::new("user")
+            .long("user")
+            .help("switch to user after binding")
+            .value_name("user")
+            .display_order(3),
+        Arg::new("group")
+            .long("group")
+            .help("switch to group after binding")
+            .value_name("group")
+            .display_order(4),
+        Arg::new("keep_caps")
+            .long("keep-caps")
+            .help("keep capabilities after switching user")
+            .value_name("caps")
+            .display_order(5),
+        Arg::new("pid_file")
+            .long("pid-file")
+            .help("write pid to file")
+            .value_name("path")
+            .display_order(6),
+        Arg::new("chroot")
+            .long("chroot")
+            .help("change root directory after binding")
+            .value_name("path")
+            .display_order(7),
+        Arg::new("seccomp")
+            .long("seccomp")
+            .help("restrict syscalls: enforce|log|off")
+            .value_name("mode")
+            .display_order(8),
+        Arg::new("seccomp_upgrade")
+            .long("seccomp-upgrade")
+            .help("allow upgrade under seccomp")
+            .action(ArgAction::SetTrue)
+            .display_order(9),
     ]);
 
     // log
@@ -202,15 +242,34 @@ pub fn add_global_options(app: Command) -> Command {
             .help("send proxy protocol version")
             .value_name("version")
             .display_order(1),
+        Arg::new("send_proxy_tlvs")
+            .long("send-proxy-tlvs")
+            .help("send proxy protocol v2 tlvs")
+            .value_name("tlvs")
+            .display_order(2),
         Arg::new("accept_proxy")
             .long("accept-proxy")
             .help("accept proxy protocol header")
-            .display_order(2),
+            .display_order(3),
         Arg::new("accept_proxy_timeout")
             .long("accept-proxy-timeout")
             .help("accept proxy protocol timeout")
             .value_name("second")
-            .display_order(3),
+            .display_order(4),
+        Arg::new("accept_proxy_from")
+            .long("accept-proxy-from")
+            .help("accept proxy protocol header from cidrs")
+            .value_name("cidrs")
+            .display_order(5),
+        Arg::new("send_proxy_udp")
+            .long("send-proxy-udp")
+            .help("send proxy protocol header over udp")
+            .value_name("mode")
+            .display_order(6),
+        Arg::new("accept_proxy_udp")
+            .long("accept-proxy-udp")
+            .help("accept proxy protocol header over udp")
+            .display_order(7),
     ]);
 
     // timeout belogs to network
@@ -235,6 +294,16 @@ pub fn add_global_options(app: Command) -> Command {
             .help("override default tcp keepalive count(3)")
             .value_name("count")
             .display_order(3),
+        Arg::new("tcp_idle_timeout")
+            .long("tcp-idle-timeout")
+            .help("close tcp relays idle for a while(disabled)")
+            .value_name("second")
+            .display_order(4),
+        Arg::new("tcp_max_lifetime")
+            .long("tcp-max-lifetime")
+            .help("close tcp relays lasting for a while(disabled)")
+            .value_name("second")
+            .display_order(5),
     ]);
 
     app
@@ -5,7 +5,7 @@ use realm_core::realm_syscall;
 
 use crate::conf::CmdOverride;
 use crate::conf::EndpointConf;
-use crate::conf::{Config, LogConf, DnsConf, NetConf};
+use crate::conf::{Config, LogConf, DnsConf, SysConf, NetConf};
 
 use crate::VERSION;
 use crate::consts::FEATURES;
@@ -130,6 +130,7 @@ fn handle_matches(matches: ArgMatches) -> CmdInput {
 fn parse_global_opts(matches: &ArgMatches) -> CmdOverride {
     let log = LogConf::from_cmd_args(matches);
     let dns = DnsConf::from_cmd_args(matches);
+    let sys = SysConf::from_cmd_args(matches);
     let network = NetConf::from_cmd_args(matches);
-    CmdOverride { log, dns, network }
+    CmdOverride { log, dns, sys, network }
 }
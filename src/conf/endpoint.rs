@@ -1,7 +1,8 @@
 use serde::{Serialize, Deserialize};
 use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
 
-use realm_core::endpoint::{Endpoint, RemoteAddr};
+use realm_core::endpoint::{Endpoint, LocalAddr, RemoteAddr};
+use realm_core::ipnet::IpNet;
 
 #[cfg(feature = "balance")]
 use realm_core::balance::Balancer;
@@ -25,6 +26,10 @@ pub struct EndpointConf {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub balance: Option<String>,
 
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub original_dst_allow: Vec<String>,
+
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub through: Option<String>,
@@ -37,6 +42,10 @@ pub struct EndpointConf {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub listen_interface: Option<String>,
 
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listen_fd_name: Option<String>,
+
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub listen_transport: Option<String>,
@@ -50,13 +59,22 @@ pub struct EndpointConf {
     pub network: NetConf,
 }
 
+/// Relay to where a redirected connection was originally sent.
+const ORIGINAL_DST: &str = "original-dst";
+
+const UNIX_PREFIX: &str = "unix:";
+
 impl EndpointConf {
-    fn build_local(&self) -> SocketAddr {
+    fn build_local(&self) -> LocalAddr {
+        if self.listen.starts_with(UNIX_PREFIX) {
+            return self.listen.parse().expect("invalid local address");
+        }
         self.listen
             .to_socket_addrs()
             .expect("invalid local address")
             .next()
             .unwrap()
+            .into()
     }
 
     fn build_remote(&self) -> RemoteAddr {
@@ -64,6 +82,12 @@ impl EndpointConf {
     }
 
     fn build_remote_x(remote: &str) -> RemoteAddr {
+        if remote == ORIGINAL_DST {
+            return RemoteAddr::OriginalDst;
+        }
+        if remote.starts_with(UNIX_PREFIX) {
+            return RemoteAddr::Unix(remote.parse().expect("invalid remote address"));
+        }
         if let Ok(sockaddr) = remote.parse::<SocketAddr>() {
             RemoteAddr::SocketAddr(sockaddr)
         } else {
@@ -91,6 +115,17 @@ impl EndpointConf {
         }
     }
 
+    fn build_original_dst_allow(&self) -> Vec<IpNet> {
+        self.original_dst_allow
+            .iter()
+            .map(|x| {
+                x.parse::<IpNet>()
+                    .or_else(|_| x.parse::<IpAddr>().map(IpNet::from))
+                    .unwrap_or_else(|_| panic!("invalid cidr: {}", x))
+            })
+            .collect()
+    }
+
     #[cfg(feature = "balance")]
     fn build_balancer(&self) -> Balancer {
         if let Some(s) = &self.balance {
@@ -156,7 +191,7 @@ impl Config for EndpointConf {
         let laddr = self.build_local();
         let raddr = self.build_remote();
 
-        let extra_raddrs = self.extra_remotes.iter().map(|r| Self::build_remote_x(r)).collect();
+        let extra_raddrs: Vec<RemoteAddr> = self.extra_remotes.iter().map(|r| Self::build_remote_x(r)).collect();
 
         // build partial conn_opts from netconf
         let NetInfo {
@@ -164,7 +199,12 @@ impl Config for EndpointConf {
             mut conn_opts,
             no_tcp,
             use_udp,
-        } = self.network.build();
+        } = self.network.clone().build();
+
+        // the original destination is only recorded for tcp
+        if use_udp && raddr == RemoteAddr::OriginalDst {
+            panic!("remote = {} conflicts with use_udp = true", ORIGINAL_DST);
+        }
 
         #[cfg(feature = "balance")]
         {
@@ -176,10 +216,41 @@ impl Config for EndpointConf {
             conn_opts.transport = self.build_transport();
         }
 
+        // unix sockets only relay streams, and have no ip to carry
+        let unix_listen = matches!(laddr, LocalAddr::Unix(_));
+        let unix_remote = std::iter::once(&raddr)
+            .chain(&extra_raddrs)
+            .any(|x| matches!(x, RemoteAddr::Unix(_)));
+
+        if (unix_listen || unix_remote) && use_udp {
+            panic!("unix socket of {} => {} conflicts with use_udp = true", laddr, raddr);
+        }
+
+        #[cfg(feature = "transport")]
+        if (unix_listen || unix_remote) && conn_opts.transport.is_some() {
+            panic!("unix socket of {} => {} conflicts with transport", laddr, raddr);
+        }
+
+        if unix_listen && raddr == RemoteAddr::OriginalDst {
+            panic!("unix socket of {} conflicts with remote = {}", laddr, ORIGINAL_DST);
+        }
+
+        #[cfg(feature = "proxy")]
+        if unix_listen && (conn_opts.proxy_opts.send_proxy || conn_opts.proxy_opts.accept_proxy) {
+            panic!("unix socket of {} conflicts with send_proxy or accept_proxy", laddr);
+        }
+
+        #[cfg(feature = "balance")]
+        if unix_listen && matches!(conn_opts.balancer, Balancer::IpHash(_)) {
+            panic!("unix socket of {} conflicts with iphash balance", laddr);
+        }
+
         // build left fields of bind_opts and conn_opts
         conn_opts.bind_address = self.build_send_through();
+        conn_opts.original_dst_allow = self.build_original_dst_allow();
         conn_opts.bind_interface = self.interface;
         bind_opts.bind_interface = self.listen_interface;
+        bind_opts.fd_name = self.listen_fd_name;
 
         EndpointInfo {
             no_tcp,
@@ -208,6 +279,7 @@ impl Config for EndpointConf {
         let through = matches.get_one("through").cloned();
         let interface = matches.get_one("interface").cloned();
         let listen_interface = matches.get_one("listen_interface").cloned();
+        let listen_fd_name = matches.get_one("listen_fd_name").cloned();
         let listen_transport = matches.get_one("listen_transport").cloned();
         let remote_transport = matches.get_one("remote_transport").cloned();
 
@@ -217,11 +289,13 @@ impl Config for EndpointConf {
             through,
             interface,
             listen_interface,
+            listen_fd_name,
             listen_transport,
             remote_transport,
             network: Default::default(),
             extra_remotes: Vec::new(),
             balance: None,
+            original_dst_allow: Vec::new(),
         }
     }
 }
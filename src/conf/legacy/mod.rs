@@ -73,11 +73,13 @@ impl From<LegacyConf> for FullConf {
                 through: None,
                 interface: None,
                 listen_interface: None,
+                listen_fd_name: None,
                 listen_transport: None,
                 remote_transport: None,
                 network: Default::default(),
                 extra_remotes: Vec::new(),
                 balance: None,
+                original_dst_allow: Vec::new(),
             })
             .collect();
 
@@ -12,11 +12,14 @@ mod dns;
 pub use dns::{DnsMode, DnsProtocol, DnsConf};
 
 mod net;
-pub use net::{NetConf, NetInfo};
+pub use net::{NetConf, NetInfo, TcpShutdown};
 
 mod endpoint;
 pub use endpoint::{EndpointConf, EndpointInfo};
 
+mod sys;
+pub use sys::{SysConf, SysInfo};
+
 mod legacy;
 pub use legacy::LegacyConf;
 
@@ -24,6 +27,7 @@ pub use legacy::LegacyConf;
 /// Conig Architecture
 /// cmd | file => LogConf => { level, output }
 /// cmd | file => DnsConf => { resolve cinfig, opts }
+/// cmd | file => SysConf => { user, group, caps, pid file, chroot }
 /// cmd | file => NetConf
 ///                      \
 /// cmd | file => EndpointConf => { [local, remote, conn_opts] }
@@ -49,6 +53,7 @@ pub trait Config {
 pub struct CmdOverride {
     pub log: LogConf,
     pub dns: DnsConf,
+    pub sys: SysConf,
     pub network: NetConf,
 }
 
@@ -62,6 +67,10 @@ pub struct FullConf {
     #[serde(skip_serializing_if = "Config::is_empty")]
     pub dns: DnsConf,
 
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Config::is_empty")]
+    pub sys: SysConf,
+
     #[serde(default)]
     #[serde(skip_serializing_if = "Config::is_empty")]
     pub network: NetConf,
@@ -71,10 +80,11 @@ pub struct FullConf {
 
 impl FullConf {
     #[allow(unused)]
-    pub fn new(log: LogConf, dns: DnsConf, network: NetConf, endpoints: Vec<EndpointConf>) -> Self {
+    pub fn new(log: LogConf, dns: DnsConf, sys: SysConf, network: NetConf, endpoints: Vec<EndpointConf>) -> Self {
         FullConf {
             log,
             dns,
+            sys,
             network,
             endpoints,
         }
@@ -139,6 +149,7 @@ impl FullConf {
     fn take_fields(&mut self, other: Self) {
         self.log.take_field(&other.log);
         self.dns.take_field(&other.dns);
+        self.sys.take_field(&other.sys);
         self.network.take_field(&other.network);
         self.endpoints.extend(other.endpoints);
     }
@@ -153,11 +164,13 @@ impl FullConf {
         let CmdOverride {
             ref log,
             ref dns,
+            ref sys,
             ref network,
         } = opts;
 
         self.log.rst_field(log);
         self.dns.rst_field(dns);
+        self.sys.rst_field(sys);
         self.endpoints.iter_mut().for_each(|x| {
             x.network.rst_field(network);
         });
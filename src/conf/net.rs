@@ -1,13 +1,22 @@
+use std::str::FromStr;
+#[cfg(feature = "proxy")]
+use std::net::IpAddr;
+
 use serde::{Serialize, Deserialize};
-use realm_core::endpoint::{BindOpts, ConnectOpts};
+use realm_core::endpoint::{BindOpts, ConnectOpts, Steering, DEFAULT_FAST_OPEN_QUEUE};
+use realm_core::realm_io::Shutdown;
+#[cfg(feature = "proxy")]
+use realm_core::ipnet::IpNet;
 
 use super::Config;
 use crate::consts::{TCP_TIMEOUT, UDP_TIMEOUT};
+use crate::consts::UDP_PACKET_SIZE;
+use crate::consts::{TCP_BACKLOG, TCP_LISTENERS, TCP_SHUTDOWN};
 use crate::consts::{TCP_KEEPALIVE, TCP_KEEPALIVE_PROBE};
-use crate::consts::PROXY_PROTOCOL_VERSION;
-use crate::consts::PROXY_PROTOCOL_TIMEOUT;
+#[cfg(feature = "proxy")]
+use crate::consts::{PROXY_PROTOCOL_VERSION, PROXY_PROTOCOL_TIMEOUT};
 
-#[derive(Serialize, Debug, Deserialize, Clone, Copy, Default)]
+#[derive(Serialize, Debug, Deserialize, Clone, Default)]
 pub struct NetConf {
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -21,6 +30,14 @@ pub struct NetConf {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ipv6_only: Option<bool>,
 
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transparent: Option<bool>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spoof_source: Option<bool>,
+
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub send_mptcp: Option<bool>,
@@ -29,13 +46,49 @@ pub struct NetConf {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub accept_mptcp: Option<bool>,
 
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_fast_open: Option<bool>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accept_fast_open: Option<bool>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fast_open_queue: Option<usize>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_mark: Option<u32>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accept_mark: Option<u32>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_tos: Option<u8>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accept_tos: Option<u8>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_congestion: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accept_congestion: Option<String>,
+
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub send_proxy: Option<bool>,
 
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub accept_proxy: Option<bool>,
+    pub accept_proxy: Option<AcceptProxy>,
 
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -44,6 +97,22 @@ pub struct NetConf {
     #[serde(default)]
     pub accept_proxy_timeout: Option<usize>,
 
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_proxy_tlvs: Option<Vec<String>>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_proxy_udp: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accept_proxy_udp: Option<bool>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accept_proxy_from: Option<Vec<String>>,
+
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tcp_keepalive: Option<usize>,
@@ -52,6 +121,22 @@ pub struct NetConf {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tcp_keepalive_probe: Option<usize>,
 
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp_idle_timeout: Option<usize>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp_max_lifetime: Option<usize>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp_shutdown: Option<TcpShutdown>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp_half_close_timeout: Option<usize>,
+
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tcp_timeout: Option<usize>,
@@ -59,6 +144,120 @@ pub struct NetConf {
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub udp_timeout: Option<usize>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub udp_resolve_interval: Option<usize>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub udp_migrate: Option<bool>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub udp_packet_size: Option<usize>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub udp_offload: Option<bool>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp_backlog: Option<usize>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp_listeners: Option<usize>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp_steering: Option<TcpSteering>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub udp_listeners: Option<usize>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_udp_over_tcp: Option<bool>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accept_udp_over_tcp: Option<bool>,
+}
+
+// tcp listener steering
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TcpSteering {
+    #[default]
+    None,
+    IncomingCpu,
+    Bpf,
+}
+
+impl From<TcpSteering> for Steering {
+    fn from(steering: TcpSteering) -> Self {
+        match steering {
+            TcpSteering::None => Steering::None,
+            TcpSteering::IncomingCpu => Steering::IncomingCpu,
+            TcpSteering::Bpf => Steering::Bpf,
+        }
+    }
+}
+
+// accept proxy-protocol: true, false or "optional"
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum AcceptProxy {
+    Bool(bool),
+    Mode(AcceptProxyMode),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AcceptProxyMode {
+    Optional,
+}
+
+impl Default for AcceptProxy {
+    fn default() -> Self {
+        AcceptProxy::Bool(false)
+    }
+}
+
+impl FromStr for AcceptProxy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "optional" => Ok(AcceptProxy::Mode(AcceptProxyMode::Optional)),
+            _ => s
+                .parse()
+                .map(AcceptProxy::Bool)
+                .map_err(|_| format!("invalid accept_proxy: {}", s)),
+        }
+    }
+}
+
+// tcp shutdown behavior
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TcpShutdown {
+    #[default]
+    Graceful,
+    Brutal,
+}
+
+impl TcpShutdown {
+    fn build(self, half_close_timeout: usize) -> Shutdown {
+        use std::time::Duration;
+        match (self, half_close_timeout) {
+            (TcpShutdown::Brutal, _) => Shutdown::Brutal,
+            (TcpShutdown::Graceful, 0) => Shutdown::Graceful,
+            (TcpShutdown::Graceful, n) => Shutdown::HalfClose(Duration::from_secs(n as u64)),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -74,10 +273,18 @@ impl Config for NetConf {
 
     fn is_empty(&self) -> bool {
         crate::empty![self =>
-            no_tcp, use_udp, ipv6_only,
+            no_tcp, use_udp, ipv6_only, transparent, spoof_source,
             send_mptcp, accept_mptcp,
-            send_proxy, accept_proxy, send_proxy_version, accept_proxy_timeout,
-            tcp_keepalive, tcp_keepalive_probe, tcp_timeout, udp_timeout
+            send_fast_open, accept_fast_open, fast_open_queue,
+            send_mark, accept_mark, send_tos, accept_tos,
+            send_congestion, accept_congestion,
+            send_proxy, accept_proxy, send_proxy_version, accept_proxy_timeout, send_proxy_tlvs,
+            send_proxy_udp, accept_proxy_udp, accept_proxy_from,
+            tcp_keepalive, tcp_keepalive_probe, tcp_timeout, udp_timeout,
+            tcp_idle_timeout, tcp_max_lifetime, tcp_shutdown, tcp_half_close_timeout,
+            tcp_backlog, tcp_listeners, tcp_steering,
+            udp_resolve_interval, udp_migrate, udp_packet_size, udp_offload, udp_listeners,
+            send_udp_over_tcp, accept_udp_over_tcp
         ]
     }
 
@@ -91,31 +298,91 @@ impl Config for NetConf {
             };
         }
 
-        let no_tcp = unbox!(no_tcp);
-        let use_udp = unbox!(use_udp);
+        let mut no_tcp = unbox!(no_tcp);
+        let mut use_udp = unbox!(use_udp);
         let ipv6_only = unbox!(ipv6_only);
+        let transparent = unbox!(transparent);
+        let spoof_source = unbox!(spoof_source);
         let send_mptcp = unbox!(send_mptcp);
         let accept_mptcp = unbox!(accept_mptcp);
+        let send_fast_open = unbox!(send_fast_open);
+        let accept_fast_open = unbox!(accept_fast_open);
+        let fast_open_queue = unbox!(fast_open_queue, DEFAULT_FAST_OPEN_QUEUE);
+        let send_mark = unbox!(send_mark);
+        let accept_mark = unbox!(accept_mark);
+        let send_tos = unbox!(send_tos);
+        let accept_tos = unbox!(accept_tos);
         let tcp_kpa = unbox!(tcp_keepalive, TCP_KEEPALIVE);
         let tcp_kpa_probe = unbox!(tcp_keepalive_probe, TCP_KEEPALIVE_PROBE);
         let tcp_timeout = unbox!(tcp_timeout, TCP_TIMEOUT);
+        let tcp_idle_timeout = unbox!(tcp_idle_timeout);
+        let tcp_max_lifetime = unbox!(tcp_max_lifetime);
+        let tcp_half_close_timeout = unbox!(tcp_half_close_timeout);
+        let tcp_shutdown = unbox!(tcp_shutdown, TCP_SHUTDOWN).build(tcp_half_close_timeout);
         let udp_timeout = unbox!(udp_timeout, UDP_TIMEOUT);
+        let udp_resolve_interval = unbox!(udp_resolve_interval);
+        let udp_migrate = unbox!(udp_migrate);
+        let udp_packet_size = unbox!(udp_packet_size, UDP_PACKET_SIZE);
+        let udp_offload = unbox!(udp_offload);
+        let udp_listeners = unbox!(udp_listeners);
+        let tcp_backlog = unbox!(tcp_backlog, TCP_BACKLOG);
+        let tcp_listeners = unbox!(tcp_listeners, TCP_LISTENERS);
+        let tcp_steering = unbox!(tcp_steering).into();
+        let send_udp_over_tcp = unbox!(send_udp_over_tcp);
+        let accept_udp_over_tcp = unbox!(accept_udp_over_tcp);
+
+        // the tcp listener is taken by the tunnel
+        if accept_udp_over_tcp {
+            if self.no_tcp == Some(false) || self.use_udp == Some(false) {
+                panic!("accept_udp_over_tcp conflicts with no_tcp = false or use_udp = false");
+            }
+            no_tcp = true;
+            use_udp = true;
+        }
 
         let bind_opts = BindOpts {
             ipv6_only,
+            transparent,
             accept_mptcp,
+            accept_fast_open,
+            fast_open_queue,
+            accept_udp_over_tcp,
+            tcp_backlog,
+            tcp_listeners,
+            tcp_steering,
+            udp_listeners,
             bind_interface: None,
+            fd_name: None,
+            accept_mark,
+            accept_tos,
+            accept_congestion: self.accept_congestion,
         };
         let conn_opts = ConnectOpts {
             send_mptcp,
+            send_fast_open,
+            send_udp_over_tcp,
             tcp_keepalive: tcp_kpa,
             tcp_keepalive_probe: tcp_kpa_probe,
+            tcp_idle_timeout,
+            tcp_max_lifetime,
+            tcp_shutdown,
             connect_timeout: tcp_timeout,
             associate_timeout: udp_timeout,
+            udp_resolve_interval,
+            udp_migrate,
+            udp_packet_size,
+            udp_offload,
+
+            spoof_source,
+
+            send_mark,
+            send_tos,
+            send_congestion: self.send_congestion,
 
             // from endpoint
             bind_address: None,
             bind_interface: None,
+            original_dst_allow: Vec::new(),
 
             #[cfg(feature = "balance")]
             balancer: Default::default(),
@@ -128,13 +395,38 @@ impl Config for NetConf {
                 use realm_core::endpoint::ProxyOpts;
                 let send_proxy = unbox!(send_proxy);
                 let send_proxy_version = unbox!(send_proxy_version, PROXY_PROTOCOL_VERSION);
-                let accept_proxy = unbox!(accept_proxy);
+                let (accept_proxy, accept_proxy_optional) = match unbox!(accept_proxy) {
+                    AcceptProxy::Bool(x) => (x, false),
+                    AcceptProxy::Mode(AcceptProxyMode::Optional) => (true, true),
+                };
+                let accept_proxy_from = unbox!(accept_proxy_from)
+                    .iter()
+                    .map(|x| {
+                        x.parse::<IpNet>()
+                            .or_else(|_| x.parse::<IpAddr>().map(IpNet::from))
+                            .unwrap_or_else(|_| panic!("invalid cidr: {}", x))
+                    })
+                    .collect();
                 let accept_proxy_timeout = unbox!(accept_proxy_timeout, PROXY_PROTOCOL_TIMEOUT);
+                let send_proxy_tlvs = unbox!(send_proxy_tlvs)
+                    .join(",")
+                    .parse()
+                    .unwrap_or_else(|e| panic!("failed to parse proxy tlvs: {}", e));
+                let send_proxy_udp = self.send_proxy_udp.map_or(Default::default(), |x| {
+                    x.parse()
+                        .unwrap_or_else(|e| panic!("failed to parse udp proxy mode: {}", e))
+                });
+                let accept_proxy_udp = unbox!(accept_proxy_udp);
                 ProxyOpts {
                     send_proxy,
                     accept_proxy,
                     send_proxy_version,
                     accept_proxy_timeout,
+                    send_proxy_tlvs,
+                    send_proxy_udp,
+                    accept_proxy_udp,
+                    accept_proxy_optional,
+                    accept_proxy_from,
                 }
             },
         };
@@ -149,41 +441,99 @@ impl Config for NetConf {
 
     fn rst_field(&mut self, other: &Self) -> &mut Self {
         use crate::rst;
-        let other = *other;
+        let other = other.clone();
 
         rst!(self, no_tcp, other);
         rst!(self, use_udp, other);
         rst!(self, ipv6_only, other);
+        rst!(self, transparent, other);
+        rst!(self, spoof_source, other);
         rst!(self, send_mptcp, other);
         rst!(self, accept_mptcp, other);
+        rst!(self, send_fast_open, other);
+        rst!(self, accept_fast_open, other);
+        rst!(self, fast_open_queue, other);
+        rst!(self, send_mark, other);
+        rst!(self, accept_mark, other);
+        rst!(self, send_tos, other);
+        rst!(self, accept_tos, other);
+        rst!(self, send_congestion, other);
+        rst!(self, accept_congestion, other);
         rst!(self, tcp_keepalive, other);
         rst!(self, tcp_keepalive_probe, other);
         rst!(self, tcp_timeout, other);
+        rst!(self, tcp_idle_timeout, other);
+        rst!(self, tcp_max_lifetime, other);
+        rst!(self, tcp_shutdown, other);
+        rst!(self, tcp_half_close_timeout, other);
+        rst!(self, tcp_backlog, other);
+        rst!(self, tcp_listeners, other);
+        rst!(self, tcp_steering, other);
         rst!(self, udp_timeout, other);
+        rst!(self, udp_resolve_interval, other);
+        rst!(self, udp_migrate, other);
+        rst!(self, udp_packet_size, other);
+        rst!(self, udp_offload, other);
+        rst!(self, udp_listeners, other);
+        rst!(self, send_udp_over_tcp, other);
+        rst!(self, accept_udp_over_tcp, other);
         rst!(self, send_proxy, other);
         rst!(self, accept_proxy, other);
         rst!(self, send_proxy_version, other);
         rst!(self, accept_proxy_timeout, other);
+        rst!(self, send_proxy_tlvs, other);
+        rst!(self, send_proxy_udp, other);
+        rst!(self, accept_proxy_udp, other);
+        rst!(self, accept_proxy_from, other);
         self
     }
 
     fn take_field(&mut self, other: &Self) -> &mut Self {
         use crate::take;
-        let other = *other;
+        let other = other.clone();
 
         take!(self, no_tcp, other);
         take!(self, use_udp, other);
         take!(self, ipv6_only, other);
+        take!(self, transparent, other);
+        take!(self, spoof_source, other);
         take!(self, send_mptcp, other);
         take!(self, accept_mptcp, other);
+        take!(self, send_fast_open, other);
+        take!(self, accept_fast_open, other);
+        take!(self, fast_open_queue, other);
+        take!(self, send_mark, other);
+        take!(self, accept_mark, other);
+        take!(self, send_tos, other);
+        take!(self, accept_tos, other);
+        take!(self, send_congestion, other);
+        take!(self, accept_congestion, other);
         take!(self, tcp_keepalive, other);
         take!(self, tcp_keepalive_probe, other);
         take!(self, tcp_timeout, other);
+        take!(self, tcp_idle_timeout, other);
+        take!(self, tcp_max_lifetime, other);
+        take!(self, tcp_shutdown, other);
+        take!(self, tcp_half_close_timeout, other);
+        take!(self, tcp_backlog, other);
+        take!(self, tcp_listeners, other);
+        take!(self, tcp_steering, other);
         take!(self, udp_timeout, other);
+        take!(self, udp_resolve_interval, other);
+        take!(self, udp_migrate, other);
+        take!(self, udp_packet_size, other);
+        take!(self, udp_offload, other);
+        take!(self, udp_listeners, other);
+        take!(self, send_udp_over_tcp, other);
+        take!(self, accept_udp_over_tcp, other);
         take!(self, send_proxy, other);
         take!(self, accept_proxy, other);
         take!(self, send_proxy_version, other);
         take!(self, accept_proxy_timeout, other);
+        take!(self, send_proxy_tlvs, other);
+        take!(self, send_proxy_udp, other);
+        take!(self, accept_proxy_udp, other);
+        take!(self, accept_proxy_from, other);
         self
     }
 
@@ -209,31 +559,75 @@ impl Config for NetConf {
         let send_mptcp = use_mptcp;
         let accept_mptcp = use_mptcp;
 
+        let use_fast_open = unpack!("fast_open");
+        let send_fast_open = use_fast_open;
+        let accept_fast_open = use_fast_open;
+
         let tcp_keepalive = unpack!("tcp_keepalive", usize);
         let tcp_keepalive_probe = unpack!("tcp_keepalive", usize);
         let tcp_timeout = unpack!("tcp_timeout", usize);
+        let tcp_idle_timeout = unpack!("tcp_idle_timeout", usize);
+        let tcp_max_lifetime = unpack!("tcp_max_lifetime", usize);
         let udp_timeout = unpack!("udp_timeout", usize);
 
         let send_proxy = unpack!("send_proxy", bool);
         let send_proxy_version = unpack!("send_proxy_version", usize);
+        let send_proxy_tlvs = matches
+            .get_one::<String>("send_proxy_tlvs")
+            .map(|x| x.split(',').map(String::from).collect());
 
-        let accept_proxy = unpack!("accept_proxy", bool);
+        let accept_proxy = unpack!("accept_proxy", AcceptProxy);
         let accept_proxy_timeout = unpack!("accept_proxy_timeout", usize);
 
+        let send_proxy_udp = matches.get_one("send_proxy_udp").cloned();
+        let accept_proxy_udp = unpack!("accept_proxy_udp", bool);
+        let accept_proxy_from = matches
+            .get_one::<String>("accept_proxy_from")
+            .map(|x| x.split(',').map(String::from).collect());
+
         Self {
             no_tcp,
             use_udp,
             ipv6_only,
+            transparent: None,
+            spoof_source: None,
             send_mptcp,
             accept_mptcp,
+            send_fast_open,
+            accept_fast_open,
+            fast_open_queue: None,
+            send_mark: None,
+            accept_mark: None,
+            send_tos: None,
+            accept_tos: None,
+            send_congestion: None,
+            accept_congestion: None,
             tcp_keepalive,
             tcp_keepalive_probe,
             tcp_timeout,
             udp_timeout,
+            tcp_idle_timeout,
+            tcp_max_lifetime,
+            tcp_shutdown: None,
+            tcp_half_close_timeout: None,
+            udp_resolve_interval: None,
+            udp_migrate: None,
+            udp_packet_size: None,
+            udp_offload: None,
+            tcp_backlog: None,
+            tcp_listeners: None,
+            tcp_steering: None,
+            udp_listeners: None,
+            send_udp_over_tcp: None,
+            accept_udp_over_tcp: None,
             send_proxy,
             accept_proxy,
             send_proxy_version,
             accept_proxy_timeout,
+            send_proxy_tlvs,
+            send_proxy_udp,
+            accept_proxy_udp,
+            accept_proxy_from,
         }
     }
 }
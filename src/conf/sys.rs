@@ -0,0 +1,213 @@
+use std::fmt::{Formatter, Display};
+use std::path::PathBuf;
+use serde::{Serialize, Deserialize};
+use super::Config;
+
+#[cfg(unix)]
+use realm_core::realm_syscall::Capability;
+#[cfg(target_os = "linux")]
+use realm_core::realm_syscall::SeccompMode;
+
+// process config, applied after all listeners are bound
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct SysConf {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_caps: Option<Vec<String>>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid_file: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chroot: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seccomp: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seccomp_upgrade: Option<bool>,
+}
+
+#[derive(Debug, Default)]
+pub struct SysInfo {
+    pub user: Option<String>,
+    pub group: Option<String>,
+    #[cfg(unix)]
+    pub keep_caps: Vec<Capability>,
+    pub pid_file: Option<PathBuf>,
+    pub chroot: Option<PathBuf>,
+    #[cfg(target_os = "linux")]
+    pub seccomp: Option<SeccompMode>,
+    pub seccomp_upgrade: bool,
+}
+
+impl SysInfo {
+    /// Listeners should be bound before switching user or root.
+    pub fn need_prebind(&self) -> bool {
+        self.user.is_some() || self.group.is_some() || self.chroot.is_some()
+    }
+}
+
+impl Config for SysConf {
+    type Output = SysInfo;
+
+    fn is_empty(&self) -> bool {
+        crate::empty![self => user, group, keep_caps, pid_file, chroot, seccomp, seccomp_upgrade]
+    }
+
+    fn build(self) -> Self::Output {
+        let SysConf {
+            user,
+            group,
+            keep_caps,
+            pid_file,
+            chroot,
+            seccomp,
+            seccomp_upgrade,
+        } = self;
+
+        #[cfg(unix)]
+        let keep_caps = keep_caps
+            .unwrap_or_default()
+            .iter()
+            .map(|x| {
+                x.parse()
+                    .unwrap_or_else(|e| panic!("failed to parse capability: {}", e))
+            })
+            .collect();
+
+        #[cfg(not(unix))]
+        let _ = keep_caps;
+
+        // "off" is the same as none, to override a config file
+        #[cfg(target_os = "linux")]
+        let seccomp = seccomp.filter(|x| x != "off").map(|x| {
+            x.parse()
+                .unwrap_or_else(|e| panic!("failed to parse seccomp mode: {}", e))
+        });
+
+        #[cfg(not(target_os = "linux"))]
+        if seccomp.as_deref().is_some_and(|x| x != "off") {
+            panic!("seccomp is not supported on this platform");
+        }
+
+        SysInfo {
+            user,
+            group,
+            #[cfg(unix)]
+            keep_caps,
+            pid_file: pid_file.map(PathBuf::from),
+            chroot: chroot.map(PathBuf::from),
+            #[cfg(target_os = "linux")]
+            seccomp,
+            seccomp_upgrade: seccomp_upgrade.unwrap_or_default(),
+        }
+    }
+
+    fn rst_field(&mut self, other: &Self) -> &mut Self {
+        use crate::rst;
+        let other = other.clone();
+
+        rst!(self, user, other);
+        rst!(self, group, other);
+        rst!(self, keep_caps, other);
+        rst!(self, pid_file, other);
+        rst!(self, chroot, other);
+        rst!(self, seccomp, other);
+        rst!(self, seccomp_upgrade, other);
+        self
+    }
+
+    fn take_field(&mut self, other: &Self) -> &mut Self {
+        use crate::take;
+        let other = other.clone();
+
+        take!(self, user, other);
+        take!(self, group, other);
+        take!(self, keep_caps, other);
+        take!(self, pid_file, other);
+        take!(self, chroot, other);
+        take!(self, seccomp, other);
+        take!(self, seccomp_upgrade, other);
+        self
+    }
+
+    fn from_cmd_args(matches: &clap::ArgMatches) -> Self {
+        let user = matches.get_one("user").cloned();
+
+        let group = matches.get_one("group").cloned();
+
+        let keep_caps = matches
+            .get_one::<String>("keep_caps")
+            .map(|x| x.split(',').map(String::from).collect());
+
+        let pid_file = matches.get_one("pid_file").cloned();
+
+        let chroot = matches.get_one("chroot").cloned();
+
+        let seccomp = matches.get_one("seccomp").cloned();
+
+        let seccomp_upgrade = matches.get_flag("seccomp_upgrade").then_some(true);
+
+        Self {
+            user,
+            group,
+            keep_caps,
+            pid_file,
+            chroot,
+            seccomp,
+            seccomp_upgrade,
+        }
+    }
+}
+
+impl Display for SysConf {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        macro_rules! default {
+            ($ref: expr) => {
+                match $ref {
+                    Some(x) => x.as_str(),
+                    None => "none",
+                }
+            };
+        }
+        let SysConf {
+            user,
+            group,
+            keep_caps,
+            pid_file,
+            chroot,
+            seccomp,
+            seccomp_upgrade,
+        } = self;
+
+        let keep_caps = match keep_caps {
+            Some(x) => x.join(","),
+            None => String::from("none"),
+        };
+
+        write!(
+            f,
+            "user={}, group={}, keep-caps={}, pid-file={}, chroot={}, seccomp={}, seccomp-upgrade={}",
+            default!(user),
+            default!(group),
+            keep_caps,
+            default!(pid_file),
+            default!(chroot),
+            default!(seccomp),
+            seccomp_upgrade.unwrap_or_default()
+        )
+    }
+}
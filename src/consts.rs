@@ -1,5 +1,7 @@
 use std::fmt::{Display, Formatter};
 
+use crate::conf::TcpShutdown;
+
 // default logfile
 pub const DEFAULT_LOG_FILE: &str = "stdout";
 
@@ -9,6 +11,28 @@ pub const TCP_KEEPALIVE: usize = 15;
 pub const TCP_KEEPALIVE_PROBE: usize = 3;
 pub const UDP_TIMEOUT: usize = 30;
 
+// time for a new process to get ready on upgrade
+pub const UPGRADE_TIMEOUT: usize = 10;
+
+// time for the old process to drain relays on upgrade
+pub const DRAIN_TIMEOUT: usize = 600;
+
+// default tcp listen backlog
+pub const TCP_BACKLOG: usize = 1024;
+
+// default tcp shutdown
+pub const TCP_SHUTDOWN: TcpShutdown = if FEATURE_BRUTAL_SHUTDOWN {
+    TcpShutdown::Brutal
+} else {
+    TcpShutdown::Graceful
+};
+
+// default tcp listeners
+pub const TCP_LISTENERS: usize = 1;
+
+// default udp packet size
+pub const UDP_PACKET_SIZE: usize = 1500;
+
 // default haproxy proxy-protocol version
 pub const PROXY_PROTOCOL_VERSION: usize = 2;
 